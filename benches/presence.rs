@@ -0,0 +1,62 @@
+// Baselines for the code every presence update runs through: template rendering, fitting text
+// to Discord's limits and the cover cache lookup. The client is a binary, so the modules are
+// compiled in here directly.
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+#[allow(dead_code)]
+#[path = "../src/cover_cache.rs"]
+mod cover_cache;
+#[allow(dead_code)]
+#[path = "../src/stats.rs"]
+mod stats;
+#[allow(dead_code)]
+#[path = "../src/template.rs"]
+mod template;
+
+fn sample_vars() -> template::Vars {
+    [
+        ("series", "The Apothecary Diaries"),
+        ("title", "The Apothecary Diaries, Vol. 7"),
+        ("number", "7"),
+        ("page", "134"),
+        ("pages", "212"),
+        ("percent", "63"),
+        ("library", "Manga"),
+        ("author", "Natsu Hyuuga"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name, value.to_string()))
+    .collect()
+}
+
+fn rendering(c: &mut Criterion) {
+    let vars = sample_vars();
+    c.bench_function("render details", |b| b.iter(|| template::render(black_box("{series} · Vol. {number}"), black_box(&vars))));
+    c.bench_function("render state", |b| {
+        b.iter(|| template::render(black_box("Page {page} of {pages} ({percent}%) in {library}, by {author} {not a name}"), black_box(&vars)))
+    });
+    c.bench_function("render sections", |b| {
+        b.iter(|| template::render(black_box("{series}{?volume} · Vol. {volume}{/volume}{?chapter} · Ch. {chapter}{/chapter}"), black_box(&vars)))
+    });
+}
+
+fn truncation(c: &mut Criterion) {
+    let short = "The Apothecary Diaries, Vol. 7";
+    let long = "薬屋のひとりごと ".repeat(20);
+    c.bench_function("truncate short", |b| b.iter(|| template::truncate(black_box(short), 128)));
+    c.bench_function("truncate long", |b| b.iter(|| template::truncate(black_box(&long), 128)));
+}
+
+fn cover_lookup(c: &mut Criterion) {
+    let mut cache = cover_cache::CoverCache::new();
+    for i in 0..500 {
+        cache.finish((format!("komga_{:024}", i), Some(format!("https://i.imgur.com/{:07}.jpg", i))));
+    }
+    let series_id = format!("{:024}", 250);
+    c.bench_function("cover cache hit", |b| b.iter(|| black_box(&cache).lookup(&format!("komga_{}", black_box(&series_id)))));
+    c.bench_function("cover cache miss", |b| b.iter(|| black_box(&cache).lookup(&format!("komga_{}", black_box("missing")))));
+}
+
+criterion_group!(benches, rendering, truncation, cover_lookup);
+criterion_main!(benches);
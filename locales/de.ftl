@@ -0,0 +1,43 @@
+## Progress
+page = Seite { $page }
+pages-spread = Seiten { $first }–{ $last }
+percent-read = { $percent } % gelesen
+percent = { $percent } %
+
+## Book and series
+untitled = Ohne Titel
+unknown-author = Unbekannter Autor
+more-authors = { $names } +{ $count } weitere
+volume = Bd. { $number }
+chapter = Kap. { $number }
+book = Buch { $number }
+view-on = Auf { $provider } ansehen
+from-library = Aus: { $library }
+status-ended = Abgeschlossen
+status-ongoing = Laufend
+status-abandoned = Abgebrochen
+status-hiatus = Pausiert
+
+## Reading time, sessions and goals
+minutes = { $minutes } Min.
+hours-minutes = { $hours } Std. { $minutes } Min.
+time-left = noch ~{ $time }
+session-pages = { $pages ->
+    [one] { $pages } Seite
+   *[other] { $pages } Seiten
+} in { $minutes } Min.
+session-rate = { $rate } Seiten/Std.
+streak = 🔥 { $days ->
+    [one] { $days } Tag
+   *[other] { $days } Tage
+} in Folge
+goal = Ziel: { $goals }
+goal-pages = { $done }/{ $goal } { $goal ->
+    [one] Seite
+   *[other] Seiten
+}
+goal-minutes = { $done }/{ $goal } Min.
+
+## Status
+resuming = wird fortgesetzt…
+server-restarting = Server startet neu
@@ -0,0 +1,45 @@
+# Text Komga-RPC puts on the Discord card, in Fluent syntax (https://projectfluent.org).
+# Translations live next to this file as <language>.ftl; anything a translation leaves
+# out is shown in English. Counts are passed as numbers, so a message can pick its
+# plural form with a selector on them.
+
+## Progress
+page = Page { $page }
+pages-spread = Pages { $first }–{ $last }
+percent-read = { $percent }% read
+percent = { $percent }%
+
+## Book and series
+untitled = Untitled
+unknown-author = Unknown Author
+more-authors = { $names } +{ $count } more
+volume = Vol. { $number }
+chapter = Ch. { $number }
+book = Book { $number }
+view-on = View on { $provider }
+from-library = From: { $library }
+status-ended = Ended
+status-ongoing = Ongoing
+status-abandoned = Abandoned
+status-hiatus = Hiatus
+
+## Reading time, sessions and goals
+minutes = { $minutes } min
+hours-minutes = { $hours }h { $minutes }m
+time-left = ~{ $time } left
+session-pages = { $pages ->
+    [one] { $pages } page
+   *[other] { $pages } pages
+} in { $minutes } min
+session-rate = { $rate } pages/h
+streak = 🔥 { $days }-day streak
+goal = goal: { $goals }
+goal-pages = { $done }/{ $goal } { $goal ->
+    [one] page
+   *[other] pages
+}
+goal-minutes = { $done }/{ $goal } min
+
+## Status
+resuming = resuming…
+server-restarting = server restarting
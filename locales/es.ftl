@@ -0,0 +1,43 @@
+## Progress
+page = Página { $page }
+pages-spread = Páginas { $first }–{ $last }
+percent-read = { $percent } % leído
+percent = { $percent } %
+
+## Book and series
+untitled = Sin título
+unknown-author = Autor desconocido
+more-authors = { $names } +{ $count } más
+volume = Vol. { $number }
+chapter = Cap. { $number }
+book = Libro { $number }
+view-on = Ver en { $provider }
+from-library = De: { $library }
+status-ended = Finalizada
+status-ongoing = En curso
+status-abandoned = Abandonada
+status-hiatus = En pausa
+
+## Reading time, sessions and goals
+minutes = { $minutes } min
+hours-minutes = { $hours } h { $minutes } min
+time-left = quedan ~{ $time }
+session-pages = { $pages ->
+    [one] { $pages } página
+   *[other] { $pages } páginas
+} en { $minutes } min
+session-rate = { $rate } páginas/h
+streak = 🔥 racha de { $days ->
+    [one] { $days } día
+   *[other] { $days } días
+}
+goal = meta: { $goals }
+goal-pages = { $done }/{ $goal } { $goal ->
+    [one] página
+   *[other] páginas
+}
+goal-minutes = { $done }/{ $goal } min
+
+## Status
+resuming = reanudando…
+server-restarting = reiniciando el servidor
@@ -0,0 +1,43 @@
+## Progress
+page = Page { $page }
+pages-spread = Pages { $first }–{ $last }
+percent-read = { $percent } % lu
+percent = { $percent } %
+
+## Book and series
+untitled = Sans titre
+unknown-author = Auteur inconnu
+more-authors = { $names } +{ $count } autres
+volume = Vol. { $number }
+chapter = Chap. { $number }
+book = Livre { $number }
+view-on = Voir sur { $provider }
+from-library = De : { $library }
+status-ended = Terminée
+status-ongoing = En cours
+status-abandoned = Abandonnée
+status-hiatus = En pause
+
+## Reading time, sessions and goals
+minutes = { $minutes } min
+hours-minutes = { $hours } h { $minutes }
+time-left = encore ~{ $time }
+session-pages = { $pages ->
+    [one] { $pages } page
+   *[other] { $pages } pages
+} en { $minutes } min
+session-rate = { $rate } pages/h
+streak = 🔥 { $days ->
+    [one] { $days } jour
+   *[other] { $days } jours
+} d'affilée
+goal = objectif : { $goals }
+goal-pages = { $done }/{ $goal } { $goal ->
+    [one] page
+   *[other] pages
+}
+goal-minutes = { $done }/{ $goal } min
+
+## Status
+resuming = reprise…
+server-restarting = redémarrage du serveur
@@ -0,0 +1,88 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::scrobble::ProgressUnit;
+
+const API_URL: &str = "https://graphql.anilist.co";
+
+const SEARCH_QUERY: &str = "query ($search: String) {
+  Page(perPage: 10) {
+    media(search: $search, type: MANGA) { id title { romaji english native } synonyms }
+  }
+}";
+
+const ENTRY_QUERY: &str = "query ($id: Int) {
+  Media(id: $id) { volumes chapters mediaListEntry { status progress progressVolumes } }
+}";
+
+const SAVE_MUTATION: &str = "mutation ($mediaId: Int, $progress: Int, $progressVolumes: Int, $status: MediaListStatus) {
+  SaveMediaListEntry(mediaId: $mediaId, progress: $progress, progressVolumes: $progressVolumes, status: $status) { id }
+}";
+
+/// AniList GraphQL client. The token comes from AniList's OAuth implicit grant
+/// (see anilist_token in config.json.example) and is valid for a year.
+pub struct AniList {
+    token: String,
+}
+
+impl AniList {
+    pub fn new(token: String) -> Self {
+        AniList { token }
+    }
+
+    async fn graphql(&self, client: &Client, query: &str, variables: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let response: Value = client
+            .post(API_URL)
+            .bearer_auth(&self.token)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(message) = response["errors"].as_array().and_then(|errors| errors.first()).and_then(|e| e["message"].as_str()) {
+            return Err(format!("AniList: {}", message).into());
+        }
+        Ok(response["data"].clone())
+    }
+
+    pub async fn search(&self, client: &Client, title: &str) -> Result<Vec<(String, Vec<String>)>, Box<dyn std::error::Error>> {
+        let data = self.graphql(client, SEARCH_QUERY, json!({ "search": title })).await?;
+        let media = data["Page"]["media"].as_array().cloned().unwrap_or_default();
+        Ok(media
+            .iter()
+            .filter_map(|m| {
+                let id = m["id"].as_u64()?.to_string();
+                let titles = ["romaji", "english", "native"]
+                    .iter()
+                    .filter_map(|key| m["title"][key].as_str())
+                    .chain(m["synonyms"].as_array().into_iter().flatten().filter_map(|s| s.as_str()))
+                    .map(|s| s.to_string())
+                    .collect();
+                Some((id, titles))
+            })
+            .collect())
+    }
+
+    pub async fn update_progress(&self, client: &Client, media_id: &str, progress: u32, unit: ProgressUnit) -> Result<(), Box<dyn std::error::Error>> {
+        let media_id: u64 = media_id.parse().map_err(|_| format!("AniList IDs are numeric, got '{}'", media_id))?;
+        let media = &self.graphql(client, ENTRY_QUERY, json!({ "id": media_id })).await?["Media"];
+        let entry = &media["mediaListEntry"];
+        let (field, current, total) = match unit {
+            ProgressUnit::Volumes => ("progressVolumes", entry["progressVolumes"].as_u64(), media["volumes"].as_u64()),
+            ProgressUnit::Chapters => ("progress", entry["progress"].as_u64(), media["chapters"].as_u64()),
+        };
+        if current.is_some_and(|current| current >= progress as u64) {
+            return Ok(());
+        }
+        let status = match entry["status"].as_str() {
+            // A reread stays a reread until the user closes it out on AniList
+            Some("REPEATING") => "REPEATING",
+            _ if total.is_some_and(|total| progress as u64 >= total) => "COMPLETED",
+            _ => "CURRENT",
+        };
+        let mut variables = json!({ "mediaId": media_id, "status": status });
+        variables[field] = progress.into();
+        self.graphql(client, SAVE_MUTATION, variables).await?;
+        Ok(())
+    }
+}
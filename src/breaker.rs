@@ -0,0 +1,180 @@
+use reqwest::StatusCode;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::Config;
+
+const DEFAULT_THRESHOLD: u32 = 3;
+const DEFAULT_PROBE_SECS: u64 = 120;
+const DEFAULT_MAINTENANCE_GRACE_SECS: u64 = 600;
+const DEFAULT_OFFLINE_GRACE_SECS: u64 = 300;
+
+/// Stops hammering Komga while it's down: after `outage_threshold` failed checks in a row the
+/// breaker opens, and checks only run every `outage_probe_secs` until one gets through.
+pub struct CircuitBreaker {
+    threshold: u32,
+    probe_interval: Duration,
+    failures: u32,
+    opened_at: Option<Instant>,
+    last_attempt: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn from_config(config: &Config) -> Self {
+        CircuitBreaker {
+            threshold: config.outage_threshold.unwrap_or(DEFAULT_THRESHOLD).max(1),
+            probe_interval: Duration::from_secs(config.outage_probe_secs.unwrap_or(DEFAULT_PROBE_SECS).max(1)),
+            failures: 0,
+            opened_at: None,
+            last_attempt: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.opened_at.is_some()
+    }
+
+    pub fn probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+
+    /// While open, whether the next probe is due.
+    pub fn probe_due(&self) -> bool {
+        self.last_attempt.is_none_or(|at| at.elapsed() >= self.probe_interval)
+    }
+
+    /// A check failed. Returns true when this failure opened the breaker.
+    pub fn record_failure(&mut self) -> bool {
+        self.failures += 1;
+        self.last_attempt = Some(Instant::now());
+        if self.opened_at.is_none() && self.failures >= self.threshold {
+            self.opened_at = Some(Instant::now());
+            return true;
+        }
+        false
+    }
+
+    /// Open straight away, for an outage already known to be more than a blip. Returns true
+    /// when this opened the breaker.
+    pub fn trip(&mut self) -> bool {
+        self.failures = self.failures.max(self.threshold);
+        self.last_attempt = Some(Instant::now());
+        if self.opened_at.is_some() {
+            return false;
+        }
+        self.opened_at = Some(Instant::now());
+        true
+    }
+
+    /// A check succeeded. Returns how long Komga was unreachable if this closed the breaker.
+    pub fn record_success(&mut self) -> Option<Duration> {
+        self.failures = 0;
+        self.last_attempt = Some(Instant::now());
+        self.opened_at.take().map(|at| at.elapsed())
+    }
+}
+
+/// Komga answered 502 or 503, which is what it (or the proxy in front of it) says while
+/// restarting or updating.
+#[derive(Debug)]
+pub struct ServerUnavailable(pub StatusCode);
+
+impl fmt::Display for ServerUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Komga is unavailable ({})", self.0)
+    }
+}
+
+impl Error for ServerUnavailable {}
+
+impl ServerUnavailable {
+    /// The error for `status`, if it's one a restarting Komga gives.
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        matches!(status, StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE).then_some(ServerUnavailable(status))
+    }
+}
+
+/// Whether `error` says this machine is offline rather than Komga being down: the connection
+/// couldn't be made because the network or host is unreachable, or names can't be looked up at all.
+pub fn is_offline(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+    let mut connecting = false;
+    while let Some(err) = source {
+        if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            if !e.is_connect() {
+                return false;
+            }
+            connecting = true;
+        }
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            return match io_err.kind() {
+                io::ErrorKind::NetworkUnreachable | io::ErrorKind::NetworkDown | io::ErrorKind::HostUnreachable => true,
+                io::ErrorKind::TimedOut => false,
+                // A connection that failed at the socket carries its OS error code; a failed name
+                // lookup has none, nor a kind of its own to check
+                _ => connecting && io_err.raw_os_error().is_none(),
+            };
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// How long the last activity stays up through a passing failure before it counts as an outage,
+/// rather than flapping between reading and cleared: Komga answering 502/503 while it restarts
+/// (`maintenance_grace_secs`), or this machine dropping off the network (`offline_grace_secs`).
+pub struct Grace {
+    grace: Duration,
+    since: Option<Instant>,
+    last_attempt: Option<Instant>,
+}
+
+impl Grace {
+    pub fn maintenance(config: &Config) -> Self {
+        Grace::new(config.maintenance_grace_secs.unwrap_or(DEFAULT_MAINTENANCE_GRACE_SECS))
+    }
+
+    pub fn offline(config: &Config) -> Self {
+        Grace::new(config.offline_grace_secs.unwrap_or(DEFAULT_OFFLINE_GRACE_SECS))
+    }
+
+    fn new(secs: u64) -> Self {
+        Grace { grace: Duration::from_secs(secs), since: None, last_attempt: None }
+    }
+
+    /// The failure happened (again). Returns whether that's still within the grace period, and
+    /// whether this is the start of it.
+    pub fn unavailable(&mut self) -> (bool, bool) {
+        let started = self.since.is_none();
+        let since = *self.since.get_or_insert_with(Instant::now);
+        self.last_attempt = Some(Instant::now());
+        (since.elapsed() < self.grace, started)
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    /// Whether the failure is ongoing, in the grace period or past it.
+    pub fn is_active(&self) -> bool {
+        self.since.is_some()
+    }
+
+    /// Whether the failure is ongoing and still within the grace period.
+    pub fn in_grace(&self) -> bool {
+        self.since.is_some_and(|since| since.elapsed() < self.grace)
+    }
+
+    /// While active, whether it's been `every` since the last attempt.
+    pub fn retry_due(&self, every: Duration) -> bool {
+        self.last_attempt.is_none_or(|at| at.elapsed() >= every)
+    }
+
+    /// A check got through. Returns how long the failure lasted, if there was one.
+    pub fn available(&mut self) -> Option<Duration> {
+        self.last_attempt = None;
+        self.since.take().map(|since| since.elapsed())
+    }
+}
@@ -0,0 +1,206 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use reqwest::Client;
+use serde_json::Value;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tracing::{debug, info};
+
+use crate::cover_cache::CoverCache;
+use crate::media_server::{rehost_cover, Reading};
+use crate::numbering::BookNumbers;
+use crate::{locale, stats, timestamps, Config, Presence};
+
+// OPDS feeds are paged by Calibre-Web; only the first books of the feed are checked for progress
+const MAX_CANDIDATES: usize = 20;
+
+static ENTRY_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<id>urn:uuid:([0-9a-fA-F-]+)</id>").unwrap());
+static CATEGORY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<category[^>]*\bterm="([^"]*)""#).unwrap());
+static DOWNLOAD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"/opds/download/\d+/(\w+)/"#).unwrap());
+
+/// Calibre-Web (or Calibre-Web-Automated) client. Calibre-Web only keeps reading progress for
+/// Kobo sync, so the books come from one of its OPDS feeds and their progress from the Kobo
+/// sync API, which is only ever read from.
+pub struct CalibreWeb {
+    url: String,
+    kobo_token: String,
+    username: Option<String>,
+    password: Option<String>,
+    shelf: Option<String>,
+}
+
+// A book from the OPDS feed: its UUID, tags and download formats
+struct Entry {
+    uuid: String,
+    tags: Vec<String>,
+    formats: Vec<String>,
+}
+
+impl CalibreWeb {
+    pub fn from_config(config: &Config) -> Self {
+        // The key can be the whole Kobo sync URL from the profile page, or just its token
+        let kobo_token = config.komga_api_key.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_string();
+        CalibreWeb {
+            url: config.komga_url.trim_end_matches('/').to_string(),
+            kobo_token,
+            username: config.calibre_web_username.clone(),
+            password: config.calibre_web_password.clone(),
+            shelf: config.calibre_web_shelf.clone(),
+        }
+    }
+
+    async fn entries(&self, client: &Client) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+        let url = match self.shelf {
+            Some(ref shelf) => format!("{}/opds/shelf/{}", self.url, shelf),
+            None => format!("{}/opds/unreadbooks", self.url),
+        };
+        let mut request = client.get(&url);
+        if let Some(ref username) = self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        let started = Instant::now();
+        let response = request.send().await;
+        stats::record_latency(&stats::endpoint_key("GET", &url), started.elapsed());
+        let feed = response?.error_for_status()?.text().await?;
+        // The feed has an ID of its own before the first entry
+        Ok(feed
+            .split("<entry>")
+            .skip(1)
+            .filter_map(|entry| {
+                Some(Entry {
+                    uuid: ENTRY_ID.captures(entry)?[1].to_string(),
+                    tags: CATEGORY.captures_iter(entry).map(|c| unescape(&c[1])).collect(),
+                    formats: DOWNLOAD.captures_iter(entry).map(|c| c[1].to_lowercase()).collect(),
+                })
+            })
+            .take(MAX_CANDIDATES)
+            .collect())
+    }
+
+    async fn kobo(&self, client: &Client, uuid: &str, what: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let url = format!("{}/kobo/{}/v1/library/{}/{}", self.url, self.kobo_token, uuid, what);
+        let started = Instant::now();
+        let response = client.get(&url).send().await;
+        // The token is as good as a password; keep it out of the stats
+        stats::record_latency(&stats::endpoint_key("GET", &url.replace(&self.kobo_token, "token")), started.elapsed());
+        // Without the URL, which has the token in it
+        let response = response?.error_for_status().map_err(|e| e.without_url())?;
+        // Both endpoints answer with a one-element list
+        let mut body: Value = response.json().await?;
+        Ok(body.get_mut(0).map(Value::take).unwrap_or(body))
+    }
+
+    /// What should be shown for the book most recently read on Calibre-Web, like the Komga
+    /// build_presence.
+    pub async fn build_presence(
+        &self,
+        client: &Client,
+        config: &Config,
+        imgur_cache: &mut CoverCache,
+        previous: Option<&Presence>,
+    ) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+        let mut latest: Option<(DateTime<Utc>, Entry, Value)> = None;
+        for entry in self.entries(client).await? {
+            let state = match self.kobo(client, &entry.uuid, "state").await {
+                Ok(state) => state,
+                Err(e) => {
+                    debug!(uuid = %entry.uuid, error = %e, "No Kobo reading state for book");
+                    continue;
+                }
+            };
+            if state["StatusInfo"]["Status"].as_str() != Some("Reading") {
+                continue;
+            }
+            let read_at = state["CurrentBookmark"]["LastModified"].as_str().or(state["LastModified"].as_str());
+            let Some(read_at) = read_at.and_then(|s| timestamps::parse(config, s)) else {
+                continue;
+            };
+            if latest.as_ref().is_none_or(|(latest, _, _)| read_at > *latest) {
+                latest = Some((read_at, entry, state));
+            }
+        }
+        let Some((read_at, entry, state)) = latest else {
+            info!("No in-progress books found in Calibre-Web");
+            return Ok(None);
+        };
+        // Only show as reading if updated in the last 5 minutes
+        if !timestamps::is_recent(config, read_at) {
+            info!(updated_at = %read_at, "Most recent in-progress book activity is too old, clearing Discord status");
+            return Ok(None);
+        }
+
+        let metadata = self.kobo(client, &entry.uuid, "metadata").await?;
+        let title = metadata["Title"].as_str().filter(|s| !s.is_empty()).map(|s| s.to_string()).unwrap_or_else(|| locale::text("untitled", &[]));
+        // A book outside any series is shown by its own title
+        let series = metadata["Series"]["Name"].as_str().filter(|s| !s.is_empty());
+        let series_id = series.and(text(&metadata["Series"]["Id"])).unwrap_or_else(|| entry.uuid.clone());
+        let series_title = series.map(|s| s.to_string()).unwrap_or_else(|| title.clone());
+        info!(%series_title, "Resolved series title");
+        let media_profile = match entry.formats.iter().map(|f| f.as_str()).collect::<Vec<_>>().as_slice() {
+            formats if formats.contains(&"epub") || formats.contains(&"kepub") => "EPUB",
+            ["pdf"] => "PDF",
+            formats if formats.iter().any(|f| matches!(*f, "cbz" | "cbr" | "cb7")) => "DIVINA",
+            _ => "EPUB",
+        };
+        let percent = state["CurrentBookmark"]["ProgressPercent"].as_f64().map(|p| p.clamp(0.0, 100.0).round() as u32);
+
+        let reading = Reading {
+            series_id,
+            series_title,
+            alternate_titles: Vec::new(),
+            book_id: entry.uuid.clone(),
+            book_title: series.map(|_| title),
+            numbers: BookNumbers {
+                number: series.and(text(&metadata["Series"]["Number"])),
+                ..Default::default()
+            },
+            oneshot: false,
+            isbn: metadata["Isbn"].as_str().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+            page: None,
+            pages: None,
+            percent,
+            percent_only: false,
+            library: None,
+            formats: entry.formats,
+            media_profile: Some(media_profile.to_string()),
+            read_at: Some(read_at),
+            authors: metadata["Contributors"].as_array().into_iter().flatten().filter_map(|a| a.as_str()).map(|s| s.to_string()).collect(),
+            publisher: metadata["Publisher"]["Name"].as_str().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+            release_date: None,
+            status: None,
+            read_list: None,
+            buttons: Vec::new(),
+            tags: entry.tags,
+            context: serde_json::json!({ "book": metadata, "series": Value::Null, "library": Value::Null, "state": state }),
+        };
+        let hides_cover = reading.hides_cover(config);
+        let Some(mut presence) = reading.into_presence(config, previous) else {
+            return Ok(None);
+        };
+        if presence.large_image.is_none() && !hides_cover {
+            let cover = client.get(format!("{}/kobo/{}/{}/512/512/false/image.jpg", self.url, self.kobo_token, entry.uuid));
+            presence.large_image = rehost_cover(client, config, format!("calibre_web_{}_{}", self.url, entry.uuid), "", cover, imgur_cache).await;
+        }
+        Ok(Some(presence))
+    }
+
+    /// Whether the Kobo reading state of the book shown in `presence` says it's finished.
+    pub async fn completed(&self, client: &Client, presence: &Presence) -> Option<bool> {
+        let state = self.kobo(client, &presence.book_id, "state").await.ok()?;
+        Some(state["StatusInfo"]["Status"].as_str()? == "Finished")
+    }
+}
+
+// A string or number field as text
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// The XML escapes OPDS attributes can contain
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
@@ -0,0 +1,155 @@
+use serde_json::{Map, Value};
+use std::fs;
+
+/// Version of the config format this build writes. Configs without `config_version` predate it,
+/// including everything the standalone installer wrote.
+pub const CONFIG_VERSION: u64 = 1;
+
+// Options that are on/off switches, so "true" and "false" strings can be read as such
+const BOOL_KEYS: &[&str] = &[
+    "read_only",
+    "show_progress",
+    "use_imgur_cover",
+    "push_only",
+    "mqtt_homeassistant_discovery",
+    "latin_titles_only",
+    "detect_spreads",
+    "show_series_status",
+    "show_elapsed",
+    "watchdog_notify",
+    "show_server_restarting",
+    "hide_authors",
+    "show_page_numbers",
+];
+
+// Options that are lists, so a single comma-separated string can be read as one
+const LIST_KEYS: &[&str] = &[
+    "exclude_libraries",
+    "exclude_tags",
+    "exclude_formats",
+    "nocover_tags",
+    "suppress_on_status",
+    "webhook_urls",
+    "webhook_events",
+    "hardcover_libraries",
+    "title_language",
+    "read_lists",
+    "author_roles",
+];
+
+/// Bring a config written by any version, or by the old installer, to the current format in
+/// place, returning what changed. Values the main loop would reject only for their shape are
+/// converted: `null`s (the installer wrote `"imgur_client_id": null`) are dropped, `"true"` strings
+/// become switches and comma-separated strings become lists, in `komga_servers` entries too.
+pub fn upgrade(config: &mut Value) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let Value::Object(root) = config else {
+        return Err("the config is not a JSON object".into());
+    };
+    let version = root.get("config_version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version > CONFIG_VERSION {
+        // Unknown options are ignored, so a newer config still loads; it's just not converted
+        return Ok(Vec::new());
+    }
+    let mut changes = upgrade_options(root, "");
+    if let Some(Value::Array(servers)) = root.get_mut("komga_servers") {
+        for (i, server) in servers.iter_mut().enumerate() {
+            if let Value::Object(server) = server {
+                changes.extend(upgrade_options(server, &format!("komga_servers[{}].", i)));
+            }
+        }
+    }
+    if version < CONFIG_VERSION {
+        root.insert("config_version".to_string(), CONFIG_VERSION.into());
+        changes.push(format!("set config_version to {}", CONFIG_VERSION));
+    }
+    Ok(changes)
+}
+
+fn upgrade_options(options: &mut Map<String, Value>, prefix: &str) -> Vec<String> {
+    let mut changes = Vec::new();
+    options.retain(|key, value| {
+        if value.is_null() {
+            changes.push(format!("removed {}{}, which was null", prefix, key));
+        }
+        !value.is_null()
+    });
+    for (key, value) in options.iter_mut() {
+        let Value::String(text) = value else {
+            continue;
+        };
+        if BOOL_KEYS.contains(&key.as_str()) {
+            let switch = match text.trim().to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => true,
+                "false" | "no" | "off" | "0" | "" => false,
+                _ => continue,
+            };
+            changes.push(format!("{}{}: \"{}\" became {}", prefix, key, text, switch));
+            *value = Value::Bool(switch);
+        } else if LIST_KEYS.contains(&key.as_str()) {
+            let items: Vec<Value> = text.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()).map(Value::from).collect();
+            changes.push(format!("{}{}: \"{}\" became a list of {}", prefix, key, text, items.len()));
+            *value = Value::Array(items);
+        }
+    }
+    changes
+}
+
+/// `config upgrade`: rewrite `config_file` in the current format and say what changed.
+pub fn run(config_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config: Value = serde_json::from_str(&fs::read_to_string(config_file)?)?;
+    if let Some(version) = config.get("config_version").and_then(|v| v.as_u64()).filter(|v| *v > CONFIG_VERSION) {
+        return Err(format!("{} was written by a newer komga-discord-rpc (config_version {}); this build knows up to {}", config_file, version, CONFIG_VERSION).into());
+    }
+    let changes = upgrade(&mut config)?;
+    if changes.is_empty() {
+        println!("{} is already up to date.", config_file);
+        return Ok(());
+    }
+    for change in &changes {
+        println!("  {}", change);
+    }
+    serde_json::from_value::<crate::Config>(config.clone()).map_err(|e| format!("the upgraded config still doesn't load: {}", e))?;
+    fs::write(config_file, serde_json::to_string_pretty(&config)? + "\n")?;
+    println!("Upgraded {}.", config_file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every switch and list option in `Config`, read from its definition so new ones are covered
+    fn options_of_type(ty: &str) -> Vec<&'static str> {
+        let source = include_str!("main.rs");
+        let start = source.find("struct Config {").unwrap();
+        let end = start + source[start..].find("\n}").unwrap();
+        source[start..end]
+            .lines()
+            .filter_map(|line| line.trim().split_once(": "))
+            .filter(|(_, rest)| rest.starts_with(ty))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn string_switches_and_lists_load_after_upgrade() {
+        let mut config = serde_json::json!({
+            "discord_client_id": "1234",
+            "komga_url": "http://localhost:25600",
+            "komga_api_key": "key",
+        });
+        let switches = options_of_type("Option<bool>");
+        let lists = options_of_type("Option<Vec<String>>");
+        assert!(switches.contains(&"show_page_numbers") && lists.contains(&"webhook_urls"));
+        for name in &switches {
+            config[name] = "yes".into();
+        }
+        for name in &lists {
+            config[name] = "a, b".into();
+        }
+        upgrade(&mut config).unwrap();
+        if let Err(e) = serde_json::from_value::<crate::Config>(config) {
+            panic!("upgraded config doesn't load: {}", e);
+        }
+    }
+}
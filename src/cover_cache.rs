@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+
+use crate::stats;
+
+/// A finished background upload: the cache key, and the cover's URL unless the upload failed.
+pub type Finished = (String, Option<String>);
+
+/// An upload held back by `defer`, not started until it's handed to `run`.
+pub struct Deferred {
+    key: String,
+    upload: Pin<Box<dyn Future<Output = Option<String>> + Send>>,
+}
+
+/// Covers already uploaded to Imgur this run, by where they came from ("komga_<series id>",
+/// "kavita_<url>_<series id>", ...), so each one is uploaded only once.
+pub struct CoverCache {
+    urls: HashMap<String, String>,
+    // Where background uploads report back; None uploads while the caller waits
+    finished: Option<mpsc::UnboundedSender<Finished>>,
+    pending: HashSet<String>,
+    deferring: bool,
+    deferred: Option<Deferred>,
+}
+
+impl CoverCache {
+    /// Uploads happen while the caller waits, for one-off runs like `preview`.
+    pub fn new() -> Self {
+        CoverCache { urls: HashMap::new(), finished: None, pending: HashSet::new(), deferring: false, deferred: None }
+    }
+
+    /// Uploads run on their own task so the activity doesn't wait for Imgur; each one comes back
+    /// on the receiver, to be handed to `finish`.
+    pub fn background() -> (Self, mpsc::UnboundedReceiver<Finished>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (CoverCache { urls: HashMap::new(), finished: Some(sender), pending: HashSet::new(), deferring: false, deferred: None }, receiver)
+    }
+
+    /// The uploaded URL for `key`, counted as a hit or a miss for `--stats`.
+    pub fn lookup(&self, key: &str) -> Option<String> {
+        let found = self.urls.get(key).cloned();
+        stats::record_cache("Imgur cover", found.is_some());
+        found
+    }
+
+    /// Run `upload` for the cover under `key`. Waiting on it, its URL comes straight back; in the
+    /// background this returns None at once and the URL arrives through the receiver instead.
+    pub async fn upload<F>(&mut self, key: String, upload: F) -> Option<String>
+    where
+        F: Future<Output = Option<String>> + Send + 'static,
+    {
+        if self.deferring {
+            self.deferred = Some(Deferred { key, upload: Box::pin(upload) });
+            return None;
+        }
+        let Some(ref finished) = self.finished else {
+            let url = upload.await?;
+            self.urls.insert(key, url.clone());
+            return Some(url);
+        };
+        // One upload per cover, however many checks happen while it's going
+        if self.pending.insert(key.clone()) {
+            let finished = finished.clone();
+            tokio::spawn(async move {
+                let url = upload.await;
+                let _ = finished.send((key, url));
+            });
+        }
+        None
+    }
+
+    /// Hold the next upload back instead of starting it, for when the cover may not be shown at all.
+    pub fn defer(&mut self) {
+        self.deferring = true;
+    }
+
+    /// Stop holding uploads back, and take the one held since `defer`, if any. Dropping it means
+    /// the cover is never downloaded.
+    pub fn take_deferred(&mut self) -> Option<Deferred> {
+        self.deferring = false;
+        self.deferred.take()
+    }
+
+    /// Start a held-back upload, as `upload` would have.
+    pub async fn run(&mut self, deferred: Deferred) -> Option<String> {
+        self.upload(deferred.key, deferred.upload).await
+    }
+
+    /// Take in a finished background upload. Returns whether it brought a new cover.
+    pub fn finish(&mut self, (key, url): Finished) -> bool {
+        self.pending.remove(&key);
+        match url {
+            Some(url) => {
+                self.urls.insert(key, url);
+                true
+            }
+            None => false,
+        }
+    }
+}
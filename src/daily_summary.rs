@@ -0,0 +1,96 @@
+use chrono::{Datelike, Local, NaiveTime, TimeZone, Utc, Weekday};
+use reqwest::Client;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::sessions::{Session, SharedSessions};
+use crate::Config;
+
+const DEFAULT_TIME: &str = "22:00";
+// Discord allows ten embeds per message; one is the summary itself
+const MAX_BOOK_EMBEDS: usize = 9;
+
+/// Post a summary of the day's reading sessions to `daily_summary_webhook` every day at
+/// `daily_summary_time` (local time). Days without reading are skipped.
+pub fn spawn(config: &Config, client: &Client, sessions: SharedSessions) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(webhook) = config.daily_summary_webhook.clone() else {
+        return Ok(());
+    };
+    let time_str = config.daily_summary_time.as_deref().unwrap_or(DEFAULT_TIME);
+    let time = NaiveTime::parse_from_str(time_str, "%H:%M").map_err(|_| format!("daily_summary_time '{}' is not HH:MM", time_str))?;
+    let client = client.clone();
+    tokio::spawn(async move {
+        let mut last_summary = Utc::now() - chrono::Duration::hours(24);
+        loop {
+            tokio::time::sleep(until_next(time, None)).await;
+            let day = sessions.lock().unwrap().since(last_summary);
+            last_summary = Utc::now();
+            if day.is_empty() {
+                info!("No reading today, skipping the daily summary");
+                continue;
+            }
+            let result = client.post(&webhook).json(&summary_message(&day)).send().await.and_then(|r| r.error_for_status());
+            match result {
+                Ok(_) => info!(sessions = day.len(), "Posted daily reading summary"),
+                Err(e) => warn!(error = %e, "Failed to post daily reading summary"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Time until the next `time` (local), on `weekday` if given.
+pub fn until_next(time: NaiveTime, weekday: Option<Weekday>) -> Duration {
+    let now = Local::now();
+    let mut next = now.date_naive().and_time(time);
+    while next <= now.naive_local() || weekday.is_some_and(|day| next.weekday() != day) {
+        next += chrono::Duration::days(1);
+    }
+    // A time skipped by a DST change falls back to an hour later
+    let next = Local.from_local_datetime(&next).earliest().unwrap_or_else(|| now + chrono::Duration::hours(1));
+    (next - now).to_std().unwrap_or(Duration::from_secs(60))
+}
+
+fn summary_message(sessions: &[Session]) -> serde_json::Value {
+    let books: HashSet<&str> = sessions.iter().map(|s| s.book_id.as_str()).collect();
+    let finished = sessions.iter().filter(|s| s.finished).count();
+    let pages: u32 = sessions.iter().map(|s| s.pages_read()).sum();
+    let minutes: i64 = sessions.iter().map(|s| s.duration().num_minutes()).sum();
+
+    let mut embeds = vec![json!({
+        "title": format!("Reading summary for {}", Local::now().format("%A, %B %-d")),
+        "color": 0x005ed3,
+        "fields": [
+            { "name": "Books read", "value": format!("{} ({} finished)", books.len(), finished), "inline": true },
+            { "name": "Pages turned", "value": pages.to_string(), "inline": true },
+            { "name": "Time spent", "value": format!("{}h {:02}m", minutes / 60, minutes % 60), "inline": true },
+        ],
+    })];
+
+    // One card per book, with its cover, most recent first
+    let mut seen = HashSet::new();
+    for session in sessions.iter().rev().filter(|s| seen.insert(s.book_id.as_str())).take(MAX_BOOK_EMBEDS) {
+        let book_sessions: Vec<&Session> = sessions.iter().filter(|s| s.book_id == session.book_id).collect();
+        let title = match (&session.book_number, &session.book_title) {
+            (Some(number), _) => format!("{} — Book {}", session.series_title, number),
+            (None, Some(title)) => format!("{} — {}", session.series_title, title),
+            (None, None) => session.series_title.clone(),
+        };
+        let mut embed = json!({
+            "title": title,
+            "description": format!(
+                "{} pages in {} min{}",
+                book_sessions.iter().map(|s| s.pages_read()).sum::<u32>(),
+                book_sessions.iter().map(|s| s.duration().num_minutes()).sum::<i64>(),
+                if book_sessions.iter().any(|s| s.finished) { " · finished" } else { "" }
+            ),
+        });
+        if let Some(ref cover) = session.cover_url {
+            embed["thumbnail"] = json!({ "url": cover });
+        }
+        embeds.push(embed);
+    }
+    json!({ "username": "Komga RPC", "embeds": embeds })
+}
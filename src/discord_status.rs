@@ -0,0 +1,138 @@
+use tracing::{debug, error, info, warn};
+use serde_json::{json, Value};
+use std::io;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+const GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json";
+// GUILDS | GUILD_PRESENCES; the presence intent must also be enabled for the bot in the developer portal
+const GATEWAY_INTENTS: u64 = (1 << 0) | (1 << 8);
+// Close codes after which reconnecting can never succeed (bad token, disallowed intents, ...)
+const FATAL_CLOSE_CODES: [u16; 5] = [4004, 4010, 4011, 4013, 4014];
+
+/// Follows the user's own Discord status through a bot that shares a server with them.
+/// The IPC handshake only identifies the user, it never reports their status, so a gateway
+/// connection with the presence intent is the only way to learn about DND/Invisible.
+pub struct DiscordStatusWatcher {
+    status: Arc<Mutex<Option<String>>>,
+}
+
+impl DiscordStatusWatcher {
+    pub fn spawn(bot_token: String, user_id: String) -> Self {
+        let status = Arc::new(Mutex::new(None));
+        let shared = Arc::clone(&status);
+        thread::spawn(move || loop {
+            match watch_gateway(&bot_token, &user_id, &shared) {
+                Ok(true) => {
+                    error!("Discord rejected the status bot connection; presence suppression is disabled");
+                    *shared.lock().unwrap() = None;
+                    return;
+                }
+                Ok(false) => debug!("Discord gateway asked us to reconnect"),
+                Err(e) => warn!(error = %e, "Discord status watcher disconnected"),
+            }
+            thread::sleep(Duration::from_secs(30));
+        });
+        DiscordStatusWatcher { status }
+    }
+
+    /// Last seen status: "online", "idle", "dnd" or "offline" (which is how Invisible appears to bots).
+    pub fn status(&self) -> Option<String> {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+// Runs one gateway session. Returns Ok(true) when the session ended in a way retrying won't fix.
+fn watch_gateway(bot_token: &str, user_id: &str, status: &Mutex<Option<String>>) -> Result<bool, Box<dyn std::error::Error>> {
+    let (mut socket, _) = tungstenite::connect(GATEWAY_URL)?;
+    set_read_timeout(&socket, Duration::from_secs(1))?;
+
+    let mut heartbeat_interval = Duration::from_secs(41);
+    let mut last_heartbeat = Instant::now();
+    let mut sequence = Value::Null;
+
+    loop {
+        if last_heartbeat.elapsed() >= heartbeat_interval {
+            socket.send(Message::text(json!({ "op": 1, "d": sequence }).to_string()))?;
+            last_heartbeat = Instant::now();
+        }
+
+        let message = match socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let payload: Value = match message {
+            Message::Text(text) => serde_json::from_str(text.as_str())?,
+            Message::Close(frame) => {
+                let code = frame.map(|f| u16::from(f.code)).unwrap_or(1000);
+                return Ok(FATAL_CLOSE_CODES.contains(&code));
+            }
+            _ => continue,
+        };
+
+        if !payload["s"].is_null() {
+            sequence = payload["s"].clone();
+        }
+        match payload["op"].as_u64() {
+            // Hello: start heartbeating and identify
+            Some(10) => {
+                let interval = payload["d"]["heartbeat_interval"].as_u64().unwrap_or(41_250);
+                heartbeat_interval = Duration::from_millis(interval);
+                let identify = json!({
+                    "op": 2,
+                    "d": {
+                        "token": bot_token,
+                        "intents": GATEWAY_INTENTS,
+                        "properties": { "os": std::env::consts::OS, "browser": "komga-rpc", "device": "komga-rpc" }
+                    }
+                });
+                socket.send(Message::text(identify.to_string()))?;
+            }
+            // Heartbeat request
+            Some(1) => {
+                socket.send(Message::text(json!({ "op": 1, "d": sequence }).to_string()))?;
+                last_heartbeat = Instant::now();
+            }
+            // Reconnect / invalid session
+            Some(7) | Some(9) => return Ok(false),
+            Some(0) => handle_dispatch(&payload, user_id, status),
+            _ => {}
+        }
+    }
+}
+
+fn handle_dispatch(payload: &Value, user_id: &str, status: &Mutex<Option<String>>) {
+    let data = &payload["d"];
+    let seen = match payload["t"].as_str() {
+        Some("READY") => {
+            info!(%user_id, "Watching Discord status");
+            None
+        }
+        Some("GUILD_CREATE") => data["presences"]
+            .as_array()
+            .and_then(|presences| presences.iter().find(|p| p["user"]["id"].as_str() == Some(user_id)))
+            .and_then(|p| p["status"].as_str()),
+        Some("PRESENCE_UPDATE") if data["user"]["id"].as_str() == Some(user_id) => data["status"].as_str(),
+        _ => None,
+    };
+    if let Some(new_status) = seen {
+        let mut current = status.lock().unwrap();
+        if current.as_deref() != Some(new_status) {
+            info!(status = %new_status, "Discord status changed");
+            *current = Some(new_status.to_string());
+        }
+    }
+}
+
+fn set_read_timeout(socket: &WebSocket<MaybeTlsStream<TcpStream>>, timeout: Duration) -> io::Result<()> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::fmt;
+use std::process::ExitCode;
+
+// Exit codes, after BSD's sysexits.h, so a service manager can tell a failure worth restarting
+// over from one that will only happen again. Anything else exits with 1.
+pub const USAGE: u8 = 64; // Unknown command or bad arguments
+pub const UNAVAILABLE: u8 = 69; // Discord couldn't be reached within discord_connect_timeout_secs
+pub const NO_PERMISSION: u8 = 77; // Komga keeps turning the API key away
+pub const CONFIG: u8 = 78; // The config is missing, unreadable or invalid
+
+/// An error the process exits on with `code` rather than 1.
+#[derive(Debug)]
+pub struct Fatal {
+    code: u8,
+    error: Box<dyn Error>,
+}
+
+impl fmt::Display for Fatal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for Fatal {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// For `map_err`: the error, exiting with `code`.
+pub fn with<E: Into<Box<dyn Error>>>(code: u8) -> impl Fn(E) -> Box<dyn Error> {
+    move |error| Box::new(Fatal { code, error: error.into() })
+}
+
+/// The exit code `error` ends the process with.
+pub fn code(error: &(dyn Error + 'static)) -> ExitCode {
+    ExitCode::from(error.downcast_ref::<Fatal>().map_or(1, |fatal| fatal.code))
+}
@@ -0,0 +1,173 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::scrobble::MappingCache;
+use crate::{Config, Presence};
+
+const API_URL: &str = "https://api.hardcover.app/v1/graphql";
+
+// Hardcover's user_book status IDs
+const CURRENTLY_READING: u64 = 2;
+const READ: u64 = 3;
+
+const EDITION_BY_ISBN: &str = "query ($isbn: String!) {
+  editions(where: {_or: [{isbn_13: {_eq: $isbn}}, {isbn_10: {_eq: $isbn}}]}, limit: 1) { id book_id }
+}";
+
+const BOOK_BY_TITLE: &str = "query ($title: String!) {
+  books(where: {title: {_ilike: $title}}, order_by: {users_count: desc}, limit: 1) { id }
+}";
+
+const USER_BOOK: &str = "query ($bookId: Int!) {
+  me { user_books(where: {book_id: {_eq: $bookId}}) { id status_id } }
+}";
+
+const INSERT_USER_BOOK: &str = "mutation ($object: UserBookCreateInput!) {
+  insert_user_book(object: $object) { id error }
+}";
+
+const UPDATE_USER_BOOK: &str = "mutation ($id: Int!, $object: UserBookUpdateInput!) {
+  update_user_book(id: $id, object: $object) { id error }
+}";
+
+/// Marks books from ebook/novel libraries as "Currently Reading" on Hardcover when they are
+/// opened and "Read" when Komga marks them completed. Books are matched by ISBN from the Komga
+/// metadata, falling back to the title.
+#[derive(Clone)]
+pub struct Hardcover {
+    inner: Arc<HardcoverInner>,
+}
+
+struct HardcoverInner {
+    client: Client,
+    token: String,
+    libraries: Option<Vec<String>>,
+    cache: Arc<MappingCache>,
+    synced: Mutex<HashMap<String, u64>>, // Komga book ID -> status already sent this run
+}
+
+impl Hardcover {
+    pub fn from_config(config: &Config, client: &Client, cache: Arc<MappingCache>) -> Option<Self> {
+        let token = config.hardcover_token.as_ref()?;
+        // The settings page shows the token with its "Bearer " prefix; accept it either way
+        let token = token.strip_prefix("Bearer ").unwrap_or(token).to_string();
+        Some(Hardcover {
+            inner: Arc::new(HardcoverInner {
+                client: client.clone(),
+                token,
+                libraries: config.hardcover_libraries.clone(),
+                cache,
+                synced: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    pub fn reading(&self, presence: &Presence) {
+        self.sync(presence, CURRENTLY_READING);
+    }
+
+    pub fn finished(&self, presence: &Presence) {
+        self.sync(presence, READ);
+    }
+
+    // Without hardcover_libraries, only EPUBs count as ebooks
+    fn applies_to(&self, presence: &Presence) -> bool {
+        match (&self.inner.libraries, &presence.library) {
+            (Some(libraries), Some(library)) => libraries.iter().any(|l| l.eq_ignore_ascii_case(library)),
+            (Some(_), None) => false,
+            (None, _) => presence.media_profile.as_deref() == Some("EPUB"),
+        }
+    }
+
+    fn sync(&self, presence: &Presence, status: u64) {
+        if !self.applies_to(presence) {
+            return;
+        }
+        if self.inner.synced.lock().unwrap().insert(presence.book_id.clone(), status) == Some(status) {
+            return;
+        }
+        let hardcover = self.clone();
+        let presence = presence.clone();
+        tokio::spawn(async move {
+            if let Err(e) = hardcover.set_status(&presence, status).await {
+                warn!(book = ?presence.book_title, error = %e, "Failed to update Hardcover");
+                hardcover.inner.synced.lock().unwrap().remove(&presence.book_id);
+            }
+        });
+    }
+
+    async fn graphql(&self, query: &str, variables: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let response: Value = self
+            .inner
+            .client
+            .post(API_URL)
+            .bearer_auth(&self.inner.token)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(message) = response["errors"].as_array().and_then(|errors| errors.first()).and_then(|e| e["message"].as_str()) {
+            return Err(format!("Hardcover: {}", message).into());
+        }
+        Ok(response["data"].clone())
+    }
+
+    async fn set_status(&self, presence: &Presence, status: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((book_id, edition_id)) = self.find_book(presence).await? else {
+            warn!(book = ?presence.book_title, isbn = ?presence.isbn, "No Hardcover book matches this ISBN or title");
+            return Ok(());
+        };
+        let data = self.graphql(USER_BOOK, json!({ "bookId": book_id })).await?;
+        let existing = &data["me"][0]["user_books"][0];
+        // Don't move a finished book back to "Currently Reading" when it's opened again
+        if existing["status_id"].as_u64().is_some_and(|current| current == status || current == READ) {
+            return Ok(());
+        }
+
+        let mut object = json!({ "status_id": status });
+        if let Some(edition_id) = edition_id {
+            object["edition_id"] = edition_id.into();
+        }
+        let result = match existing["id"].as_u64() {
+            Some(id) => self.graphql(UPDATE_USER_BOOK, json!({ "id": id, "object": object })).await?["update_user_book"].clone(),
+            None => {
+                object["book_id"] = book_id.into();
+                self.graphql(INSERT_USER_BOOK, json!({ "object": object })).await?["insert_user_book"].clone()
+            }
+        };
+        if let Some(error) = result["error"].as_str() {
+            return Err(format!("Hardcover: {}", error).into());
+        }
+        let status_name = if status == READ { "Read" } else { "Currently Reading" };
+        info!(book = ?presence.book_title, status = status_name, "Hardcover status updated");
+        Ok(())
+    }
+
+    // Hardcover book ID and, when matched by ISBN, the exact edition
+    async fn find_book(&self, presence: &Presence) -> Result<Option<(u64, Option<u64>)>, Box<dyn std::error::Error>> {
+        if let Some(book_id) = self.inner.cache.get("hardcover", &presence.book_id).and_then(|id| id.parse().ok()) {
+            return Ok(Some((book_id, None)));
+        }
+        let mut found = None;
+        if let Some(ref isbn) = presence.isbn {
+            let isbn: String = isbn.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+            let data = self.graphql(EDITION_BY_ISBN, json!({ "isbn": isbn })).await?;
+            let edition = &data["editions"][0];
+            found = edition["book_id"].as_u64().map(|book_id| (book_id, edition["id"].as_u64()));
+        }
+        if found.is_none() {
+            if let Some(ref title) = presence.book_title {
+                let data = self.graphql(BOOK_BY_TITLE, json!({ "title": title })).await?;
+                found = data["books"][0]["id"].as_u64().map(|book_id| (book_id, None));
+            }
+        }
+        if let Some((book_id, _)) = found {
+            self.inner.cache.insert("hardcover", &presence.book_id, &book_id.to_string());
+        }
+        Ok(found)
+    }
+}
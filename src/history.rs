@@ -0,0 +1,167 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use rusqlite::{params, Connection, Row};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::sessions::Session;
+use crate::Config;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    book_id TEXT NOT NULL,
+    book_title TEXT,
+    book_number TEXT,
+    series_id TEXT NOT NULL,
+    series_title TEXT NOT NULL,
+    library TEXT,
+    cover_url TEXT,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    start_page INTEGER NOT NULL,
+    end_page INTEGER NOT NULL,
+    finished INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (book_id, started_at)
+);
+CREATE INDEX IF NOT EXISTS sessions_ended_at ON sessions (ended_at);
+";
+
+const COLUMNS: &str = "book_id, book_title, book_number, series_id, series_title, library, cover_url, started_at, ended_at, start_page, end_page, finished";
+
+/// Local SQLite record of every reading session (`history_db`), so the history survives
+/// restarts and Komga pruning its own read progress.
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+impl History {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        info!(%path, "Recording reading history");
+        Ok(History { conn: Mutex::new(conn) })
+    }
+
+    /// Insert the session, or update it if it was recorded earlier while still in progress.
+    pub fn record(&self, session: &Session) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            &format!(
+                "INSERT INTO sessions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                 ON CONFLICT (book_id, started_at) DO UPDATE SET
+                     ended_at = excluded.ended_at, end_page = excluded.end_page, finished = excluded.finished",
+                COLUMNS
+            ),
+            params![
+                session.book_id,
+                session.book_title,
+                session.book_number,
+                session.series_id,
+                session.series_title,
+                session.library,
+                session.cover_url,
+                session.started_at,
+                session.ended_at,
+                session.start_page,
+                session.end_page,
+                session.finished,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Sessions that ended after `since`, oldest first.
+    pub fn since(&self, since: DateTime<Utc>) -> rusqlite::Result<Vec<Session>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&format!("SELECT {} FROM sessions WHERE ended_at > ?1 ORDER BY started_at", COLUMNS))?;
+        let sessions = statement.query_map(params![since], session_from_row)?.collect();
+        sessions
+    }
+
+    /// Pages read per local calendar day, for days since `since`.
+    pub fn daily_pages(&self, since: DateTime<Utc>) -> rusqlite::Result<BTreeMap<NaiveDate, u32>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT ended_at, MAX(end_page - start_page, 0) FROM sessions WHERE ended_at > ?1")?;
+        let mut days = BTreeMap::new();
+        for row in statement.query_map(params![since], |row| Ok((row.get::<_, DateTime<Utc>>(0)?, row.get::<_, u32>(1)?)))? {
+            let (ended_at, pages) = row?;
+            *days.entry(ended_at.with_timezone(&Local).date_naive()).or_insert(0) += pages;
+        }
+        Ok(days)
+    }
+}
+
+/// `history export`: write recorded sessions to stdout as CSV or JSON, optionally only those
+/// since a local date.
+pub fn export(config: &Config, format: &str, since: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = config.history_db.as_deref().ok_or("history_db is not set in the config, so no history has been recorded")?;
+    let since = match since {
+        Some(date) => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("--since '{}' is not YYYY-MM-DD", date))?;
+            Local.from_local_datetime(&date.and_time(Default::default())).earliest().ok_or("--since is not a valid local date")?.with_timezone(&Utc)
+        }
+        None => DateTime::<Utc>::MIN_UTC,
+    };
+    let history = History::open(path)?;
+    let sessions = history.since(since)?;
+
+    let mut out = io::stdout().lock();
+    match format {
+        "json" => {
+            serde_json::to_writer_pretty(&mut out, &sessions)?;
+            writeln!(out)?;
+        }
+        "csv" => {
+            writeln!(out, "{}", COLUMNS.replace(' ', "").split(',').chain(["pages_read", "minutes"]).collect::<Vec<_>>().join(","))?;
+            for s in &sessions {
+                let fields = [
+                    s.book_id.clone(),
+                    s.book_title.clone().unwrap_or_default(),
+                    s.book_number.clone().unwrap_or_default(),
+                    s.series_id.clone(),
+                    s.series_title.clone(),
+                    s.library.clone().unwrap_or_default(),
+                    s.cover_url.clone().unwrap_or_default(),
+                    s.started_at.to_rfc3339(),
+                    s.ended_at.to_rfc3339(),
+                    s.start_page.to_string(),
+                    s.end_page.to_string(),
+                    s.finished.to_string(),
+                    s.pages_read().to_string(),
+                    s.duration().num_minutes().to_string(),
+                ];
+                writeln!(out, "{}", fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","))?;
+            }
+        }
+        other => return Err(format!("Unknown export format '{}', expected csv or json", other).into()),
+    }
+    Ok(())
+}
+
+// Quote fields containing separators, quotes or line breaks (RFC 4180)
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+    Ok(Session {
+        book_id: row.get(0)?,
+        book_title: row.get(1)?,
+        book_number: row.get(2)?,
+        series_id: row.get(3)?,
+        series_title: row.get(4)?,
+        library: row.get(5)?,
+        cover_url: row.get(6)?,
+        started_at: row.get(7)?,
+        ended_at: row.get(8)?,
+        start_page: row.get(9)?,
+        end_page: row.get(10)?,
+        finished: row.get(11)?,
+        media_profile: None,
+    })
+}
@@ -0,0 +1,80 @@
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::{Config, Presence};
+
+/// Runs the `on_start` / `on_update` / `on_stop` commands. Each command goes through the
+/// system shell and receives the event payload twice: as JSON on stdin and as
+/// `KOMGA_RPC_<FIELD>` environment variables (KOMGA_RPC_SERIES, KOMGA_RPC_PAGE, ...).
+pub struct CommandHooks {
+    on_start: Option<String>,
+    on_update: Option<String>,
+    on_stop: Option<String>,
+}
+
+impl CommandHooks {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if config.on_start.is_none() && config.on_update.is_none() && config.on_stop.is_none() {
+            return None;
+        }
+        Some(CommandHooks {
+            on_start: config.on_start.clone(),
+            on_update: config.on_update.clone(),
+            on_stop: config.on_stop.clone(),
+        })
+    }
+
+    pub fn run(&self, event: &str, presence: Option<&Presence>) {
+        let command = match event {
+            "started" => &self.on_start,
+            "updated" => &self.on_update,
+            "stopped" => &self.on_stop,
+            _ => return,
+        };
+        if let Some(command) = command {
+            tokio::spawn(run_command(command.clone(), event.to_string(), crate::event_payload(event, presence)));
+        }
+    }
+}
+
+async fn run_command(command: String, event: String, payload: serde_json::Value) {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C").arg(&command);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(&command);
+        process
+    };
+    if let Some(fields) = payload.as_object() {
+        for (key, value) in fields {
+            let value = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            process.env(format!("KOMGA_RPC_{}", key.to_uppercase()), value);
+        }
+    }
+    process.stdin(Stdio::piped()).kill_on_drop(true);
+
+    let mut child = match process.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(%event, %command, error = %e, "Failed to start hook command");
+            return;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores stdin may exit before reading it; that's fine
+        let _ = stdin.write_all(payload.to_string().as_bytes()).await;
+    }
+    match child.wait().await {
+        Ok(status) if status.success() => debug!(%event, %command, "Hook command finished"),
+        Ok(status) => warn!(%event, %command, %status, "Hook command failed"),
+        Err(e) => warn!(%event, %command, error = %e, "Hook command failed"),
+    }
+}
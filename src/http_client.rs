@@ -0,0 +1,87 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Client;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Config;
+
+// Idle connections outlive the gap between full checks, so each poll reuses one instead of
+// paying for a new TCP and TLS handshake
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+// Keeps NAT and proxies from silently dropping a pooled connection between polls
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Which addresses a lookup may return, for `ip_family`.
+#[derive(Clone, Copy)]
+enum Family {
+    Only4,
+    Only6,
+    Prefer4,
+    Prefer6,
+}
+
+impl Family {
+    fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match value.to_lowercase().as_str() {
+            "ipv4" => Ok(Family::Only4),
+            "ipv6" => Ok(Family::Only6),
+            "prefer_ipv4" => Ok(Family::Prefer4),
+            "prefer_ipv6" => Ok(Family::Prefer6),
+            other => Err(format!("ip_family must be \"ipv4\", \"ipv6\", \"prefer_ipv4\" or \"prefer_ipv6\", not \"{}\"", other).into()),
+        }
+    }
+}
+
+// The system resolver, with its answers filtered or reordered by family. The connector tries the
+// first address's family first and falls back to the other, so ordering is enough for a preference.
+struct FamilyResolver(Family);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let mut addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            match family {
+                Family::Only4 => addrs.retain(|addr| addr.is_ipv4()),
+                Family::Only6 => addrs.retain(|addr| addr.is_ipv6()),
+                Family::Prefer4 => addrs.sort_by_key(|addr| addr.is_ipv6()),
+                Family::Prefer6 => addrs.sort_by_key(|addr| addr.is_ipv4()),
+            }
+            if addrs.is_empty() {
+                return Err(format!("{} has no address of the family ip_family asks for", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The HTTP client for everything the polling loop sends. It speaks HTTP/2 where the server
+/// offers it over TLS, takes gzip or brotli responses, and keeps connections alive across the
+/// polling interval. Names resolve as `ip_family` says, and komga_url's host goes straight to
+/// `komga_address` when set, for split-horizon DNS or Tailscale setups where the system resolver
+/// gives an address that doesn't work from here.
+pub fn build(config: &Config) -> Result<Client, Box<dyn std::error::Error>> {
+    let mut builder = Client::builder()
+        .gzip(true)
+        .brotli(true)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(KEEP_ALIVE)
+        .http2_keep_alive_interval(KEEP_ALIVE)
+        .http2_keep_alive_while_idle(true);
+    if let Some(ref family) = config.ip_family {
+        builder = builder.dns_resolver(Arc::new(FamilyResolver(Family::parse(family)?)));
+    }
+    if let Some(ref address) = config.komga_address {
+        let ip: IpAddr = address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map_err(|_| format!("komga_address must be an IP address, not \"{}\"", address))?;
+        let url = url::Url::parse(&config.komga_url)?;
+        let host = url.host_str().ok_or("komga_url has no host for komga_address to stand in for")?;
+        // Port 0 keeps the one in komga_url (or the scheme's default)
+        builder = builder.resolve(host, SocketAddr::new(ip, 0));
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,225 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::exit;
+
+// Name of the Start Menu shortcut and the logon task
+const APP_NAME: &str = "Komga-RPC";
+// launchd label and systemd unit name
+const SERVICE_NAME: &str = "com.komga.discord-rpc";
+const UNIT_NAME: &str = "komga-discord-rpc.service";
+
+/// `install`: copy this binary to the per-user program directory, say how to get it on PATH, and
+/// have it start in the background with `config_file` whenever the user logs in: a logon task on
+/// Windows, a LaunchAgent on macOS and a systemd user service elsewhere.
+pub fn run(config_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = std::path::absolute(config_file)?;
+    let bin_dir = if cfg!(windows) { windows_program_dir()? } else { home()?.join(".local").join("bin") };
+    let exe = copy_binary(&bin_dir)?;
+    println!("Installed {}.", exe.display());
+    path_hint(&bin_dir);
+
+    if cfg!(windows) {
+        windows_shortcut(&exe, &config_file)?;
+        windows_logon_task(&exe, &config_file)?;
+        println!("Added a Start Menu shortcut and a logon task named {}; it starts at your next logon.", APP_NAME);
+    } else if cfg!(target_os = "macos") {
+        let plist = launch_agent(&exe, &config_file)?;
+        println!("Registered the LaunchAgent {}; it's running now and starts at every login.", plist.display());
+    } else {
+        let unit = systemd_unit(&exe, &config_file)?;
+        println!("Enabled the systemd user service {}; it's running now and starts at every login.", unit.display());
+        println!("Follow it with: journalctl --user -u {} -f", UNIT_NAME);
+    }
+    Ok(())
+}
+
+fn home() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    env::var_os("HOME").map(PathBuf::from).ok_or_else(|| "HOME is not set".into())
+}
+
+// Say how to run the binary by name, unless `bin_dir` is already on PATH
+fn path_hint(bin_dir: &Path) {
+    let on_path = env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| dir == bin_dir));
+    if on_path {
+        return;
+    }
+    if cfg!(windows) {
+        println!("To run komga-discord-rpc by name, add {} to your user Path under Settings > System > About > Advanced system settings > Environment Variables.", bin_dir.display());
+    } else {
+        println!("To run komga-discord-rpc by name, add this to your shell profile (~/.profile, ~/.zshrc, ...):");
+        println!("  export PATH=\"{}:$PATH\"", bin_dir.display());
+    }
+}
+
+// A KeepAlive LaunchAgent, like the com.komga.discord-rpc.plist example, loaded right away
+fn launch_agent(exe: &Path, config_file: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let home = home()?;
+    let escape = |path: &Path| path.display().to_string().replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let logs = home.join("Library").join("Logs");
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{exe}</string>
+        <string>-c</string>
+        <string>{config}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{stdout}</string>
+    <key>StandardErrorPath</key>
+    <string>{stderr}</string>
+    <key>WorkingDirectory</key>
+    <string>{dir}</string>
+</dict>
+</plist>
+"#,
+        label = SERVICE_NAME,
+        exe = escape(exe),
+        config = escape(config_file),
+        stdout = escape(&logs.join("komga-discord-rpc.log")),
+        stderr = escape(&logs.join("komga-discord-rpc-error.log")),
+        dir = escape(Path::new(&working_dir(config_file))),
+    );
+    let path = home.join("Library").join("LaunchAgents").join(format!("{}.plist", SERVICE_NAME));
+    fs::create_dir_all(path.parent().unwrap_or(&home))?;
+    fs::write(&path, plist)?;
+    // Unloading first picks up a changed plist when installing again; it fails harmlessly otherwise
+    let _ = Command::new("launchctl").arg("unload").arg(&path).output();
+    check(Command::new("launchctl").arg("load").arg("-w").arg(&path))?;
+    Ok(path)
+}
+
+// A systemd user unit, enabled and started
+fn systemd_unit(exe: &Path, config_file: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let config_home = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from).map_or_else(|| home().map(|home| home.join(".config")), Ok)?;
+    let quote = |path: &Path| format!("\"{}\"", path.display().to_string().replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%"));
+    let unit = format!(
+        "[Unit]\n\
+         Description=Komga Discord Rich Presence\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={} -c {}\n\
+         WorkingDirectory={}\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         RestartPreventExitStatus={}\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        quote(exe),
+        quote(config_file),
+        // WorkingDirectory takes the path as it is, spaces and all
+        working_dir(config_file),
+        // Restarting won't fix a broken config or a revoked API key
+        [exit::USAGE, exit::NO_PERMISSION, exit::CONFIG].map(|code| code.to_string()).join(" "),
+    );
+    let path = config_home.join("systemd").join("user").join(UNIT_NAME);
+    fs::create_dir_all(path.parent().unwrap_or(&config_home))?;
+    fs::write(&path, unit)?;
+    check(Command::new("systemctl").args(["--user", "daemon-reload"]))?;
+    check(Command::new("systemctl").args(["--user", "enable", UNIT_NAME]))?;
+    // Restart rather than start, so installing again swaps in the new binary
+    check(Command::new("systemctl").args(["--user", "restart", UNIT_NAME]))?;
+    Ok(path)
+}
+
+// Run a service manager command, turning a failure into its stderr
+fn check(command: &mut Command) -> Result<(), Box<dyn std::error::Error>> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(())
+}
+
+/// Copy the running binary to `dir`, unless it's already running from there, and return the copy.
+pub fn copy_binary(dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let exe = env::current_exe()?;
+    let target = dir.join(exe.file_name().ok_or("the running binary has no file name")?);
+    if target != exe {
+        fs::create_dir_all(dir)?;
+        // Copy beside it and rename over, so a half-written copy never takes its place. Elsewhere a
+        // running installed copy keeps the file it started from; Windows won't replace a running
+        // program but will move one, so there it goes aside to .old until the next install.
+        let mut partial = target.clone().into_os_string();
+        partial.push(".new");
+        fs::copy(&exe, &partial)?;
+        if cfg!(windows) && target.exists() {
+            let mut old = target.clone().into_os_string();
+            old.push(".old");
+            // Still running from the install before, perhaps; then the move below says so
+            let _ = fs::remove_file(&old);
+            fs::rename(&target, &old).map_err(|e| format!("Couldn't move the installed {} aside to replace it (is an older copy still running?): {}", target.display(), e))?;
+        }
+        fs::rename(&partial, &target).map_err(|e| format!("Couldn't put the new binary in place at {}: {}", target.display(), e))?;
+    }
+    Ok(target)
+}
+
+/// Where programs installed for the current user go on Windows: %LOCALAPPDATA%\Programs\Komga-RPC.
+pub fn windows_program_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let local = env::var_os("LOCALAPPDATA").ok_or("LOCALAPPDATA is not set")?;
+    Ok(PathBuf::from(local).join("Programs").join(APP_NAME))
+}
+
+/// Start Menu shortcut running `exe` with `config_file`, made through the WScript.Shell COM object.
+pub fn windows_shortcut(exe: &Path, config_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let appdata = env::var_os("APPDATA").ok_or("APPDATA is not set")?;
+    let shortcut = PathBuf::from(appdata).join(r"Microsoft\Windows\Start Menu\Programs").join(format!("{}.lnk", APP_NAME));
+    powershell(
+        "$s = (New-Object -ComObject WScript.Shell).CreateShortcut($env:KOMGA_RPC_SHORTCUT); \
+         $s.TargetPath = $env:KOMGA_RPC_EXE; $s.Arguments = $env:KOMGA_RPC_ARGS; \
+         $s.WorkingDirectory = $env:KOMGA_RPC_DIR; $s.Save()",
+        &[
+            ("KOMGA_RPC_SHORTCUT", shortcut.display().to_string()),
+            ("KOMGA_RPC_EXE", exe.display().to_string()),
+            ("KOMGA_RPC_ARGS", format!("-c \"{}\"", config_file.display())),
+            ("KOMGA_RPC_DIR", working_dir(config_file)),
+        ],
+    )
+}
+
+/// Scheduled task starting `exe` with `config_file` whenever the current user logs on. It goes
+/// through a hidden PowerShell, since the binary itself would open a console window.
+pub fn windows_logon_task(exe: &Path, config_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let quote = |path: &Path| format!("'{}'", path.display().to_string().replace('\'', "''"));
+    powershell(
+        "$action = New-ScheduledTaskAction -Execute 'powershell.exe' -Argument $env:KOMGA_RPC_ARGS -WorkingDirectory $env:KOMGA_RPC_DIR; \
+         $trigger = New-ScheduledTaskTrigger -AtLogOn -User $env:USERNAME; \
+         $settings = New-ScheduledTaskSettingsSet -AllowStartIfOnBatteries -DontStopIfGoingOnBatteries -ExecutionTimeLimit 0; \
+         Register-ScheduledTask -TaskName $env:KOMGA_RPC_TASK -Action $action -Trigger $trigger -Settings $settings -Force | Out-Null",
+        &[
+            ("KOMGA_RPC_TASK", APP_NAME.to_string()),
+            ("KOMGA_RPC_ARGS", format!("-NoProfile -WindowStyle Hidden -Command \"& {} -c {}\"", quote(exe), quote(config_file))),
+            ("KOMGA_RPC_DIR", working_dir(config_file)),
+        ],
+    )
+}
+
+// Relative log_file, state_file, history_db and metadata_cache_db paths keep resolving next to the config
+fn working_dir(config_file: &Path) -> String {
+    config_file.parent().map(|dir| dir.display().to_string()).unwrap_or_default()
+}
+
+// Values go through the environment so paths with spaces or quotes need no escaping in the script
+fn powershell(script: &str, vars: &[(&str, String)]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+    for (name, value) in vars {
+        command.env(name, value);
+    }
+    check(&mut command)
+}
@@ -0,0 +1,93 @@
+use reqwest::Client;
+use tracing::warn;
+
+use crate::hardcover::Hardcover;
+use crate::history::History;
+use crate::hooks::CommandHooks;
+use crate::mqtt::MqttPublisher;
+use crate::scrobble::{MappingCache, Scrobbler};
+use crate::sessions::{SessionTracker, SharedSessions};
+use crate::state::StateFile;
+use crate::webhooks::Webhooks;
+use crate::{Config, Presence};
+
+/// Everything outside Discord that reacts to reading state changes.
+pub struct Integrations {
+    sessions: SharedSessions,
+    mqtt: Option<MqttPublisher>,
+    webhooks: Option<Webhooks>,
+    hooks: Option<CommandHooks>,
+    scrobbler: Option<Scrobbler>,
+    hardcover: Option<Hardcover>,
+    daily_summary: bool,
+}
+
+impl Integrations {
+    pub fn from_config(config: &Config, client: &Client) -> Result<Self, Box<dyn std::error::Error>> {
+        let tracker_cache = MappingCache::load(config);
+        let history = config.history_db.as_deref().map(History::open).transpose()?;
+        if let Some(ref reset) = config.daily_goal_reset {
+            chrono::NaiveTime::parse_from_str(reset, "%H:%M").map_err(|_| format!("daily_goal_reset '{}' is not HH:MM", reset))?;
+        }
+        if history.is_none() && config.show_streak.is_some() {
+            warn!("show_streak needs history_db to be set; no streak will be shown");
+        }
+        Ok(Integrations {
+            sessions: SessionTracker::new(history, StateFile::from_config(config)),
+            mqtt: MqttPublisher::spawn(config),
+            webhooks: Webhooks::from_config(config, client),
+            hooks: CommandHooks::from_config(config),
+            scrobbler: Scrobbler::from_config(config, client, tracker_cache.clone())?,
+            hardcover: Hardcover::from_config(config, client, tracker_cache),
+            daily_summary: config.daily_summary_webhook.is_some(),
+        })
+    }
+
+    pub fn sessions(&self) -> SharedSessions {
+        self.sessions.clone()
+    }
+
+    /// Whether anything listens for "finished", which costs an extra Komga request per book change.
+    pub fn wants_finished(&self) -> bool {
+        // Session tracking marks finished books too, but only the daily summary reads that
+        self.webhooks.is_some() || self.scrobbler.is_some() || self.hardcover.is_some() || self.daily_summary
+    }
+
+    /// `finished` is the previous presence when its book was completed since the last poll.
+    pub fn reading_changed(&self, previous: Option<&Presence>, current: Option<&Presence>, finished: Option<&Presence>) {
+        self.sessions.lock().unwrap().observe(current, finished);
+        if let Some(ref mqtt) = self.mqtt {
+            mqtt.publish_change(previous, current);
+        }
+        let event = crate::reading_event(previous, current);
+        if let (Some(hooks), Some(event)) = (&self.hooks, event) {
+            hooks.run(event, if event == "stopped" { previous } else { current });
+        }
+        if let Some(ref scrobbler) = self.scrobbler {
+            if let Some(book) = finished {
+                scrobbler.finished(book);
+            }
+            if let (Some(book), Some(_)) = (current, event) {
+                scrobbler.progress(book);
+            }
+        }
+        if let Some(ref hardcover) = self.hardcover {
+            if let Some(book) = finished {
+                hardcover.finished(book);
+            }
+            if let (Some(book), Some(_)) = (current, event) {
+                hardcover.reading(book);
+            }
+        }
+        if let Some(ref webhooks) = self.webhooks {
+            if let Some(book) = finished {
+                webhooks.send("finished", Some(book));
+            }
+            match event {
+                Some("started") => webhooks.send("started", current),
+                Some("stopped") => webhooks.send("stopped", previous),
+                _ => {}
+            }
+        }
+    }
+}
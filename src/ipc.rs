@@ -0,0 +1,384 @@
+use discord_rich_presence::activity::Activity;
+use tracing::{debug, info};
+use serde_json::{json, Value};
+#[cfg(unix)]
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::Config;
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const OP_CLOSE: u32 = 2;
+
+// Environment keys that may point at the directory holding the Discord socket
+#[cfg(unix)]
+const ENV_KEYS: [&str; 4] = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"];
+
+// Locations relative to the runtime dir used by sandboxed Discord builds (Flatpak, Snap)
+#[cfg(unix)]
+const APP_SUBPATHS: [&str; 9] = [
+    "",
+    "app/com.discordapp.Discord/",
+    "app/com.discordapp.DiscordCanary/",
+    "app/dev.vencord.Vesktop/",
+    ".flatpak/com.discordapp.Discord/xdg-run/",
+    ".flatpak/dev.vencord.Vesktop/xdg-run/",
+    "snap.discord/",
+    "snap.discord-canary/",
+    "snap.discord-ptb/",
+];
+
+static NONCE: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(unix)]
+type IpcStream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type IpcStream = std::fs::File;
+
+// A second handle on the IPC socket in use, for the panic hook
+static PANIC_STREAM: Mutex<Option<IpcStream>> = Mutex::new(None);
+
+const DEFAULT_WS_URL: &str = "ws://127.0.0.1:6463";
+
+/// How presence reaches Discord: the native IPC socket, or the RPC websocket exposed by
+/// arRPC-style bridges for users on the Discord web client.
+enum Transport {
+    Ipc(IpcStream),
+    WebSocket(Box<WebSocket<MaybeTlsStream<TcpStream>>>),
+}
+
+/// Discord RPC client that, unlike `DiscordIpcClient`, can be pointed at an explicit socket,
+/// also looks in the Flatpak/Snap locations when discovering one, and can talk to a websocket
+/// bridge instead.
+pub struct DiscordClient {
+    client_id: String,
+    ipc_path: Option<String>,
+    ws_url: Option<String>,
+    transport: Option<Transport>,
+}
+
+impl DiscordClient {
+    pub fn new(client_id: &str, ipc_path: Option<String>) -> Self {
+        DiscordClient {
+            client_id: client_id.to_string(),
+            ipc_path,
+            ws_url: None,
+            transport: None,
+        }
+    }
+
+    pub fn from_config(config: &Config, client_id: &str) -> Self {
+        let mut client = DiscordClient::new(client_id, config.discord_ipc_path.clone());
+        if config.discord_transport.as_deref().is_some_and(|t| t.eq_ignore_ascii_case("websocket")) {
+            client.ws_url = Some(config.discord_ws_url.clone().unwrap_or_else(|| DEFAULT_WS_URL.to_string()));
+        }
+        client
+    }
+
+    #[tracing::instrument(name = "discord_ipc", skip_all)]
+    pub fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ws_url) = self.ws_url.clone() {
+            return self.connect_websocket(&ws_url);
+        }
+
+        let candidates = candidate_paths(self.ipc_path.as_deref());
+        if candidates.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Could not find a Discord IPC socket").into());
+        }
+
+        let mut last_err = None;
+        for path in candidates {
+            debug!(path = %path.display(), "Trying Discord IPC socket");
+            match open_stream(&path) {
+                Ok(stream) => {
+                    info!(path = %path.display(), "Connected to Discord IPC socket");
+                    self.transport = Some(Transport::Ipc(stream));
+                    last_err = None;
+                    break;
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        if let Some(e) = last_err {
+            return Err(e.into());
+        }
+
+        self.send(OP_HANDSHAKE, &json!({ "v": 1, "client_id": self.client_id }))?;
+        let (op, data) = self.recv()?;
+        if op == OP_CLOSE {
+            self.transport = None;
+            let message = data.get("message").and_then(|v| v.as_str()).unwrap_or("handshake rejected");
+            return Err(format!("Discord rejected the IPC handshake: {}", message).into());
+        }
+        if let Some(Transport::Ipc(ref stream)) = self.transport {
+            *PANIC_STREAM.lock().unwrap() = stream.try_clone().ok();
+        }
+        Ok(())
+    }
+
+    fn connect_websocket(&mut self, ws_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("{}/?v=1&client_id={}&encoding=json", ws_url.trim_end_matches('/'), self.client_id);
+        debug!(%ws_url, "Connecting to Discord RPC websocket");
+        let (socket, _) = tungstenite::connect(url.as_str()).map_err(ws_error)?;
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+            stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+        }
+        self.transport = Some(Transport::WebSocket(Box::new(socket)));
+        // The bridge greets us with a READY dispatch, the websocket equivalent of the handshake reply
+        let (_, data) = self.recv()?;
+        if data.get("evt").and_then(|v| v.as_str()) != Some("READY") {
+            self.transport = None;
+            return Err(format!("Unexpected greeting from Discord RPC websocket: {}", data).into());
+        }
+        info!(%ws_url, "Connected to Discord RPC websocket");
+        Ok(())
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Reconnects under a different application ID, which changes the activity header Discord shows.
+    pub fn switch_client_id(&mut self, client_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.transport.is_some() {
+            // Clear first so the old application's card doesn't linger until Discord notices the disconnect
+            let _ = self.clear_activity();
+            let _ = self.close();
+        }
+        self.client_id = client_id.to_string();
+        self.connect()
+    }
+
+    #[tracing::instrument(name = "discord_ipc", skip_all)]
+    pub fn set_activity(&mut self, activity: Activity) -> Result<(), Box<dyn std::error::Error>> {
+        self.command(json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": std::process::id(), "activity": activity },
+            "nonce": next_nonce(),
+        }))
+    }
+
+    #[tracing::instrument(name = "discord_ipc", skip_all)]
+    pub fn clear_activity(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.command(json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": std::process::id(), "activity": Value::Null },
+            "nonce": next_nonce(),
+        }))
+    }
+
+    pub fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let result = match self.transport.as_mut() {
+            Some(Transport::WebSocket(socket)) => socket.close(None).map_err(ws_error),
+            _ => self.send(OP_CLOSE, &json!({})),
+        };
+        if matches!(self.transport, Some(Transport::Ipc(_))) {
+            *PANIC_STREAM.lock().unwrap() = None;
+        }
+        self.transport = None;
+        result.map_err(|e| e.into())
+    }
+
+    fn command(&mut self, payload: Value) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(OP_FRAME, &payload)?;
+        // Drain the reply so the socket buffer never fills up, and surface command errors
+        let (op, data) = self.recv()?;
+        if op == OP_CLOSE {
+            self.transport = None;
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Discord closed the IPC connection").into());
+        }
+        if data.get("evt").and_then(|v| v.as_str()) == Some("ERROR") {
+            let message = data.get("data").and_then(|d| d.get("message")).and_then(|v| v.as_str()).unwrap_or("unknown error");
+            return Err(format!("Discord returned an error: {}", message).into());
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, op: u32, payload: &Value) -> io::Result<()> {
+        let stream = match self.transport.as_mut().ok_or_else(not_connected)? {
+            Transport::Ipc(stream) => stream,
+            // Websocket frames carry the JSON alone; there is no opcode header
+            Transport::WebSocket(socket) => return socket.send(Message::text(payload.to_string())).map_err(ws_error),
+        };
+        write_frame(stream, op, payload)
+    }
+
+    fn recv(&mut self) -> io::Result<(u32, Value)> {
+        let stream = match self.transport.as_mut().ok_or_else(not_connected)? {
+            Transport::Ipc(stream) => stream,
+            Transport::WebSocket(socket) => return recv_websocket(socket),
+        };
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header)?;
+        let op = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body)?;
+        let data = serde_json::from_slice(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok((op, data))
+    }
+}
+
+// Best effort on the way out, so Discord doesn't keep showing the last activity: this also runs
+// while a panic in the polling loop unwinds
+impl Drop for DiscordClient {
+    fn drop(&mut self) {
+        if self.transport.is_some() {
+            let _ = self.clear_activity();
+            let _ = self.close();
+        }
+    }
+}
+
+/// Clear the activity and close the IPC connection when any thread panics, before anything else
+/// happens, so a crash doesn't leave a frozen "reading" status behind even if unwinding never gets
+/// as far as dropping the client. Only the first panic finds the stream to take.
+///
+/// The hook writes on its own handle without waiting for the client, so if the panic strikes
+/// mid-`send` its frames may land inside one already half written. Discord then drops the
+/// connection over the garbled frame, which clears the activity all the same.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // try_lock, since the panic may have struck while the lock was held, or a second panic
+        // may be running this hook already
+        let stream = PANIC_STREAM.try_lock().ok().and_then(|mut stream| stream.take());
+        if let Some(mut stream) = stream {
+            let clear = json!({
+                "cmd": "SET_ACTIVITY",
+                "args": { "pid": std::process::id(), "activity": Value::Null },
+                "nonce": next_nonce(),
+            });
+            let _ = write_frame(&mut stream, OP_FRAME, &clear);
+            let _ = write_frame(&mut stream, OP_CLOSE, &json!({}));
+        }
+        previous(info);
+    }));
+}
+
+fn write_frame(stream: &mut IpcStream, op: u32, payload: &Value) -> io::Result<()> {
+    let body = payload.to_string();
+    let mut frame = Vec::with_capacity(8 + body.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(body.as_bytes());
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+fn recv_websocket(socket: &mut WebSocket<MaybeTlsStream<TcpStream>>) -> io::Result<(u32, Value)> {
+    loop {
+        match socket.read().map_err(ws_error)? {
+            Message::Text(text) => {
+                let data = serde_json::from_str(text.as_str()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                return Ok((OP_FRAME, data));
+            }
+            Message::Close(_) => return Ok((OP_CLOSE, json!({}))),
+            // Pings are answered by tungstenite itself
+            _ => continue,
+        }
+    }
+}
+
+// Map websocket failures onto io errors so a dropped bridge triggers the same reconnect path as a closed pipe
+fn ws_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::Error::new(io::ErrorKind::BrokenPipe, "Discord RPC websocket closed")
+        }
+        other => io::Error::other(other.to_string()),
+    }
+}
+
+fn not_connected() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "Not connected to Discord")
+}
+
+fn next_nonce() -> String {
+    format!("{}-{}", std::process::id(), NONCE.fetch_add(1, Ordering::Relaxed))
+}
+
+// A Discord that stops answering mid-frame fails the read after these timeouts, and the client
+// reconnects
+#[cfg(unix)]
+fn open_stream(path: &Path) -> io::Result<IpcStream> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+    Ok(stream)
+}
+
+// The named pipe is opened for blocking I/O, which has no timeout: a Discord that hangs without
+// closing the pipe blocks a read until it's killed. Bounding that needs overlapped I/O, which
+// std::fs::File doesn't offer. Discord exiting does close the pipe, so the common case fails fast.
+#[cfg(windows)]
+fn open_stream(path: &Path) -> io::Result<IpcStream> {
+    std::fs::OpenOptions::new().read(true).write(true).open(path)
+}
+
+/// Sockets to try, in order. A configured `discord_ipc_path` may name the socket itself or a
+/// directory to search; otherwise the usual runtime directories are scanned.
+fn candidate_paths(override_path: Option<&str>) -> Vec<PathBuf> {
+    if let Some(path) = override_path {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            return (0..10)
+                .map(|i| path.join(format!("discord-ipc-{}", i)))
+                .filter(|p| p.exists())
+                .collect();
+        }
+        return vec![path];
+    }
+    discover_ipc_paths()
+}
+
+#[cfg(unix)]
+fn discover_ipc_paths() -> Vec<PathBuf> {
+    let mut bases: Vec<PathBuf> = Vec::new();
+    for key in ENV_KEYS {
+        if let Ok(val) = env::var(key) {
+            let mut base = PathBuf::from(val);
+            // Inside a snap, XDG_RUNTIME_DIR points at /run/user/<uid>/snap.<name>
+            if key == "XDG_RUNTIME_DIR" && env::var("SNAP").is_ok() {
+                if let Some(parent) = base.parent() {
+                    base = parent.to_path_buf();
+                }
+            }
+            if !bases.contains(&base) {
+                bases.push(base);
+            }
+        }
+    }
+    let tmp = PathBuf::from("/tmp");
+    if !bases.contains(&tmp) {
+        bases.push(tmp);
+    }
+
+    let mut found = Vec::new();
+    for base in bases.iter().filter(|b| b.is_dir()) {
+        for i in 0..10 {
+            for subpath in APP_SUBPATHS {
+                let path = base.join(subpath).join(format!("discord-ipc-{}", i));
+                if path.exists() && !found.contains(&path) {
+                    found.push(path);
+                }
+            }
+        }
+    }
+    found
+}
+
+#[cfg(windows)]
+fn discover_ipc_paths() -> Vec<PathBuf> {
+    (0..10).map(|i| PathBuf::from(format!(r"\\.\pipe\discord-ipc-{}", i))).collect()
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::{Client, Method, StatusCode};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::cover_cache::CoverCache;
+use crate::media_server::{rehost_cover, Reading};
+use crate::numbering::BookNumbers;
+use crate::{locale, stats, timestamps, Config, Presence};
+
+const PLUGIN_NAME: &str = "Komga-RPC";
+// Kavita numbers loose-leaf volumes and volume-less chapters -100000, and specials 100000
+const PLACEHOLDER_NUMBER: f64 = 100_000.0;
+
+// Logins by server URL, kept across polls
+static TOKENS: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Kavita REST client. Kavita takes no API key header; the key is traded for a JWT through its
+/// plugin login, which is redone whenever the token expires.
+pub struct Kavita {
+    url: String,
+    api_key: String,
+}
+
+impl Kavita {
+    pub fn new(url: &str, api_key: &str) -> Self {
+        Kavita { url: url.trim_end_matches('/').to_string(), api_key: api_key.to_string() }
+    }
+
+    async fn token(&self, client: &Client) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(token) = TOKENS.lock().unwrap().get(&self.url) {
+            return Ok(token.clone());
+        }
+        let response = client
+            .post(format!("{}/api/Plugin/authenticate", self.url))
+            .query(&[("apiKey", self.api_key.as_str()), ("pluginName", PLUGIN_NAME)])
+            .send()
+            .await?;
+        // Kept as reqwest's error, so a turned-away key counts as auth; the URL carries the key
+        let user: Value = response.error_for_status().map_err(|e| e.without_url())?.json().await?;
+        let token = user["token"].as_str().ok_or("Kavita login returned no token")?.to_string();
+        info!(server = %self.url, user = user["username"].as_str().unwrap_or(""), "Logged in to Kavita");
+        TOKENS.lock().unwrap().insert(self.url.clone(), token.clone());
+        Ok(token)
+    }
+
+    async fn request(&self, client: &Client, method: Method, path: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let url = format!("{}{}", self.url, path);
+        for attempt in 0..2 {
+            let token = self.token(client).await?;
+            let started = Instant::now();
+            let response = client.request(method.clone(), &url).bearer_auth(&token).send().await;
+            stats::record_latency(&stats::endpoint_key(method.as_str(), &url), started.elapsed());
+            let response = response?;
+            if response.status() == StatusCode::UNAUTHORIZED && attempt == 0 {
+                debug!(server = %self.url, "Kavita token expired, logging in again");
+                TOKENS.lock().unwrap().remove(&self.url);
+                continue;
+            }
+            // A second 401 means the fresh login was turned away too
+            return Ok(response.error_for_status()?.json().await?);
+        }
+        unreachable!("the second attempt always returns")
+    }
+
+    /// What should be shown for the chapter most recently read on Kavita, like the Komga
+    /// build_presence. Kavita's "On Deck" shelf has the series being read, most recent first.
+    pub async fn build_presence(
+        &self,
+        client: &Client,
+        config: &Config,
+        imgur_cache: &mut CoverCache,
+        previous: Option<&Presence>,
+    ) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+        let on_deck = self.request(client, Method::POST, "/api/Series/on-deck?libraryId=0&PageNumber=1&PageSize=20").await?;
+        // latestReadDate is in the server's local time, which is fine for ordering
+        let Some(series) = on_deck.as_array().into_iter().flatten().max_by_key(|s| s["latestReadDate"].as_str().unwrap_or("").to_string()) else {
+            info!("No in-progress books found in Kavita");
+            return Ok(None);
+        };
+        let series_id = series["id"].as_u64().ok_or("Kavita series has no ID")?;
+        let chapter = self.request(client, Method::GET, &format!("/api/Reader/continue-point?seriesId={}", series_id)).await?;
+        let chapter_id = chapter["id"].as_u64().ok_or("Kavita returned no chapter to continue")?;
+        let progress = self.request(client, Method::GET, &format!("/api/Reader/get-progress?chapterId={}", chapter_id)).await?;
+
+        // Only show as reading if updated in the last 5 minutes
+        let read_at = progress["lastModifiedUtc"].as_str().and_then(parse_utc);
+        match read_at {
+            Some(updated_at) if timestamps::is_recent(config, updated_at) => {}
+            Some(updated_at) => {
+                info!(%updated_at, "Most recent in-progress book activity is too old, clearing Discord status");
+                return Ok(None);
+            }
+            None => {
+                info!("No valid lastModifiedUtc for the most recent Kavita progress, clearing Discord status");
+                return Ok(None);
+            }
+        }
+
+        let series_title = series["localizedName"].as_str().or(series["name"].as_str()).filter(|s| !s.is_empty()).map(|s| s.to_string()).unwrap_or_else(|| locale::text("untitled", &[]));
+        info!(%series_title, "Resolved series title");
+        let metadata = self.request(client, Method::GET, &format!("/api/Series/metadata?seriesId={}", series_id)).await.unwrap_or_else(|e| {
+            warn!(%series_id, error = %e, "Failed to fetch Kavita series metadata");
+            Value::Null
+        });
+        let names = |field: &str, key: &str| -> Vec<String> {
+            metadata[field].as_array().into_iter().flatten().filter_map(|v| v[key].as_str()).map(|s| s.to_string()).collect()
+        };
+        let volume = match chapter["volumeId"].as_u64() {
+            Some(volume_id) => self.request(client, Method::GET, &format!("/api/Series/volume?volumeId={}", volume_id)).await.ok(),
+            None => None,
+        };
+        let pages = chapter["pages"].as_u64().map(|v| v as u32).filter(|pages| *pages > 0);
+        // Kavita saves how many pages have been read; the one on screen is the next
+        let page = progress["pageNum"].as_u64().map(|read| (read as u32 + 1).min(pages.unwrap_or(u32::MAX)));
+        let (format, media_profile) = media_format(series["format"].as_u64());
+
+        // Kavita doesn't say which language its names are in
+        let alternate_titles = [&series["name"], &series["originalName"], &series["localizedName"]].iter().filter_map(|t| t.as_str()).map(|title| (String::new(), title.to_string())).collect();
+        let reading = Reading {
+            series_id: series_id.to_string(),
+            series_title,
+            alternate_titles,
+            book_id: chapter_id.to_string(),
+            book_title: chapter["titleName"].as_str().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+            numbers: BookNumbers {
+                number: None,
+                volume: volume.as_ref().and_then(|v| real_number(v.get("minNumber").or(v.get("number")))),
+                chapter: real_number(chapter.get("minNumber").or(chapter.get("number"))),
+            },
+            oneshot: false,
+            isbn: chapter["isbn"].as_str().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+            page,
+            pages,
+            percent: page.zip(pages).map(|(page, pages)| page * 100 / pages),
+            percent_only: false,
+            library: series["libraryName"].as_str().map(|s| s.to_string()),
+            formats: vec![format.to_string()],
+            media_profile: Some(media_profile.to_string()),
+            read_at,
+            authors: names("writers", "name"),
+            publisher: names("publishers", "name").into_iter().next(),
+            release_date: None,
+            status: None,
+            read_list: None,
+            buttons: Vec::new(),
+            tags: names("tags", "title").into_iter().chain(names("genres", "title")).collect(),
+            context: serde_json::json!({ "book": chapter, "series": series, "library": series["libraryName"] }),
+        };
+        let hides_cover = reading.hides_cover(config);
+        let Some(mut presence) = reading.into_presence(config, previous) else {
+            return Ok(None);
+        };
+        if presence.large_image.is_none() && !hides_cover {
+            let cover = client
+                .get(format!("{}/api/Image/series-cover", self.url))
+                .query(&[("seriesId", series_id.to_string()), ("apiKey", self.api_key.clone())]);
+            presence.large_image = rehost_cover(client, config, format!("kavita_{}_{}", self.url, series_id), series["coverImage"].as_str().unwrap_or_default(), cover, imgur_cache).await;
+        }
+        Ok(Some(presence))
+    }
+
+    /// Whether the chapter shown in `presence` has been read to its last page.
+    pub async fn completed(&self, client: &Client, presence: &Presence) -> Option<bool> {
+        let progress = self.request(client, Method::GET, &format!("/api/Reader/get-progress?chapterId={}", presence.book_id)).await.ok()?;
+        Some(progress["pageNum"].as_u64()? as u32 >= presence.pages?)
+    }
+}
+
+// Kavita's format as a name exclude_formats can use, and the Komga media profile it reads like
+fn media_format(format: Option<u64>) -> (&'static str, &'static str) {
+    match format {
+        Some(0) => ("image", "DIVINA"),
+        Some(3) => ("epub", "EPUB"),
+        Some(4) => ("pdf", "PDF"),
+        _ => ("archive", "DIVINA"),
+    }
+}
+
+// Kavita's UTC timestamps come back with or without the "Z", depending on how they were loaded
+fn parse_utc(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f").map(|dt| dt.and_utc()))
+        .ok()
+}
+
+// A volume or chapter number, unless it's one of Kavita's placeholders
+fn real_number(value: Option<&Value>) -> Option<String> {
+    let number = match value? {
+        Value::String(s) => s.trim().parse::<f64>().ok()?,
+        Value::Number(n) => n.as_f64()?,
+        _ => return None,
+    };
+    // 12.0 prints as "12"
+    (number.abs() < PLACEHOLDER_NUMBER).then(|| number.to_string())
+}
@@ -0,0 +1,134 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+
+const API_URL: &str = "https://kitsu.app/api/edge";
+const TOKEN_URL: &str = "https://kitsu.app/api/oauth/token";
+const JSON_API: &str = "application/vnd.api+json";
+
+/// Kitsu JSON:API client. Kitsu has no app registration for personal use, so it logs in with
+/// the account's username (or email) and password and keeps the token for the session.
+pub struct Kitsu {
+    username: String,
+    password: String,
+    session: Mutex<Option<(String, String)>>, // access token, user ID
+}
+
+impl Kitsu {
+    pub fn new(username: String, password: String) -> Self {
+        Kitsu { username, password, session: Mutex::new(None) }
+    }
+
+    async fn session(&self, client: &Client) -> Result<(String, String), Box<dyn std::error::Error>> {
+        if let Some(session) = self.session.lock().unwrap().clone() {
+            return Ok(session);
+        }
+        let response = client
+            .post(TOKEN_URL)
+            .json(&json!({ "grant_type": "password", "username": self.username, "password": self.password }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("Kitsu login failed with status: {}", response.status()).into());
+        }
+        let token: Value = response.json().await?;
+        let access_token = token["access_token"].as_str().ok_or("Kitsu login returned no access token")?.to_string();
+
+        let me: Value = client
+            .get(format!("{}/users?filter[self]=true", API_URL))
+            .bearer_auth(&access_token)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let user_id = me["data"][0]["id"].as_str().ok_or("Kitsu did not return the logged-in user")?.to_string();
+        let session = (access_token, user_id);
+        *self.session.lock().unwrap() = Some(session.clone());
+        Ok(session)
+    }
+
+    pub async fn search(&self, client: &Client, title: &str) -> Result<Vec<(String, Vec<String>)>, Box<dyn std::error::Error>> {
+        let response: Value = client
+            .get(format!("{}/manga", API_URL))
+            .query(&[("filter[text]", title), ("page[limit]", "10")])
+            .header("Accept", JSON_API)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let media = response["data"].as_array().cloned().unwrap_or_default();
+        Ok(media
+            .iter()
+            .filter_map(|m| {
+                let id = m["id"].as_str()?.to_string();
+                let attributes = &m["attributes"];
+                let titles = attributes["titles"]
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|titles| titles.values())
+                    .chain(attributes["abbreviatedTitles"].as_array().into_iter().flatten())
+                    .chain(std::iter::once(&attributes["canonicalTitle"]))
+                    .filter_map(|t| t.as_str())
+                    .map(|t| t.to_string())
+                    .collect();
+                Some((id, titles))
+            })
+            .collect())
+    }
+
+    /// Kitsu only counts chapters, so this is always chapter progress.
+    pub async fn update_progress(&self, client: &Client, manga_id: &str, progress: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let (token, user_id) = self.session(client).await?;
+        let manga: Value = client
+            .get(format!("{}/manga/{}", API_URL, manga_id))
+            .header("Accept", JSON_API)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let total = manga["data"]["attributes"]["chapterCount"].as_u64();
+        let entries: Value = client
+            .get(format!("{}/library-entries", API_URL))
+            .query(&[("filter[userId]", user_id.as_str()), ("filter[mangaId]", manga_id)])
+            .bearer_auth(&token)
+            .header("Accept", JSON_API)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let entry = &entries["data"][0];
+        if entry["attributes"]["progress"].as_u64().is_some_and(|current| current >= progress as u64) {
+            return Ok(());
+        }
+        let status = if total.is_some_and(|total| progress as u64 >= total) { "completed" } else { "current" };
+
+        let request = match entry["id"].as_str() {
+            Some(entry_id) => client.patch(format!("{}/library-entries/{}", API_URL, entry_id)).body(
+                json!({
+                    "data": { "type": "libraryEntries", "id": entry_id, "attributes": { "progress": progress, "status": status } }
+                })
+                .to_string(),
+            ),
+            None => client.post(format!("{}/library-entries", API_URL)).body(
+                json!({
+                    "data": {
+                        "type": "libraryEntries",
+                        "attributes": { "progress": progress, "status": status },
+                        "relationships": {
+                            "user": { "data": { "type": "users", "id": user_id } },
+                            "media": { "data": { "type": "manga", "id": manga_id } }
+                        }
+                    }
+                })
+                .to_string(),
+            ),
+        };
+        let response = request.bearer_auth(&token).header("Content-Type", JSON_API).header("Accept", JSON_API).send().await?;
+        if !response.status().is_success() {
+            // An expired token shows up here first; log in again next time
+            *self.session.lock().unwrap() = None;
+            return Err(format!("Kitsu rejected the progress update with status: {}", response.status()).into());
+        }
+        Ok(())
+    }
+}
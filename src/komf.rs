@@ -0,0 +1,96 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::{metadata_cache, stats, Config};
+
+// Komf searches every metadata provider it has, which is slow, so each series is looked up once
+static MATCHES: LazyLock<Mutex<HashMap<String, Option<KomfMatch>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The best match Komf's metadata providers have for a series.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KomfMatch {
+    pub title: String,
+    pub url: Option<String>,
+    pub image_url: Option<String>,
+    pub provider: String,
+}
+
+impl KomfMatch {
+    /// "MANGA_UPDATES" as "Manga Updates", for the link button.
+    pub fn provider_name(&self) -> String {
+        self.provider
+            .split('_')
+            .map(|word| {
+                let word = word.to_lowercase();
+                let mut chars = word.chars();
+                chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Until a provider has matched a series, Komga knows little more than its folder name: no
+/// summary, no authors and no links.
+pub fn is_sparse(series_json: &serde_json::Value) -> bool {
+    let empty = |value: Option<&serde_json::Value>| match value {
+        Some(serde_json::Value::String(s)) => s.is_empty(),
+        Some(serde_json::Value::Array(a)) => a.is_empty(),
+        _ => true,
+    };
+    let metadata = series_json.get("metadata");
+    let books_metadata = series_json.get("booksMetadata");
+    empty(metadata.and_then(|m| m.get("summary")))
+        && empty(books_metadata.and_then(|m| m.get("summary")))
+        && empty(books_metadata.and_then(|m| m.get("authors")))
+        && empty(metadata.and_then(|m| m.get("links")))
+}
+
+/// Komf's top search result for the series, if `komf_url` is set and it found anything. `version`
+/// is the series' lastModified, which a match kept on disk from an earlier run has to have.
+pub async fn lookup(client: &Client, config: &Config, series_id: &str, version: &str, library_id: &str, title: &str) -> Option<KomfMatch> {
+    let komf_url = config.komf_url.as_deref()?.trim_end_matches('/');
+    if let Some(found) = MATCHES.lock().unwrap().get(series_id) {
+        stats::record_cache("Komf match", true);
+        return found.clone();
+    }
+    stats::record_cache("Komf match", false);
+    if let Some(stored) = metadata_cache::get("komf", series_id, Some(version)) {
+        let found: Option<KomfMatch> = serde_json::from_str(&stored).ok()?;
+        MATCHES.lock().unwrap().insert(series_id.to_string(), found.clone());
+        return found;
+    }
+    let url = format!("{}/api/komga/metadata/search", komf_url);
+    let started = Instant::now();
+    let response = client.get(&url).query(&[("name", title), ("seriesId", series_id), ("libraryId", library_id)]).send().await;
+    stats::record_latency(&stats::endpoint_key("GET", &url), started.elapsed());
+    let results: Vec<KomfMatch> = match response.and_then(|r| r.error_for_status()) {
+        Ok(response) => match response.json().await {
+            Ok(results) => results,
+            Err(e) => {
+                warn!(error = %e, "Unexpected Komf search response");
+                return None;
+            }
+        },
+        Err(e) => {
+            // Not cached, so the next poll tries again
+            warn!(error = %e, "Failed to search Komf");
+            return None;
+        }
+    };
+    let found = results.into_iter().next();
+    match found {
+        Some(ref found) => info!(%title, komf_title = %found.title, provider = %found.provider, "Matched series with Komf"),
+        None => debug!(%title, "Komf found no match for series"),
+    }
+    if let Ok(stored) = serde_json::to_string(&found) {
+        metadata_cache::put("komf", series_id, version, &stored);
+    }
+    MATCHES.lock().unwrap().insert(series_id.to_string(), found.clone());
+    found
+}
@@ -0,0 +1,56 @@
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{komga_get, metadata_cache, Config};
+
+// Libraries are hardly ever added or renamed, so the list is only fetched again this often
+const REFRESH_EVERY: Duration = Duration::from_secs(30 * 60);
+
+struct Libraries {
+    names: HashMap<String, String>,
+    fetched_at: Instant,
+}
+
+// Each server's libraries, by its komga_url
+static LIBRARIES: LazyLock<Mutex<HashMap<String, Libraries>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The name of the library `library_id`, from one list of all the server's libraries loaded on the
+/// first check and refreshed every half hour, rather than a request per book. If the list can't
+/// be fetched, the name an earlier run kept in the metadata cache.
+pub async fn name(client: &Client, config: &Config, library_id: &str) -> Option<String> {
+    if library_id.is_empty() {
+        return None;
+    }
+    let stale = LIBRARIES.lock().unwrap().get(&config.komga_url).is_none_or(|libraries| libraries.fetched_at.elapsed() >= REFRESH_EVERY);
+    if stale {
+        refresh(client, config).await;
+    }
+    let found = LIBRARIES.lock().unwrap().get(&config.komga_url).and_then(|libraries| libraries.names.get(library_id).cloned());
+    found.or_else(|| metadata_cache::get("library", library_id, None))
+}
+
+async fn refresh(client: &Client, config: &Config) {
+    let url = format!("{}/api/v1/libraries", config.komga_url);
+    let fetched = async { Ok::<Vec<serde_json::Value>, Box<dyn std::error::Error>>(komga_get(client, config, &url).await?.error_for_status()?.json().await?) };
+    let libraries = match fetched.await {
+        Ok(libraries) => libraries,
+        Err(e) => {
+            // Tried again on the next check
+            warn!(error = %e, "Failed to fetch the library list");
+            return;
+        }
+    };
+    let mut names = HashMap::new();
+    for library in &libraries {
+        let (Some(id), Some(name)) = (library.get("id").and_then(|v| v.as_str()), library.get("name").and_then(|v| v.as_str())) else {
+            continue;
+        };
+        metadata_cache::put("library", id, library.get("lastModified").and_then(|v| v.as_str()).unwrap_or_default(), name);
+        names.insert(id.to_string(), name.to_string());
+    }
+    info!(server = %config.komga_url, libraries = names.len(), "Loaded the library list");
+    LIBRARIES.lock().unwrap().insert(config.komga_url.clone(), Libraries { names, fetched_at: Instant::now() });
+}
@@ -0,0 +1,95 @@
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::fmt::Display;
+use std::sync::{LazyLock, OnceLock};
+
+use crate::Config;
+
+// The message files under locales/, built in so there's nothing to install alongside the binary
+const LANGUAGES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+    ("es", include_str!("../locales/es.ftl")),
+    ("fr", include_str!("../locales/fr.ftl")),
+];
+
+static ENGLISH: LazyLock<FluentBundle<FluentResource>> = LazyLock::new(|| bundle(LANGUAGES[0].0, LANGUAGES[0].1));
+static CHOSEN: OnceLock<FluentBundle<FluentResource>> = OnceLock::new();
+
+/// Pick the language the activity is shown in (`language`). English until this is called.
+pub fn init(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(ref language) = config.language else {
+        return Ok(());
+    };
+    // "de-AT" or "de_AT.UTF-8" find "de"
+    let base = language.split(['-', '_', '.']).next().unwrap_or_default().to_lowercase();
+    let Some((name, messages)) = LANGUAGES.iter().find(|(name, _)| *name == base) else {
+        let known: Vec<&str> = LANGUAGES.iter().map(|(name, _)| *name).collect();
+        return Err(format!("Unknown language '{}', expected one of {}", language, known.join(", ")).into());
+    };
+    let _ = CHOSEN.set(bundle(name, messages));
+    Ok(())
+}
+
+/// The message `id` in the chosen language (or English, if it has no translation), with its
+/// variables filled in from `args`.
+pub fn text(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    CHOSEN.get().into_iter().chain([&*ENGLISH]).find_map(|bundle| format(bundle, id, args)).unwrap_or_else(|| id.to_string())
+}
+
+// The message `id` from one language, if it has it. Whole numbers are passed as numbers, so a
+// message can pick its plural form by them.
+fn format(bundle: &FluentBundle<FluentResource>, id: &str, args: &[(&str, &dyn Display)]) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        let value = value.to_string();
+        match value.parse::<i64>() {
+            // "05" stays as written
+            Ok(number) if number.to_string() == value => fluent_args.set(*name, number),
+            _ => fluent_args.set(*name, value),
+        }
+    }
+    let mut errors = Vec::new();
+    Some(bundle.format_pattern(pattern, Some(&fluent_args), &mut errors).into_owned())
+}
+
+// A language's messages. The files are built in and checked by the tests below, so a message
+// that fails to parse is simply left out, and shown in English.
+fn bundle(language: &str, source: &str) -> FluentBundle<FluentResource> {
+    let resource = FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, _)| resource);
+    let mut bundle = FluentBundle::new_concurrent(vec![language.parse().unwrap_or_default()]);
+    // Discord would show the marks Fluent puts around each variable
+    bundle.set_use_isolating(false);
+    let _ = bundle.add_resource(resource);
+    bundle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_message_file_parses() {
+        for (name, source) in LANGUAGES {
+            if let Err((_, errors)) = FluentResource::try_new(source.to_string()) {
+                panic!("locales/{}.ftl: {:?}", name, errors);
+            }
+        }
+    }
+
+    #[test]
+    fn plural_forms_follow_the_number() {
+        assert_eq!(text("session-pages", &[("pages", &1), ("minutes", &3)]), "1 page in 3 min");
+        assert_eq!(text("session-pages", &[("pages", &12), ("minutes", &3)]), "12 pages in 3 min");
+        let german = bundle("de", LANGUAGES[1].1);
+        assert_eq!(format(&german, "session-pages", &[("pages", &1), ("minutes", &3)]).unwrap(), "1 Seite in 3 Min.");
+    }
+
+    #[test]
+    fn variables_are_filled_in_with_or_without_spaces() {
+        let bundle = bundle("en", "page = Page {$page}\nminutes = { $minutes } min");
+        assert_eq!(format(&bundle, "page", &[("page", &7)]).unwrap(), "Page 7");
+        assert_eq!(format(&bundle, "minutes", &[("minutes", &"05")]).unwrap(), "05 min");
+    }
+}
@@ -0,0 +1,446 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::Config;
+
+const DEFAULT_MAX_SIZE_MB: u64 = 10;
+const DEFAULT_MAX_FILES: usize = 5;
+
+// Secrets shorter than this are not worth masking (and would shred ordinary words)
+const MIN_SECRET_LEN: usize = 4;
+
+/// Set up logging to stdout (or the `dashboard` log pane), plus `log_file` when configured.
+/// RUST_LOG still controls the filter; `--log-format json` switches every output to one JSON
+/// object per line. Every output goes through the same redaction pass, so secrets never reach a
+/// terminal or file.
+pub fn init(log_format: Option<&str>, config: &Config, dashboard: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let json = log_format.is_some_and(|f| f.eq_ignore_ascii_case("json"));
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let redactor = Arc::new(Redactor::from_config(config));
+
+    let stdout = Redacting { inner: io::stdout, redactor: redactor.clone() };
+    let stdout_layer = if dashboard {
+        let pane = Redacting { inner: crate::tui::log_writer, redactor: redactor.clone() };
+        tracing_subscriber::fmt::layer().with_ansi(false).with_target(false).with_writer(pane).boxed()
+    } else if json {
+        tracing_subscriber::fmt::layer().json().with_current_span(true).with_span_list(true).with_writer(stdout).boxed()
+    } else {
+        tracing_subscriber::fmt::layer().with_writer(stdout).boxed()
+    };
+
+    let file_layer = match config.log_file {
+        Some(ref log_file) => {
+            let writer = Redacting { inner: file_writer(Path::new(log_file), config)?, redactor };
+            let layer = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(writer);
+            Some(if json { layer.json().with_current_span(true).with_span_list(true).boxed() } else { layer.boxed() })
+        }
+        None => None,
+    };
+
+    #[cfg(feature = "otel")]
+    let otel_layer = match config.otlp_endpoint {
+        Some(ref endpoint) => Some(otel_layer(endpoint)?),
+        None => None,
+    };
+    #[cfg(not(feature = "otel"))]
+    let otel_layer: Option<tracing_subscriber::layer::Identity> = None;
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stdout_layer)
+        .with(file_layer)
+        .with(otel_layer)
+        .try_init()?;
+
+    #[cfg(not(feature = "otel"))]
+    if config.otlp_endpoint.is_some() {
+        warn!("otlp_endpoint is set, but this build was compiled without the `otel` feature; traces will not be exported");
+    }
+    Ok(())
+}
+
+// Export every span (poll cycles, Komga requests, cover uploads, Discord IPC calls) over OTLP/HTTP,
+// e.g. to http://localhost:4318/v1/traces
+#[cfg(feature = "otel")]
+fn otel_layer<S>(endpoint: &str) -> Result<impl Layer<S>, Box<dyn std::error::Error>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(opentelemetry_sdk::Resource::builder().with_service_name(env!("CARGO_PKG_NAME")).build())
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Collapses a run of identical errors (e.g. every poll while Komga is down) into the first full
+/// log line plus a periodic "still failing" summary.
+pub struct ErrorThrottle {
+    label: &'static str,
+    summary_interval: Duration,
+    last: Option<String>,
+    count: u64,
+    first_seen: DateTime<Utc>,
+    last_summary: Instant,
+}
+
+impl ErrorThrottle {
+    pub fn new(label: &'static str, summary_interval: Duration) -> Self {
+        ErrorThrottle { label, summary_interval, last: None, count: 0, first_seen: Utc::now(), last_summary: Instant::now() }
+    }
+
+    /// Records an occurrence; returns true when the caller should log it in full.
+    pub fn record(&mut self, message: &str) -> bool {
+        if self.last.as_deref() == Some(message) {
+            self.count += 1;
+            if self.last_summary.elapsed() >= self.summary_interval {
+                warn!(occurrences = self.count, since = %self.first_seen, error = %message, "{} still failing", self.label);
+                self.last_summary = Instant::now();
+            }
+            return false;
+        }
+        self.reset();
+        self.last = Some(message.to_string());
+        self.count = 1;
+        self.first_seen = Utc::now();
+        self.last_summary = Instant::now();
+        true
+    }
+
+    /// Call after a success; reports how long the previous failure lasted.
+    pub fn reset(&mut self) {
+        if self.count > 1 {
+            info!(occurrences = self.count, since = %self.first_seen, "{} recovered", self.label);
+        }
+        self.last = None;
+        self.count = 0;
+    }
+}
+
+/// Masks configured secrets, plus anything that looks like a credential header or query parameter
+/// (covers `{:?}` dumps of requests and errors that echo URLs).
+pub struct Redactor {
+    secrets: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn from_config(config: &Config) -> Self {
+        let mut secrets: Vec<String> = [
+            Some(&config.komga_api_key),
+            config.imgur_client_id.as_ref(),
+            config.discord_status_bot_token.as_ref(),
+            config.mqtt_password.as_ref(),
+            config.anilist_token.as_ref(),
+            config.kitsu_password.as_ref(),
+            config.calibre_web_password.as_ref(),
+            config.suwayomi_password.as_ref(),
+            config.hardcover_token.as_ref(),
+            config.daily_summary_webhook.as_ref(),
+            config.weekly_report_bot_token.as_ref(),
+            config.watchdog_webhook.as_ref(),
+            config.push_token.as_ref(),
+            config.status_api_token.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(config.komga_servers.iter().flatten().map(|server| &server.komga_api_key))
+        .chain(config.webhook_urls.iter().flatten())
+        .filter(|s| s.len() >= MIN_SECRET_LEN)
+        .cloned()
+        .collect();
+        // Longest first so a secret containing another is masked whole
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+        let patterns = [
+            r#"(?i)(x-api-key|authorization)("?\s*[:=]\s*"?)((?:Client-ID|Bearer|Bot|Basic)\s+)?[^\s",}]+"#,
+            r#"(?i)([?&](?:api_?key|token|client_id|access_token)=)[^&\s"]+"#,
+            // Calibre-Web's Kobo sync token sits in the path
+            r#"(/kobo/)[^/\s"]+"#,
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid redaction pattern"))
+        .collect();
+
+        Redactor { secrets, patterns }
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut out = Cow::Borrowed(text);
+        for secret in &self.secrets {
+            if out.contains(secret.as_str()) {
+                out = Cow::Owned(out.replace(secret.as_str(), "***"));
+            }
+        }
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if pattern.is_match(&out) {
+                let replacement = if i == 0 { "$1$2$3***" } else { "$1***" };
+                out = Cow::Owned(pattern.replace_all(&out, replacement).into_owned());
+            }
+        }
+        out
+    }
+}
+
+struct Redacting<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<'a, M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter { inner: self.inner.make_writer(), redactor: &self.redactor }
+    }
+}
+
+// The fmt layer renders each event into one buffer and hands it over in a single write,
+// so a secret can't be split across calls.
+struct RedactingWriter<'a, W> {
+    inner: W,
+    redactor: &'a Redactor,
+}
+
+impl<W: Write> Write for RedactingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => self.inner.write_all(self.redactor.redact(text).as_bytes())?,
+            Err(_) => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum FileWriter {
+    Rolling(RollingFileAppender),
+    Sized(SizeRotatingFile),
+}
+
+impl<'a> MakeWriter<'a> for FileWriter {
+    type Writer = Box<dyn Write + 'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        match self {
+            FileWriter::Rolling(appender) => Box::new(appender.make_writer()),
+            FileWriter::Sized(file) => Box::new(file.make_writer()),
+        }
+    }
+}
+
+// `log_rotation`: "daily" (dated files), "size" (log, log.1, log.2, ...) or "never"
+fn file_writer(path: &Path, config: &Config) -> Result<FileWriter, Box<dyn std::error::Error>> {
+    let max_files = config.log_max_files.unwrap_or(DEFAULT_MAX_FILES).max(1);
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file_name = path.file_name().ok_or("log_file must name a file")?.to_string_lossy().to_string();
+    fs::create_dir_all(dir)?;
+
+    match config.log_rotation.as_deref().unwrap_or("never") {
+        "daily" => Ok(FileWriter::Rolling(
+            RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(file_name)
+                .max_log_files(max_files)
+                .build(dir)?,
+        )),
+        "size" => {
+            let max_bytes = config.log_max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024;
+            Ok(FileWriter::Sized(SizeRotatingFile::open(path.to_path_buf(), max_bytes, max_files)?))
+        }
+        "never" => Ok(FileWriter::Rolling(RollingFileAppender::new(Rotation::NEVER, dir, file_name))),
+        other => Err(format!("Unknown log_rotation '{}', expected daily, size or never", other).into()),
+    }
+}
+
+/// Appends to a single file and shifts it to `.1`, `.2`, ... once it grows past `max_bytes`,
+/// keeping at most `max_files` rotated copies.
+struct SizeRotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    state: Mutex<(File, u64)>,
+}
+
+impl SizeRotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(SizeRotatingFile { path, max_bytes, max_files, state: Mutex::new((file, size)) })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self, state: &mut (File, u64)) -> io::Result<()> {
+        state.0.flush()?;
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for i in (1..self.max_files).rev() {
+            let from = self.rotated_path(i);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(i + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        *state = (OpenOptions::new().create(true).append(true).open(&self.path)?, 0);
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for SizeRotatingFile {
+    type Writer = SizeRotatingGuard<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SizeRotatingGuard { file: self, state: self.state.lock().unwrap_or_else(|e| e.into_inner()) }
+    }
+}
+
+struct SizeRotatingGuard<'a> {
+    file: &'a SizeRotatingFile,
+    state: MutexGuard<'a, (File, u64)>,
+}
+
+impl Write for SizeRotatingGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state.1 > 0 && self.state.1 + buf.len() as u64 > self.file.max_bytes {
+            self.file.rotate(&mut self.state)?;
+        }
+        let written = self.state.0.write(buf)?;
+        self.state.1 += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.0.flush()
+    }
+}
+
+/// `logs [-f] [-n N]`: print the last `lines` lines of `log_file`, then keep printing new lines
+/// as they are written when `follow` is set. Follows across daily and size-based rotation.
+pub fn tail(config: &Config, follow: bool, lines: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let log_file = config.log_file.as_deref().ok_or("log_file is not set in the config, so there is no log to show")?;
+    let path = Path::new(log_file);
+    let mut current = active_log_file(path, config)?;
+    let contents = fs::read(&current)?;
+    let text = String::from_utf8_lossy(&contents);
+    let skip = text.lines().count().saturating_sub(lines);
+    let mut stdout = io::stdout();
+    for line in text.lines().skip(skip) {
+        writeln!(stdout, "{}", line)?;
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut position = contents.len() as u64;
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+        // A new dated file (daily) or a truncated one (size rotation) means we start over from its top
+        let latest = active_log_file(path, config)?;
+        let len = fs::metadata(&latest).map(|m| m.len()).unwrap_or(0);
+        if latest != current || len < position {
+            current = latest;
+            position = 0;
+        }
+        if len > position {
+            let mut file = File::open(&current)?;
+            file.seek(SeekFrom::Start(position))?;
+            position += io::copy(&mut file, &mut stdout)?;
+            stdout.flush()?;
+        }
+    }
+}
+
+// The file currently being written: `log_file` itself, or the newest `log_file.YYYY-MM-DD` with daily rotation
+fn active_log_file(path: &Path, config: &Config) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if config.log_rotation.as_deref() != Some("daily") {
+        return Ok(path.to_path_buf());
+    }
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let prefix = format!("{}.", path.file_name().ok_or("log_file must name a file")?.to_string_lossy());
+    fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .max()
+        .ok_or_else(|| format!("No log files found for {}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(options: serde_json::Value) -> Redactor {
+        let mut config = serde_json::json!({
+            "discord_client_id": "1234",
+            "komga_url": "http://localhost:25600",
+            "komga_api_key": "komga-key-0123",
+        });
+        config.as_object_mut().unwrap().extend(options.as_object().unwrap().clone());
+        Redactor::from_config(&serde_json::from_value(config).unwrap())
+    }
+
+    #[test]
+    fn configured_secrets_are_masked() {
+        let redactor = redactor(serde_json::json!({ "mqtt_password": "hunter22" }));
+        assert_eq!(redactor.redact("key komga-key-0123, password hunter22"), "key ***, password ***");
+        assert!(matches!(redactor.redact("nothing to hide"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn credential_headers_in_a_debug_dump_are_masked() {
+        let redactor = redactor(serde_json::json!({}));
+        let dump = r#"headers: {"x-api-key": "abc123", "authorization": "Bearer eyJhbGciOi.xyz"}"#;
+        assert_eq!(redactor.redact(dump), r#"headers: {"x-api-key": "***", "authorization": "Bearer ***"}"#);
+        assert_eq!(redactor.redact("X-Api-Key: abc123"), "X-Api-Key: ***");
+    }
+
+    #[test]
+    fn credential_query_parameters_are_masked() {
+        let redactor = redactor(serde_json::json!({}));
+        assert_eq!(
+            redactor.redact("http://kavita/api/Plugin/authenticate?apiKey=abc123&pluginName=komga-rpc"),
+            "http://kavita/api/Plugin/authenticate?apiKey=***&pluginName=komga-rpc"
+        );
+        assert_eq!(redactor.redact("https://example.com/x?user=1&access_token=abc123"), "https://example.com/x?user=1&access_token=***");
+    }
+
+    #[test]
+    fn kobo_token_in_the_path_is_masked() {
+        let redactor = redactor(serde_json::json!({}));
+        assert_eq!(redactor.redact("http://calibre/kobo/0a1b2c3d/v1/library/uuid/state"), "http://calibre/kobo/***/v1/library/uuid/state");
+    }
+
+    #[test]
+    fn a_secret_containing_another_is_masked_whole() {
+        let redactor = redactor(serde_json::json!({ "push_token": "komga-key-0123-extended" }));
+        assert_eq!(redactor.redact("token komga-key-0123-extended"), "token ***");
+    }
+}
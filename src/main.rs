@@ -1,30 +1,322 @@
-use discord_rich_presence::{activity, DiscordIpcClient, DiscordIpc};
+use discord_rich_presence::activity;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::time::Duration;
 use tokio::time;
 use reqwest::Client;
 use std::env;
-use std::time::SystemTime;
-use log::{info, error, warn};
-use env_logger;
-use std::io::ErrorKind;
+use std::time::{Instant, SystemTime};
+use tracing::{debug, info, error, warn, Instrument};
+use std::io::IsTerminal;
 use std::collections::HashMap;
+use std::process::ExitCode;
 use chrono::Utc;
 
+mod anilist;
+mod breaker;
+mod calibre_web;
+mod config_upgrade;
+mod cover_cache;
+mod daily_summary;
+mod discord_status;
+mod exit;
+mod hardcover;
+mod history;
+mod hooks;
+mod http_client;
+mod install;
+mod integrations;
+mod ipc;
+mod kavita;
+mod kitsu;
+mod komf;
+mod libraries;
+mod locale;
+mod logging;
+mod media_server;
+mod metadata_cache;
+mod mqtt;
+mod numbering;
+mod presenter;
+mod preview;
+mod push;
+mod retry;
+mod scripting;
+mod scrobble;
+mod sessions;
+mod settings;
+mod setup;
+mod state;
+mod stats;
+mod status_api;
+mod suwayomi;
+mod template;
+mod timestamps;
+mod tui;
+mod watchdog;
+mod webhooks;
+mod weekly_report;
+
+use breaker::{CircuitBreaker, Grace, ServerUnavailable};
+use cover_cache::{CoverCache, Deferred};
+use discord_status::DiscordStatusWatcher;
+use ipc::DiscordClient;
+use integrations::Integrations;
+use logging::ErrorThrottle;
+use media_server::{MediaServer, Reading};
+use numbering::BookNumbers;
+use presenter::Presenter;
+use sessions::SharedSessions;
+use status_api::{ApiState, ErrorCategory};
+use watchdog::Watchdog;
+
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Komga stops counting a book as being read five minutes after its progress last moved
+const PUSH_FOLLOW_UP: Duration = Duration::from_secs(300);
+// How long to keep trying to reach Discord at startup unless discord_connect_timeout_secs says
+const DEFAULT_DISCORD_CONNECT_TIMEOUT: u64 = 60;
+// Komga rejecting the API key this many full checks in a row ends the process
+const AUTH_FAILURE_LIMIT: u32 = 3;
+// Longest details, state or hover text Discord accepts, in characters
+const DISCORD_TEXT_LIMIT: usize = 128;
+// In-progress books fetched with concurrent_books "rotate", to find every one read at once
+const ROTATE_CANDIDATES: usize = 10;
+// How often to try Komga while this machine is offline, for offline_grace_secs; it fails fast, and resyncs the moment it's back
+const OFFLINE_RETRY: Duration = Duration::from_secs(5);
+// Longest a single full check or page update may take unless cycle_timeout_secs says
+const DEFAULT_CYCLE_TIMEOUT: u64 = 120;
+// The profile that is just the top-level options
+const DEFAULT_PROFILE: &str = "default";
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 struct Config {
     discord_client_id: String,
+    server_type: Option<String>, // "komga" (default), "kavita", "calibre-web" or "suwayomi"
+    calibre_web_username: Option<String>, // For Calibre-Web's OPDS feed
+    calibre_web_password: Option<String>,
+    calibre_web_shelf: Option<String>, // Shelf ID whose books are checked, rather than all unread books
+    suwayomi_username: Option<String>, // When Suwayomi has basic auth turned on
+    suwayomi_password: Option<String>,
     komga_url: String,
     komga_api_key: String,
+    komga_user: Option<String>, // Email or ID of the Komga user whose reading is shown
+    read_only: Option<bool>, // Refuse to send Komga anything but GET requests
+    komf_url: Option<String>, // Komf, for metadata on series Komga has none for yet
+    komga_servers: Option<Vec<KomgaServer>>, // More servers polled alongside komga_url; the most recent reading wins
+    server_nickname: Option<String>, // Name komga_url goes by on Discord, e.g. "Home library"
+    server_icon: Option<String>, // Discord asset key or image URL standing for the server
+    show_server: Option<String>, // Where the nickname goes: "prefix" (default), "small", "state" or "hover"
+    show_library: Option<String>, // "From: Manga" as a "small" image's text, on the "state" line, in the "hover" text, or as a "prefix"
+    library_icon: Option<String>, // Discord asset key or image URL for show_library "small"
     show_progress: Option<bool>,
     use_imgur_cover: Option<bool>,
     imgur_client_id: Option<String>,
     exclude_libraries: Option<Vec<String>>,
     exclude_tags: Option<Vec<String>>,
+    concurrent_books: Option<String>, // Several books read at once: show the "latest" (default) or "rotate" between them
+    exclude_formats: Option<Vec<String>>, // Media profiles (PDF, EPUB, DIVINA), media types or file extensions
     nocover_tags: Option<Vec<String>>, // Tags for which cover art should be hidden
+    discord_ipc_path: Option<String>, // Socket path (or directory) overriding IPC discovery
+    discord_transport: Option<String>, // "ipc" (default) or "websocket" for arRPC-style bridges
+    discord_ws_url: Option<String>,
+    library_client_ids: Option<HashMap<String, String>>, // Library name -> Discord application ID
+    media_profile_client_ids: Option<HashMap<String, String>>, // DIVINA / EPUB / PDF -> Discord application ID
+    series_assets: Option<HashMap<String, String>>, // Series title or ID -> Discord app asset key
+    library_assets: Option<HashMap<String, String>>, // Library name -> Discord app asset key
+    discord_status_bot_token: Option<String>, // Bot sharing a server with you, used to read your status
+    discord_user_id: Option<String>,
+    suppress_on_status: Option<Vec<String>>, // Defaults to dnd and invisible
+    status_api_addr: Option<String>, // e.g. "127.0.0.1:8765" to serve /healthz, /status and controls
+    status_api_token: Option<String>, // Bearer token the POST controls require
+    profiles: Option<HashMap<String, serde_json::Value>>, // Named sets of options laid over these ones, switched through the status API
+    profile: Option<String>, // The profile to start in, until another is switched to
+    push_listen_addr: Option<String>, // Where to accept POST /progress notifications
+    push_token: Option<String>, // Bearer token pushes must carry
+    push_only: Option<bool>, // Check Komga only when pushed, not every few seconds
+    log_file: Option<String>,
+    log_rotation: Option<String>, // "daily", "size" or "never"
+    log_max_size_mb: Option<u64>,
+    log_max_files: Option<usize>,
+    otlp_endpoint: Option<String>, // OTLP/HTTP traces endpoint; needs the `otel` build feature
+    mqtt_host: Option<String>, // Publish reading state to this MQTT broker
+    mqtt_port: Option<u16>,
+    mqtt_username: Option<String>,
+    mqtt_password: Option<String>,
+    mqtt_topic: Option<String>, // Topic prefix, defaults to "komga-rpc"
+    mqtt_homeassistant_discovery: Option<bool>, // Announce sensors to Home Assistant
+    mqtt_discovery_prefix: Option<String>, // Defaults to "homeassistant"
+    webhook_urls: Option<Vec<String>>, // POSTed JSON when reading starts, stops or a book is finished
+    webhook_events: Option<Vec<String>>, // Subset of "started", "stopped", "finished"
+    on_start: Option<String>, // Shell commands run on reading changes, see config.json.example
+    on_update: Option<String>,
+    on_stop: Option<String>,
+    presence_script: Option<String>, // Rhai script adjusting the presence; needs the `scripting` build feature
+    tracker: Option<String>, // "anilist" or "kitsu": push finished books to a reading tracker
+    anilist_token: Option<String>,
+    kitsu_username: Option<String>,
+    kitsu_password: Option<String>,
+    tracker_progress: Option<String>, // Book numbers are "volumes" (default) or "chapters"
+    tracker_series_ids: Option<HashMap<String, String>>, // Series title or ID -> tracker ID, when matching fails
+    tracker_cache_file: Option<String>,
+    scrobble_threshold: Option<u32>, // Percent of pages read that counts as finished, default 100
+    hardcover_token: Option<String>, // Sync ebook reading status to Hardcover.app
+    hardcover_libraries: Option<Vec<String>>, // Libraries synced to Hardcover; defaults to EPUB books
+    daily_summary_webhook: Option<String>, // Discord webhook URL for a daily reading summary
+    daily_summary_time: Option<String>, // Local "HH:MM", default "22:00"
+    history_db: Option<String>, // SQLite file recording every reading session
+    metadata_cache_db: Option<String>, // SQLite file keeping cover URLs, Komf matches and library names across restarts
+    session_stats: Option<String>, // Show "42 pages in 35 min" on the "state" line or in the image "hover" text
+    show_eta: Option<String>, // Estimated time to finish the book, on the "state" line or as a countdown "timestamp"
+    show_streak: Option<String>, // "🔥 12-day streak" on the "state" line or in the "hover" text; needs history_db
+    streak_min_pages: Option<u32>, // Pages a day needs to count toward the streak, default 1
+    daily_goal_pages: Option<u32>, // Show progress toward a daily goal, e.g. "goal: 34/50 pages"
+    daily_goal_minutes: Option<u32>,
+    daily_goal_reset: Option<String>, // Local "HH:MM" when the day's progress starts over, default "00:00"
+    show_goal: Option<String>, // "state" (default) or "hover"
+    server_timezone: Option<String>, // Time zone of timestamps the server sends without one, e.g. "Europe/Berlin" or "local"; default UTC
+    clock_skew_tolerance: Option<u64>, // Seconds the server's clock may be off from this machine's, default 60
+    language: Option<String>, // Language of the activity's own words ("Page", "Untitled", ...): en (default), de, es or fr
+    title_language: Option<Vec<String>>, // Alternate title labels to prefer, in order, e.g. ["English", "Romaji"]
+    latin_titles_only: Option<bool>, // Skip titles in non-Latin scripts when there is another choice
+    details_template: Option<String>, // First line, e.g. "{series} ({year})"; see config.json.example for the names
+    state_template: Option<String>, // Second line
+    hover_template: Option<String>, // The cover's hover text, otherwise the first line again
+    detect_spreads: Option<bool>, // "Pages 34–35" when the reader turns two pages at a time
+    show_page_numbers: Option<bool>, // false keeps page numbers off the activity, {page} and {pages} included
+    progress_display: Option<String>, // "page" (default), "percent" or "both"; EPUBs and webtoons always use percent
+    media_profile_layouts: Option<HashMap<String, Layout>>, // DIVINA / EPUB / PDF -> templates and progress display
+    show_series_status: Option<bool>, // "One Piece (Ongoing)" on the first line
+    show_publisher: Option<String>, // "Shueisha · 2021" on the "state" line or in the image "hover" text
+    show_read_list: Option<String>, // "Infinity Saga · 7/23" on the "state" line or in the image "hover" text
+    read_lists: Option<Vec<String>>, // Read lists being worked through, in order of preference; default any
+    show_author: Option<String>, // Authors on the "state" line or in the image "hover" text
+    author_roles: Option<Vec<String>>, // Only authors with these roles, listed in this order, e.g. ["writer", "story"]
+    max_authors: Option<usize>, // Names listed before "+3 more"
+    author_fallback: Option<String>, // Shown when no one is credited, instead of the library name or "Unknown Author"
+    hide_authors: Option<bool>, // Leave authors out everywhere, {author} included
+    number_format: Option<String>, // How book numbers render, e.g. "#{number}" or "Volume {number}"
+    library_number_formats: Option<HashMap<String, String>>, // Library name -> number format
+    media_profile_number_formats: Option<HashMap<String, String>>, // DIVINA / EPUB / PDF -> number format
+    weekly_report_channel_id: Option<String>, // Channel a bot posts a weekly recap to; needs history_db
+    weekly_report_bot_token: Option<String>, // Defaults to discord_status_bot_token
+    weekly_report_day: Option<String>, // Default "sunday"
+    weekly_report_time: Option<String>, // Local "HH:MM", default "20:00"
+    show_elapsed: Option<bool>, // Elapsed reading time on Discord, default true
+    state_file: Option<String>, // Remembers the session in progress across restarts, default "komga-rpc-state.json"
+    watchdog_minutes: Option<u64>, // Alert when reading goes this long without Discord accepting an update
+    watchdog_notify: Option<bool>, // Desktop notification on the alert
+    watchdog_webhook: Option<String>, // URL POSTed JSON on the alert
+    outage_threshold: Option<u32>, // Failed Komga checks in a row before it counts as down, default 3
+    outage_probe_secs: Option<u64>, // How often to try Komga while it's down, default 120
+    maintenance_grace_secs: Option<u64>, // How long Komga may answer 502/503 (restarting) before it counts as down, default 600
+    show_server_restarting: Option<bool>, // Add "(server restarting)" to the activity meanwhile
+    offline_grace_secs: Option<u64>, // How long the activity stays up (its clock stopped) while the network is down, default 300
+    retry_attempts: Option<u32>, // Tries per Komga/Imgur request on transient failures, default 3; 1 turns retrying off
+    retry_base_ms: Option<u64>, // First wait before retrying, doubled (with jitter) for each further try; default 500
+    discord_connect_timeout_secs: Option<u64>, // How long to wait for Discord at startup before giving up, default 60
+    cycle_timeout_secs: Option<u64>, // A full check or page update taking longer is cancelled, default 120
+    ip_family: Option<String>, // "ipv4" or "ipv6" to use only that family, "prefer_ipv4" or "prefer_ipv6" to try it first
+    komga_address: Option<String>, // IP address komga_url's host goes to, skipping DNS
+    // The file as loaded, for laying a profile over
+    #[serde(skip)]
+    source: serde_json::Value,
+}
+
+/// Another Komga (or Kavita) server to poll. Filters left out fall back to the top-level ones.
+#[derive(Debug, Deserialize, Clone)]
+struct KomgaServer {
+    server_type: Option<String>,
+    komga_url: String,
+    komga_api_key: String,
+    server_nickname: Option<String>,
+    server_icon: Option<String>,
+    exclude_libraries: Option<Vec<String>>,
+    exclude_tags: Option<Vec<String>>,
+    nocover_tags: Option<Vec<String>>,
+    exclude_formats: Option<Vec<String>>,
+    server_timezone: Option<String>,
+}
+
+impl Config {
+    // The config as seen when talking to `server`
+    fn for_server(&self, server: &KomgaServer) -> Config {
+        let mut config = self.clone();
+        config.server_type = server.server_type.clone().or(config.server_type);
+        config.komga_url = server.komga_url.clone();
+        config.komga_api_key = server.komga_api_key.clone();
+        // Each server goes by its own name, or none
+        config.server_nickname = server.server_nickname.clone();
+        config.server_icon = server.server_icon.clone().or(config.server_icon);
+        config.exclude_libraries = server.exclude_libraries.clone().or(config.exclude_libraries);
+        config.exclude_tags = server.exclude_tags.clone().or(config.exclude_tags);
+        config.nocover_tags = server.nocover_tags.clone().or(config.nocover_tags);
+        config.exclude_formats = server.exclude_formats.clone().or(config.exclude_formats);
+        config.server_timezone = server.server_timezone.clone().or(config.server_timezone);
+        config
+    }
+
+    // The config with profile `name`'s options in place of the top-level ones. "default" is the
+    // top-level options as they are, unless a profile takes that name.
+    fn with_profile(&self, name: &str) -> Result<Config, Box<dyn std::error::Error>> {
+        let overrides = match self.profiles.as_ref().and_then(|profiles| profiles.get(name)) {
+            Some(overrides) => overrides,
+            None if name == DEFAULT_PROFILE => return Ok(self.clone()),
+            None => return Err(format!("No profile named '{}'", name).into()),
+        };
+        let Some(overrides) = overrides.as_object() else {
+            return Err(format!("Profile '{}' should be an object of options", name).into());
+        };
+        let mut merged = self.source.clone();
+        if let Some(merged) = merged.as_object_mut() {
+            merged.extend(overrides.clone());
+        }
+        let mut config: Config = serde_json::from_value(merged).map_err(|e| format!("Profile '{}': {}", name, e))?;
+        config.source = self.source.clone();
+        Ok(config)
+    }
+
+    // Every profile that can be switched to, "default" first
+    fn profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.iter().flat_map(|profiles| profiles.keys()).filter(|name| *name != DEFAULT_PROFILE).cloned().collect();
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        names
+    }
+
+    // The config for the server `presence` came from
+    fn for_presence(&self, presence: &Presence) -> Config {
+        self.komga_servers
+            .iter()
+            .flatten()
+            .find(|server| server.komga_url == presence.komga_url)
+            .map(|server| self.for_server(server))
+            .unwrap_or_else(|| self.clone())
+    }
+}
+
+/// How the presence text is laid out. Each field left out falls back to the top-level option of
+/// the same name.
+#[derive(Debug, Deserialize, Clone, Default)]
+struct Layout {
+    details_template: Option<String>,
+    state_template: Option<String>,
+    hover_template: Option<String>,
+    progress_display: Option<String>,
+}
+
+impl Layout {
+    fn for_media_profile(config: &Config, media_profile: Option<&str>) -> Layout {
+        let profile = media_profile
+            .and_then(|profile| config.media_profile_layouts.as_ref()?.iter().find(|(name, _)| name.eq_ignore_ascii_case(profile)))
+            .map(|(_, layout)| layout.clone())
+            .unwrap_or_default();
+        Layout {
+            details_template: profile.details_template.or_else(|| config.details_template.clone()),
+            state_template: profile.state_template.or_else(|| config.state_template.clone()),
+            hover_template: profile.hover_template.or_else(|| config.hover_template.clone()),
+            progress_display: profile.progress_display.or_else(|| config.progress_display.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -133,17 +425,296 @@ struct BookReadProgress {
     updated_at: Option<String>, // ISO8601 timestamp
 }
 
+/// Everything needed to publish one Discord activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Presence {
+    client_id: String,
+    details: String,
+    state: String,
+    large_image: Option<String>,
+    large_text: String,
+    small_image: Option<String>,
+    small_text: Option<String>,
+    buttons: Vec<PresenceButton>,
+    start_timestamp: Option<i64>, // Unix seconds; Discord counts up from it
+    end_timestamp: Option<i64>, // Unix seconds; Discord counts down to it
+    // What is being read, for integrations that want more than the display strings
+    series_id: String,
+    series_title: String,
+    #[serde(default)]
+    komga_url: String, // Server the book is on
+    read_at: Option<chrono::DateTime<Utc>>, // When Komga last saw its progress move
+    #[serde(default)]
+    alternate_titles: Vec<String>, // The series' other Komga titles, for matching on other sites
+    book_id: String,
+    book_title: Option<String>,
+    book_number: Option<String>,
+    volume: Option<String>,
+    chapter: Option<String>,
+    isbn: Option<String>,
+    page: Option<u32>,
+    pages: Option<u32>,
+    percent: Option<u32>, // How far through the book, when known better than by page
+    #[serde(default)]
+    spread: bool, // Reading two pages at a time
+    library: Option<String>,
+    media_profile: Option<String>, // DIVINA, EPUB or PDF
+    #[serde(skip)]
+    reading: Option<Box<Reading>>, // What it was laid out from, so a page turn can be laid out again
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PresenceButton {
+    label: String,
+    url: String,
+}
+
+impl Presence {
+    fn to_activity(&self) -> activity::Activity<'_> {
+        let mut activity_builder = activity::Activity::new()
+            .details(&self.details)
+            .state(&self.state)
+            .activity_type(activity::ActivityType::Playing);
+        // A countdown takes the place of the elapsed time
+        if let Some(end) = self.end_timestamp {
+            activity_builder = activity_builder.timestamps(activity::Timestamps::new().end(end));
+        } else if let Some(start) = self.start_timestamp {
+            activity_builder = activity_builder.timestamps(activity::Timestamps::new().start(start));
+        }
+        if !self.buttons.is_empty() {
+            // Discord shows at most two buttons
+            let buttons = self.buttons.iter().take(2).map(|b| activity::Button::new(&b.label, &b.url)).collect();
+            activity_builder = activity_builder.buttons(buttons);
+        }
+        if self.large_image.is_none() && self.small_image.is_none() {
+            return activity_builder;
+        }
+        let mut assets = activity::Assets::new();
+        if let Some(ref url) = self.large_image {
+            assets = assets.large_image(url).large_text(&self.large_text);
+        }
+        if let Some(ref image) = self.small_image {
+            assets = assets.small_image(image);
+            if let Some(ref text) = self.small_text {
+                assets = assets.small_text(text);
+            }
+        }
+        activity_builder.assets(assets)
+    }
+
+    // Say which server the book is on, per show_server
+    fn label_server(&mut self, config: &Config) {
+        let Some(ref nickname) = config.server_nickname else {
+            return;
+        };
+        match (config.show_server.as_deref().unwrap_or("prefix"), &config.server_icon) {
+            ("prefix", _) => self.details = format!("{} · {}", nickname, self.details),
+            ("small", Some(icon)) => {
+                self.small_image = Some(icon.clone());
+                self.small_text = Some(nickname.clone());
+            }
+            // A small image needs something to show; without server_icon the hover text will do
+            ("small", None) => self.append("hover", nickname),
+            (placement, _) => self.append(placement, nickname),
+        }
+    }
+
+    // Say which library the book is from, per show_library. Labelled before the server, so a
+    // server prefix comes first; the server keeps the small image if both want it.
+    fn label_library(&mut self, config: &Config) {
+        let (Some(placement), Some(library)) = (config.show_library.as_deref(), self.library.clone()) else {
+            return;
+        };
+        let text = locale::text("from-library", &[("library", &library)]);
+        let server_small = config.server_nickname.is_some() && config.server_icon.is_some() && config.show_server.as_deref() == Some("small");
+        match (placement, &config.library_icon) {
+            ("prefix", _) => self.details = format!("{} · {}", library, self.details),
+            ("small", Some(icon)) if !server_small => {
+                self.small_image = Some(icon.clone());
+                self.small_text = Some(text);
+            }
+            ("small", _) => self.append("hover", &text),
+            (placement, _) => self.append(placement, &text),
+        }
+    }
+
+    // Discord turns the whole activity away if a line runs past its limit, and a template or the
+    // show_* additions can easily get there with a long series title. Cut here, on the way out,
+    // so every path to Discord is covered and the status API still has the full text.
+    fn fit_discord_limits(&mut self) {
+        self.details = template::truncate(&self.details, DISCORD_TEXT_LIMIT);
+        self.state = template::truncate(&self.state, DISCORD_TEXT_LIMIT);
+        self.large_text = template::truncate(&self.large_text, DISCORD_TEXT_LIMIT);
+    }
+
+    // Add extra text to the "state" line, or with "hover" to the cover's hover text
+    fn append(&mut self, placement: &str, text: &str) {
+        let target = if placement == "hover" { &mut self.large_text } else { &mut self.state };
+        *target = format!("{} · {}", target, text);
+    }
+
+    // Discord asset keys only mean something to Discord, so only a real URL is worth sharing
+    fn cover_url(&self) -> Option<&str> {
+        self.large_image.as_deref().filter(|image| image.starts_with("http"))
+    }
+}
+
+// How the reading state moved between two polls: "started", "updated", "stopped", or None if unchanged.
+// Only the book and page count; display text such as a ticking ETA doesn't make an update.
+fn reading_event(previous: Option<&Presence>, current: Option<&Presence>) -> Option<&'static str> {
+    match (previous, current) {
+        (None, Some(_)) => Some("started"),
+        (Some(_), None) => Some("stopped"),
+        (Some(before), Some(after)) if (&before.book_id, before.page) != (&after.book_id, after.page) => Some("updated"),
+        _ => None,
+    }
+}
+
+// The previous presence, if the reader has moved off its book and Komga now marks it completed
+async fn finished_book<'a>(client: &Client, config: &Config, previous: Option<&'a Presence>, current: Option<&Presence>) -> Option<&'a Presence> {
+    let previous = previous?;
+    if current.is_some_and(|current| current.book_id == previous.book_id) {
+        return None;
+    }
+    let config = config.for_presence(previous);
+    let completed = MediaServer::from_config(&config).ok()?.completed(client, &config, previous).await;
+    completed.unwrap_or(false).then_some(previous)
+}
+
+// Whether Komga marks the book completed
+async fn komga_completed(client: &Client, config: &Config, book_id: &str) -> Option<bool> {
+    let book_url = format!("{}/api/v1/books/{}", config.komga_url, book_id);
+    let book: serde_json::Value = komga_get(client, config, &book_url).await.ok()?.json().await.ok()?;
+    book.get("readProgress").and_then(|rp| rp.get("completed")).and_then(|v| v.as_bool())
+}
+
+// JSON shared by MQTT, webhooks and command hooks. `presence` is what is (or, for "stopped"
+// and "finished", what was) being read.
+fn event_payload(event: &str, presence: Option<&Presence>) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "event": event,
+        "reading": matches!(event, "started" | "updated"),
+        "timestamp": Utc::now().to_rfc3339(),
+    });
+    if let Some(presence) = presence {
+        payload["series"] = presence.series_title.clone().into();
+        payload["series_id"] = presence.series_id.clone().into();
+        payload["book_id"] = presence.book_id.clone().into();
+        payload["book_title"] = presence.book_title.clone().into();
+        payload["book_number"] = presence.book_number.clone().into();
+        payload["volume"] = presence.volume.clone().into();
+        payload["chapter"] = presence.chapter.clone().into();
+        payload["page"] = presence.page.into();
+        payload["pages"] = presence.pages.into();
+        payload["percent"] = presence.percent.into();
+        payload["library"] = presence.library.clone().into();
+        payload["cover_url"] = presence.cover_url().into();
+        payload["details"] = presence.details.clone().into();
+        payload["state"] = presence.state.clone().into();
+    }
+    payload
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit::code(&*e)
+        }
+    }
+}
 
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args().map_err(exit::with(exit::USAGE))?;
     let client = Client::new();
-    let config_file = parse_args()?;
-    info!("Using config file: {}", config_file);
-    let config = load_config(&config_file)?;
-    let mut discord = DiscordIpcClient::new(&config.discord_client_id);
-    discord.connect()?;
-    info!("Komga Discord RPC Connected!");
+    let config_file = args.config_file;
+    // Setup, settings and config upgrade write the config, so they can't need a loadable one
+    match args.command.as_deref() {
+        Some("setup") => return setup::run(&client, &config_file).await,
+        Some("settings") => return settings::run(&config_file),
+        _ => {}
+    }
+    match (args.command.as_deref(), args.subcommand.as_deref()) {
+        (Some("config"), Some("upgrade")) => return config_upgrade::run(&config_file),
+        (Some("config"), _) => return Err(exit::with(exit::USAGE)("Usage: config upgrade [-c config.json]")),
+        _ => {}
+    }
+    // First run from a terminal: ask for a config instead of failing, then carry on with it
+    if args.command.is_none() && !std::path::Path::new(&config_file).exists() && std::io::stdin().is_terminal() {
+        println!("No config found at {}; let's make one.", config_file);
+        setup::run(&client, &config_file).await?;
+    }
+    let config = load_config(&config_file).map_err(|e| exit::with(exit::CONFIG)(format!("Couldn't load {}: {}", config_file, e)))?;
+    let client = http_client::build(&config).map_err(exit::with(exit::CONFIG))?;
+    // Handled before logging starts so our own startup lines don't end up in the output
+    match (args.command.as_deref(), args.subcommand.as_deref()) {
+        (Some("install"), _) => return install::run(&config_file),
+        (Some("logs"), _) => return logging::tail(&config, args.follow, args.lines),
+        (Some("history"), Some("export")) => return history::export(&config, &args.format, args.since.as_deref()),
+        (Some("history"), _) => return Err(exit::with(exit::USAGE)("Usage: history export [--format csv|json] [--since YYYY-MM-DD]")),
+        _ => {}
+    }
+    let dashboard = args.command.as_deref() == Some("tui");
+    logging::init(args.log_format.as_deref(), &config, dashboard).map_err(exit::with(exit::CONFIG))?;
+    scripting::load(&config).map_err(exit::with(exit::CONFIG))?;
+    metadata_cache::open(&config).map_err(exit::with(exit::CONFIG))?;
+    locale::init(&config).map_err(exit::with(exit::CONFIG))?;
+    timestamps::check(&config).map_err(exit::with(exit::CONFIG))?;
+    push::check(&config).map_err(exit::with(exit::CONFIG))?;
+    status_api::check(&config).map_err(exit::with(exit::CONFIG))?;
+    for name in config.profile_names().iter().chain(&config.profile) {
+        config.with_profile(name).map_err(exit::with(exit::CONFIG))?;
+    }
+    info!(%config_file, "Using config file");
+    match args.command.as_deref() {
+        None | Some("tui") => {}
+        Some("preview") => return preview::run(&client, &config).await,
+        Some(other) => return Err(exit::with(exit::USAGE)(format!("Unknown command: {}", other))),
+    }
+    ipc::install_panic_hook();
+    let discord = connect_discord(&config).await?;
+    let api_state = ApiState::new();
+    api_state.lock().unwrap().discord.record_success();
+    let presenter = Presenter::spawn(&config, discord, api_state.clone());
+    if let Some(ref addr) = config.status_api_addr {
+        tokio::spawn(status_api::serve(addr.clone(), config.status_api_token.clone(), api_state.clone()));
+    }
+    if let Some(ref addr) = config.push_listen_addr {
+        tokio::spawn(push::serve(addr.clone(), config.push_token.clone(), api_state.clone()));
+    }
+    if config.read_only.unwrap_or(false) {
+        info!("Read-only mode: only GET requests will be sent to Komga");
+    }
+    for server_config in std::iter::once(config.clone()).chain(config.komga_servers.iter().flatten().map(|server| config.for_server(server))) {
+        MediaServer::from_config(&server_config).map_err(exit::with(exit::CONFIG))?.check_user(&client, &server_config).await?;
+    }
+    let integrations = Integrations::from_config(&config, &client).map_err(exit::with(exit::CONFIG))?;
+    let sessions = integrations.sessions();
+    // The profile last switched to survives a restart, as long as the config still has it
+    let saved_profile = sessions.lock().unwrap().profile().map(str::to_string);
+    let mut active_profile = saved_profile
+        .filter(|name| config.profile_names().contains(name))
+        .or_else(|| config.profile.clone())
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    {
+        let mut api_state = api_state.lock().unwrap();
+        api_state.profile = active_profile.clone();
+        api_state.profiles = config.profile_names();
+    }
+    daily_summary::spawn(&config, &client, sessions.clone()).map_err(exit::with(exit::CONFIG))?;
+    weekly_report::spawn(&config, &client, sessions.clone()).map_err(exit::with(exit::CONFIG))?;
+    // Put back what was shown before a restart rather than nothing until the first full check
+    let restored = sessions.lock().unwrap().take_restorable();
+    if let Some(mut presence) = restored {
+        presence.append("state", &locale::text("resuming", &[]));
+        info!(series = %presence.series_title, "Restoring the last reading activity");
+        presenter.show(&presence);
+    }
+    if args.stats {
+        tokio::spawn(stats::report_periodically(Duration::from_secs(60)));
+    }
     let mut playback_state = PlaybackState {
         last_api_time: SystemTime::now(),
         is_reading: false,
@@ -153,278 +724,618 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         last_api_time: None,
         last_position: None,
     };
-    let mut imgur_cache: HashMap<String, String> = HashMap::new();
+    let (mut imgur_cache, mut finished_uploads) = CoverCache::background();
     let mut last_series_id: Option<String> = None;
     let mut last_series_time: Option<SystemTime> = None;
-    let mut current_book_id: Option<String> = None;
-    let mut current_series_id: Option<String> = None;
-    let mut current_series_title: Option<String> = None;
-    let mut last_full_check = SystemTime::now();
+    // The first full check runs straight away; it picks the book the page updates then follow
+    let mut last_full_check = SystemTime::UNIX_EPOCH;
     let mut last_page_update = SystemTime::now();
     let full_check_interval = Duration::from_secs(40);
-    let page_update_interval = Duration::from_secs(10);
+    let page_update_interval = Duration::from_secs(5);
+    let cycle_timeout = Duration::from_secs(config.cycle_timeout_secs.unwrap_or(DEFAULT_CYCLE_TIMEOUT).max(1));
+    let status_watcher = match (&config.discord_status_bot_token, &config.discord_user_id) {
+        (Some(token), Some(user_id)) => Some(DiscordStatusWatcher::spawn(token.clone(), user_id.clone())),
+        _ => None,
+    };
+    let mut presence_hidden = false;
+    let mut activity_errors = ErrorThrottle::new("Komga polling", Duration::from_secs(300));
+    let mut watchdog = Watchdog::from_config(&config, &client);
+    let mut discord_last_success = None;
+    let mut komga_breaker = CircuitBreaker::from_config(&config);
+    let mut auth_failures = 0;
+    let mut maintenance = Grace::maintenance(&config);
+    let mut offline = Grace::offline(&config);
+    let polling = !(config.push_only.unwrap_or(false) && config.push_listen_addr.is_some());
+    let mut last_push: Option<SystemTime> = None;
+    // Started last, so a startup error still prints to a normal terminal
+    let _dashboard = dashboard.then(|| {
+        tui::spawn(api_state.clone(), tui::Timers { full_check: full_check_interval, page_update: page_update_interval, polling })
+    });
+
+    // A profile swaps the options the loop works from; what was set up above keeps the
+    // top-level ones
+    let base_config = config.clone();
+    let mut config = base_config.with_profile(&active_profile).map_err(exit::with(exit::CONFIG))?;
+    if active_profile != DEFAULT_PROFILE {
+        info!(profile = %active_profile, "Using profile");
+    }
 
     loop {
+        let (paused, clear_requested, push_requested, requested_profile) = {
+            let mut api_state = api_state.lock().unwrap();
+            (api_state.paused, std::mem::take(&mut api_state.clear_requested), std::mem::take(&mut api_state.push_requested), api_state.profile.clone())
+        };
+        if requested_profile != active_profile {
+            match base_config.with_profile(&requested_profile) {
+                Ok(profile_config) => {
+                    info!(from = %active_profile, to = %requested_profile, "Switched profile");
+                    config = profile_config;
+                    active_profile = requested_profile;
+                    sessions.lock().unwrap().set_profile(&active_profile);
+                    // Show the current book the new way straight away
+                    last_full_check = SystemTime::UNIX_EPOCH;
+                }
+                Err(e) => {
+                    warn!(profile = %requested_profile, error = %e, "Couldn't switch profile");
+                    api_state.lock().unwrap().profile = active_profile.clone();
+                }
+            }
+        }
+        if push_requested {
+            last_push = Some(SystemTime::now());
+        }
+        // The activity went out without its cover while it uploaded; check again to add it
+        while let Ok(upload) = finished_uploads.try_recv() {
+            if imgur_cache.finish(upload) {
+                last_full_check = SystemTime::UNIX_EPOCH;
+            }
+        }
+        let hide_reason = if paused {
+            Some("paused through the status API".to_string())
+        } else {
+            status_watcher
+                .as_ref()
+                .and_then(|watcher| watcher.status())
+                .filter(|status| is_status_suppressed(&config, Some(status)))
+                .map(|status| format!("Discord status is {}", status))
+        };
+        if clear_requested || (hide_reason.is_some() && !presence_hidden) {
+            if let Some(ref reason) = hide_reason {
+                info!(%reason, "Hiding reading activity");
+            }
+            presenter.clear();
+            let previous = api_state.lock().unwrap().current.take();
+            integrations.reading_changed(previous.as_ref(), None, None);
+        }
+        if hide_reason.is_some() {
+            presence_hidden = true;
+            time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        presence_hidden = false;
         let now = SystemTime::now();
-        let do_full_check = last_full_check.elapsed().unwrap_or(Duration::from_secs(0)) >= full_check_interval;
-        let do_page_update = last_page_update.elapsed().unwrap_or(Duration::from_secs(0)) >= page_update_interval;
+        // Without polling, one more check once pushes stop lets Komga's staleness rule clear the activity
+        let push_follow_up = !polling && last_push.is_some_and(|at| at.elapsed().unwrap_or_default() >= PUSH_FOLLOW_UP);
+        if push_follow_up {
+            last_push = None;
+        }
+        // While Komga is down only the breaker's probes reach it, pushed or not. Offline, a quick
+        // retry catches the network coming back until the grace period is up; past it, the outage
+        // gets the breaker's backoff like any other.
+        let do_full_check = if offline.in_grace() {
+            offline.retry_due(OFFLINE_RETRY)
+        } else if komga_breaker.is_open() {
+            komga_breaker.probe_due()
+        } else {
+            // A grace period running out is checked on straight away, so the activity goes when it ends
+            let grace_over = [&offline, &maintenance].iter().any(|grace| grace.is_active() && !grace.in_grace());
+            grace_over || push_requested || push_follow_up || (polling && last_full_check.elapsed().unwrap_or(Duration::from_secs(0)) >= full_check_interval)
+        };
+        let do_page_update = polling && !komga_breaker.is_open() && !offline.is_active() && last_page_update.elapsed().unwrap_or(Duration::from_secs(0)) >= page_update_interval;
 
         if do_full_check {
             // Full scan for most recent in-progress book (as before)
-            if let Err(e) = set_activity(
+            let previous = {
+                let mut api_state = api_state.lock().unwrap();
+                api_state.last_full_check = Some(Utc::now());
+                api_state.current.clone()
+            };
+            let full_check = set_activity(
                 &client,
                 &config,
-                &mut discord,
+                &presenter,
                 &mut playback_state,
                 &mut current_series,
                 &mut timing_info,
                 &mut imgur_cache,
-            ).await {
-                let mut is_pipe_error = false;
-                let mut is_auth_error = false;
-
-                // Check for authentication errors
-                if let Some(source_err) = e.downcast_ref::<reqwest::Error>() {
-                    if let Some(status) = source_err.status() {
-                        if status == reqwest::StatusCode::UNAUTHORIZED {
-                            is_auth_error = true;
-                        }
+                &sessions,
+                &mut watchdog,
+                previous.as_ref(),
+            ).instrument(tracing::info_span!("poll_cycle", kind = "full"));
+            // A request that hangs would otherwise stall the loop for good
+            let result = time::timeout(cycle_timeout, full_check).await.unwrap_or_else(|elapsed| {
+                warn!(budget_secs = cycle_timeout.as_secs(), "Full check ran over its time budget; cancelled it");
+                Err(elapsed.into())
+            });
+            last_full_check = SystemTime::now();
+            last_page_update = SystemTime::now();
+            match result {
+                Err(e) => {
+                    let is_auth_error = e
+                        .downcast_ref::<reqwest::Error>()
+                        .and_then(|e| e.status())
+                        .is_some_and(|status| status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN);
+
+                    {
+                        let mut api_state = api_state.lock().unwrap();
+                        api_state.komga.record_failure(e.to_string());
+                        let category = if is_auth_error { ErrorCategory::Auth } else { ErrorCategory::of(&*e) };
+                        api_state.record_error(category, e.to_string());
                     }
-                }
 
-                if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
-                    if io_err.kind() == ErrorKind::BrokenPipe || io_err.raw_os_error() == Some(232) || io_err.raw_os_error() == Some(32) {
-                        is_pipe_error = true;
+                    // A revoked key won't come back by itself, so stop and let whatever restarts us say so
+                    if is_auth_error {
+                        auth_failures += 1;
+                        if auth_failures >= AUTH_FAILURE_LIMIT {
+                            return Err(exit::with(exit::NO_PERMISSION)(format!(
+                                "Komga at {} keeps turning the API key away ({}); check komga_api_key and the user's shared libraries",
+                                config.komga_url, e
+                            )));
+                        }
+                        warn!(error = %e, attempt = auth_failures, "Komga rejected the API key, trying again shortly");
+                        time::sleep(Duration::from_secs(10)).await;
+                        continue;
                     }
-                }
+                    auth_failures = 0;
 
-                if !is_pipe_error && !is_auth_error {
-                    let mut source = e.source();
-                    while let Some(err) = source {
-                        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
-                            if io_err.kind() == ErrorKind::BrokenPipe || io_err.raw_os_error() == Some(232) || io_err.raw_os_error() == Some(32) {
-                                is_pipe_error = true;
-                                break;
+                    // Offline or restarting, and whether that's still within its grace period
+                    let offline_state = breaker::is_offline(&*e).then(|| offline.unavailable());
+                    let maintenance_state = match offline_state {
+                        None => e.downcast_ref::<ServerUnavailable>().map(|_| maintenance.unavailable()),
+                        Some(_) => None,
+                    };
+                    if let Some((true, started)) = offline_state {
+                        // Offline: keep what's shown, its clock stopped, rather than claim reading went on
+                        if started {
+                            info!(error = %e, grace_secs = offline.grace().as_secs(), "The network looks to be down, keeping the activity while it's out");
+                            let shown = api_state.lock().unwrap().current.clone();
+                            if let Some(mut presence) = shown {
+                                presence.start_timestamp = None;
+                                presence.end_timestamp = None;
+                                presenter.show(&presence);
+                            }
+                        }
+                    } else if let Some((true, started)) = maintenance_state {
+                        // Restarting or updating: keep what's shown instead of clearing it
+                        if started {
+                            info!(error = %e, grace_secs = maintenance.grace().as_secs(), "Komga looks to be restarting, keeping the activity while it does");
+                            let shown = api_state.lock().unwrap().current.clone();
+                            if let Some(mut presence) = shown.filter(|_| config.show_server_restarting.unwrap_or(false)) {
+                                presence.state = format!("{} ({})", presence.state, locale::text("server-restarting", &[]));
+                                presenter.show(&presence);
                             }
                         }
-                        source = err.source();
+                    } else if offline_state.or(maintenance_state).is_some() {
+                        // The grace period is up: clear now, and leave it to the breaker's probes from here
+                        if komga_breaker.trip() {
+                            warn!(
+                                error = %e,
+                                probe_every_secs = komga_breaker.probe_interval().as_secs(),
+                                "Komga is still unavailable after the grace period; clearing the activity and only checking occasionally until it answers"
+                            );
+                            presenter.clear();
+                            let previous = api_state.lock().unwrap().current.take();
+                            integrations.reading_changed(previous.as_ref(), None, None);
+                        }
+                    } else if komga_breaker.record_failure() {
+                        warn!(
+                            error = %e,
+                            probe_every_secs = komga_breaker.probe_interval().as_secs(),
+                            "Komga keeps failing; clearing the activity and only checking occasionally until it answers"
+                        );
+                        presenter.clear();
+                        let previous = api_state.lock().unwrap().current.take();
+                        integrations.reading_changed(previous.as_ref(), None, None);
+                    } else if !komga_breaker.is_open() && activity_errors.record(&e.to_string()) {
+                        error!(error = %e, "Error setting activity");
+                        error!(error = ?e, "Full error details");
                     }
                 }
-
-                if is_auth_error {
-                    warn!("Authentication expired, re-authenticating...");
-                    // access_token = None;
-                    continue;
-                }
-
-                if is_pipe_error {
-                    warn!("Connection to Discord lost (pipe closed). Attempting to reconnect...");
-                    if let Err(close_err) = discord.close() {
-                        error!("Error closing old Discord client (connection likely already broken): {}", close_err);
+                Ok(presence) => {
+                    activity_errors.reset();
+                    auth_failures = 0;
+                    if let Some(down_for) = offline.available() {
+                        info!(offline_for_secs = down_for.as_secs(), "Back online, resynced the activity");
                     }
-                    time::sleep(Duration::from_secs(5)).await;
-                    let mut new_discord = DiscordIpcClient::new(&config.discord_client_id);
-                    if let Err(connect_err) = new_discord.connect() {
-                        error!("Failed to reconnect to Discord: {}", connect_err);
-                    } else {
-                        info!("Successfully reconnected to Discord.");
-                        discord = new_discord;
+                    if let Some(down_for) = maintenance.available() {
+                        info!(unavailable_for_secs = down_for.as_secs(), "Komga is available again");
                     }
-                } else {
-                    error!("Error setting activity (not identified as pipe error): {}", e);
-                    error!("Full error details: {:?}", e);
-                }
-            } else {
-                // Update the last_series_id and last_series_time if a new series is set
-                if let Some(series) = &current_series {
-                    if last_series_id.as_ref().map_or(true, |id| id != &series.id) {
-                        last_series_id = Some(series.id.clone());
-                        last_series_time = Some(SystemTime::now());
+                    if let Some(down_for) = komga_breaker.record_success() {
+                        info!(down_for_secs = down_for.as_secs(), "Komga is answering again, back to normal polling");
                     }
-                }
-            }
-        } else if do_page_update {
-            if let (Some(ref book_id), Some(ref series_id), Some(ref series_title)) = (&current_book_id, &current_series_id, &current_series_title) {
-                let book_url = format!("{}/api/v1/books/{}", config.komga_url, book_id);
-                let response = client
-                    .get(&book_url)
-                    .header("X-API-Key", &config.komga_api_key)
-                    .send()
-                    .await?;
-                if response.status().is_success() {
-                    let book: serde_json::Value = response.json().await?;
-                    let page_num = book.get("readProgress").and_then(|rp| rp.get("page")).and_then(|v| v.as_u64()).map(|v| v as u32);
-                    let details = series_title.to_string();
-                    let mut state = book.get("metadata")
-                        .and_then(|m| m.get("title"))
-                        .and_then(|v| v.as_str())
-                        .map(|s| s.to_string())
-                        .or_else(|| book.get("title").and_then(|v| v.as_str()).map(|s| s.to_string()))
-                        .or_else(|| book.get("name").and_then(|v| v.as_str()).map(|s| s.to_string()))
-                        .unwrap_or_else(|| "Untitled Book".to_string());
-                    let details = if let Some(page_num) = page_num {
-                        format!("{} (Page {})", state, page_num)
-                    } else {
-                        state.clone()
+                    let previous = {
+                        let mut api_state = api_state.lock().unwrap();
+                        api_state.komga.record_success();
+                        std::mem::replace(&mut api_state.current, presence.clone())
                     };
-                    let state = "Komga-RPC";
-
-                    // Fetch the latest series title for this book
-                    let series_url = format!("{}/api/v1/series/{}", config.komga_url, series_id);
-                    let series_response = client
-                        .get(&series_url)
-                        .header("X-API-Key", &config.komga_api_key)
-                        .send()
-                        .await?;
-                    let series_title = if series_response.status().is_success() {
-                        let series_json: serde_json::Value = series_response.json().await?;
-                        series_json.get("title")
-                            .and_then(|v| v.as_str())
-                            .or_else(|| series_json.get("metadata").and_then(|m| m.get("title")).and_then(|v| v.as_str()))
-                            .unwrap_or("Untitled")
-                            .to_string()
+                    let finished = if integrations.wants_finished() {
+                        finished_book(&client, &config, previous.as_ref(), presence.as_ref()).await
                     } else {
-                        "Untitled".to_string()
+                        None
                     };
-                    let large_text = &series_title;
-                    let cover_url = get_komga_cover_path(&client, &config, series_id, &mut imgur_cache, false).await?;
-                    let activity_builder = activity::Activity::new()
-                        .details(&details)
-                        .state(state)
-                        .activity_type(activity::ActivityType::Playing);
-                    let final_activity = if let Some(ref url) = cover_url {
-                        activity_builder.assets(
-                            activity::Assets::new()
-                                .large_image(url)
-                                .large_text(large_text)
-                        )
-                    } else {
-                        activity_builder
-                    };
-                    discord.set_activity(final_activity)?;
+                    integrations.reading_changed(previous.as_ref(), presence.as_ref(), finished);
+                    // Update the last_series_id and last_series_time if a new series is set
+                    if let Some(series) = &current_series {
+                        if last_series_id.as_ref().map_or(true, |id| id != &series.id) {
+                            last_series_id = Some(series.id.clone());
+                            last_series_time = Some(SystemTime::now());
+                        }
+                    }
+                }
+            }
+        } else if do_page_update {
+            // Between full checks only the book being read is looked at: one request, and a page
+            // turn laid out again from what the last full check found
+            api_state.lock().unwrap().last_page_update = Some(Utc::now());
+            // They follow whatever is shown, as long as it's on Komga: the only server they know how to ask
+            let shown = api_state.lock().unwrap().current.clone().filter(|p| matches!(MediaServer::from_config(&config.for_presence(p)), Ok(MediaServer::Komga)));
+            if let Some(shown) = shown {
+                let (series_id, series_title) = (&shown.series_id, &shown.series_title);
+                let server_config = config.for_presence(&shown);
+                let book_url = format!("{}/api/v1/books/{}", server_config.komga_url, shown.book_id);
+                let page_update = async {
+                    Ok::<serde_json::Value, Box<dyn std::error::Error>>(komga_get(&client, &server_config, &book_url).await?.error_for_status()?.json().await?)
+                }
+                .instrument(tracing::info_span!("poll_cycle", kind = "page"));
+                match time::timeout(cycle_timeout, page_update).await {
+                    Err(_) => warn!(budget_secs = cycle_timeout.as_secs(), "Page update ran over its time budget; cancelled it"),
+                    // The next full check will say what's wrong, if it's more than a blip
+                    Ok(Err(e)) => debug!(error = %e, "Page update failed"),
+                    Ok(Ok(book)) => {
+                        let progress = book.get("readProgress");
+                        let page = progress.and_then(|rp| rp.get("page")).and_then(|v| v.as_u64()).map(|v| v as u32);
+                        let tracked_finished = progress.and_then(|rp| rp.get("completed")).and_then(|v| v.as_bool()).unwrap_or(false);
+                        // With page numbers hidden a page turn alone shows nothing new; a moving
+                        // percentage can wait for the next full check
+                        let page_turned = page != shown.page && server_config.show_page_numbers.unwrap_or(true);
+                        // Finished since the last tick: clear it now rather than once it goes stale, and look
+                        // for whatever is being read next straight away
+                        if tracked_finished {
+                            info!("The tracked book was finished, clearing its activity");
+                            presenter.clear();
+                            let previous = api_state.lock().unwrap().current.take();
+                            integrations.reading_changed(previous.as_ref(), None, previous.as_ref());
+                            last_full_check = SystemTime::UNIX_EPOCH;
+                        } else if book.get("seriesId").and_then(|v| v.as_str()) != Some(series_id.as_str()) {
+                            debug!(series = %series_title, "The tracked book moved to another series, checking in full");
+                            last_full_check = SystemTime::UNIX_EPOCH;
+                        } else if !page_turned {
+                            debug!(series = %series_title, ?page, "Still on the same page");
+                        } else if let Some(presence) = turn_page(&config, &server_config, &sessions, &shown, &book) {
+                            debug!(series = %series_title, ?page, "Turned the page");
+                            watchdog.komga_progress(&presence);
+                            presenter.show(&presence);
+                            let previous = api_state.lock().unwrap().current.replace(presence.clone());
+                            integrations.reading_changed(previous.as_ref(), Some(&presence), None);
+                        } else {
+                            debug!(series = %series_title, ?page, "The page turn needs more than the book, checking in full");
+                            last_full_check = SystemTime::UNIX_EPOCH;
+                        }
+                    }
                 }
             }
             last_page_update = SystemTime::now();
         }
+        {
+            // The presenter shows updates on its own thread; going by its successes keeps the watchdog honest
+            let api_state = api_state.lock().unwrap();
+            if api_state.discord.last_success != discord_last_success {
+                discord_last_success = api_state.discord.last_success;
+                watchdog.discord_updated();
+            }
+            watchdog.check(api_state.discord.last_error.as_deref());
+        }
         // If not updating, just wait 1 second
         time::sleep(Duration::from_secs(1)).await;
     }
 }
 
-fn parse_args() -> Result<String, Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    if let Some(index) = args.iter().position(|arg| arg == "-c") {
-        if index + 1 < args.len() {
-            Ok(args[index + 1].clone())
-        } else {
-            Err("Error: missing argument for -c option".into())
+struct Args {
+    command: Option<String>,
+    subcommand: Option<String>,
+    config_file: String,
+    log_format: Option<String>,
+    // Log rolling latency percentiles and cache hit rates every minute
+    stats: bool,
+    // `logs` options
+    follow: bool,
+    lines: usize,
+    // `history export` options
+    format: String,
+    since: Option<String>,
+}
+
+fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
+    let mut command = None;
+    let mut config_file = "config.json".to_string();
+    let mut log_format = None;
+    let mut stats = false;
+    let mut follow = false;
+    let mut lines = 20;
+    let mut subcommand = None;
+    let mut format = "csv".to_string();
+    let mut since = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => config_file = args.next().ok_or("missing argument for -c option")?,
+            "--log-format" => log_format = Some(args.next().ok_or("missing argument for --log-format option")?),
+            "--stats" => stats = true,
+            "-f" | "--follow" => follow = true,
+            "-n" => lines = args.next().ok_or("missing argument for -n option")?.parse()?,
+            "--format" => format = args.next().ok_or("missing argument for --format option")?,
+            "--since" => since = Some(args.next().ok_or("missing argument for --since option")?),
+            // The first bare word selects a command, the second a subcommand (`history export`)
+            _ if !arg.starts_with('-') && command.is_none() => command = Some(arg),
+            _ if !arg.starts_with('-') && subcommand.is_none() => subcommand = Some(arg),
+            _ => {}
         }
-    } else {
-        Ok("config.json".to_string())
     }
+    Ok(Args { command, subcommand, config_file, log_format, stats, follow, lines, format, since })
 }
 
+// Discord may start after us (at login, say), so keep trying for a while before giving up
+async fn connect_discord(config: &Config) -> Result<DiscordClient, Box<dyn std::error::Error>> {
+    let timeout = Duration::from_secs(config.discord_connect_timeout_secs.unwrap_or(DEFAULT_DISCORD_CONNECT_TIMEOUT));
+    let started = Instant::now();
+    let mut discord = DiscordClient::from_config(config, &config.discord_client_id);
+    loop {
+        match discord.connect() {
+            Ok(()) => {
+                info!("Komga Discord RPC Connected!");
+                return Ok(discord);
+            }
+            Err(e) if started.elapsed() >= timeout => {
+                return Err(exit::with(exit::UNAVAILABLE)(format!("Couldn't connect to Discord within {}s: {}; is it running?", timeout.as_secs(), e)));
+            }
+            Err(e) => {
+                warn!(error = %e, "Couldn't connect to Discord yet, retrying");
+                time::sleep(Duration::from_secs(5)).await;
+                discord = DiscordClient::from_config(config, &config.discord_client_id);
+            }
+        }
+    }
+}
+
+// Older configs are upgraded on the way in, without touching the file (`config upgrade` does that)
 fn load_config(config_file: &str) -> Result<Config, Box<dyn std::error::Error>> {
     let config_str = fs::read_to_string(config_file)?;
-    let config: Config = serde_json::from_str(&config_str)?;
-    Ok(config)
+    let mut config: serde_json::Value = serde_json::from_str(&config_str)?;
+    config_upgrade::upgrade(&mut config)?;
+    let mut parsed: Config = serde_json::from_value(config.clone())?;
+    parsed.source = config;
+    Ok(parsed)
 }
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, clippy::too_many_arguments)]
 async fn set_activity(
     client: &Client,
     config: &Config,
-    discord: &mut DiscordIpcClient,
+    presenter: &Presenter,
     playback_state: &mut PlaybackState,
     current_series: &mut Option<Series>,
     timing_info: &mut TimingInfo,
-    imgur_cache: &mut HashMap<String, String>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Optimized: fetch books in pages, filter for in-progress (readProgress.completed == false)
-    let mut page = 0;
-    let page_size = 100;
-    let mut most_recent_book: Option<serde_json::Value> = None;
-    let mut most_recent_time = None;
-    let mut found = false;
-    let now = Utc::now();
+    imgur_cache: &mut CoverCache,
+    sessions: &SharedSessions,
+    watchdog: &mut Watchdog,
+    previous: Option<&Presence>,
+) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+    let mut presence = match build_presence_from_servers(client, config, imgur_cache, previous).await? {
+        Some(presence) => {
+            watchdog.komga_progress(&presence);
+            presence
+        }
+        None => {
+            presenter.clear();
+            return Ok(None);
+        }
+    };
+    add_session_details(config, sessions, &mut presence);
+    presenter.show(&presence);
+    timing_info.last_api_time = Some(SystemTime::now());
+    Ok(Some(presence))
+}
 
-    loop {
-        let books_url = format!(
-            "{}/api/v1/books?page={}&pageSize={}&sort=lastModified,desc",
-            config.komga_url, page, page_size
-        );
-        let response = client
-            .get(&books_url)
-            .header("X-API-Key", &config.komga_api_key)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(format!("Failed to fetch books with status: {}", response.status()).into());
-        }
-
-        let books_page: serde_json::Value = response.json().await?;
-        let books = books_page.get("content").and_then(|c| c.as_array()).cloned().unwrap_or_default();
-        if books.is_empty() {
-            break;
-        }
-
-        for book in &books {
-            let read_progress = book.get("readProgress");
-            if let Some(rp) = read_progress {
-                let completed = rp.get("completed").and_then(|v| v.as_bool()).unwrap_or(true);
-                if !completed {
-                    let last_modified_str = rp.get("lastModified").and_then(|v| v.as_str());
-                    let last_modified = last_modified_str.and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok()).map(|dt| dt.with_timezone(&Utc));
-                    if let Some(updated_at) = last_modified {
-                        if (now - updated_at).num_seconds() < 300 {
-                            // Found a recent in-progress book, use it immediately
-                            most_recent_book = Some(book.clone());
-                            most_recent_time = Some(updated_at);
-                            found = true;
-                            break;
-                        } else if most_recent_time.map_or(true, |t| updated_at > t) {
-                            // Track the most recent in-progress book, even if not within 5 minutes
-                            most_recent_book = Some(book.clone());
-                            most_recent_time = Some(updated_at);
-                        }
-                    }
+// `shown` laid out again at the page `book` is now on, keeping its cover and adding back the
+// labels and session details a full check would. None when that takes more than the book: an
+// EPUB's position has its own request, and a presence restored from disk has nothing to lay out
+// from. Also None if the presence script now hides the book.
+fn turn_page(config: &Config, server_config: &Config, sessions: &SharedSessions, shown: &Presence, book: &serde_json::Value) -> Option<Presence> {
+    let mut reading = *shown.reading.clone()?;
+    if reading.media_profile.as_deref() == Some("EPUB") {
+        return None;
+    }
+    reading.page = book.get("readProgress").and_then(|rp| rp.get("page")).and_then(|v| v.as_u64()).map(|v| v as u32);
+    reading.percent = reading.page.zip(reading.pages).filter(|(_, pages)| *pages > 0).map(|(page, pages)| page.min(pages) * 100 / pages);
+    reading.read_at = progress_modified(server_config, book).or(reading.read_at);
+    reading.context["book"] = book.clone();
+    let mut presence = reading.lay_out(server_config, Some(shown))?;
+    presence.large_image = shown.large_image.clone();
+    presence.label_library(server_config);
+    presence.label_server(server_config);
+    add_session_details(config, sessions, &mut presence);
+    Some(presence)
+}
+
+// The elapsed time and whatever the reading sessions add: stats, time left, streak and goals
+fn add_session_details(config: &Config, sessions: &SharedSessions, presence: &mut Presence) {
+    let sessions = sessions.lock().unwrap();
+    if config.show_elapsed.unwrap_or(true) {
+        presence.start_timestamp = Some(sessions.started_at(presence).unwrap_or_else(Utc::now).timestamp());
+    }
+    if let Some(ref placement) = config.session_stats {
+        if let Some(stats) = sessions.stats_for(presence) {
+            presence.append(placement, &stats.describe());
+        }
+    }
+    if let Some(ref placement) = config.show_eta {
+        if let Some(minutes) = sessions.minutes_left(presence) {
+            match placement.as_str() {
+                "timestamp" => presence.end_timestamp = Some(Utc::now().timestamp() + minutes * 60),
+                _ => presence.append(placement, &locale::text("time-left", &[("time", &format_minutes(minutes))])),
+            }
+        }
+    }
+    if let Some(ref placement) = config.show_streak {
+        // A one-day "streak" is just today
+        if let Some(days) = sessions.streak(config.streak_min_pages.unwrap_or(1)).filter(|days| *days >= 2) {
+            presence.append(placement, &locale::text("streak", &[("days", &days)]));
+        }
+    }
+    if config.daily_goal_pages.is_some() || config.daily_goal_minutes.is_some() {
+        let reset = config.daily_goal_reset.as_deref().and_then(|t| chrono::NaiveTime::parse_from_str(t, "%H:%M").ok()).unwrap_or_default();
+        let (pages, minutes) = sessions.today(reset);
+        let mut goals = Vec::new();
+        if let Some(goal) = config.daily_goal_pages {
+            goals.push(format!("{}{}", locale::text("goal-pages", &[("done", &pages), ("goal", &goal)]), if pages >= goal { " ✓" } else { "" }));
+        }
+        if let Some(goal) = config.daily_goal_minutes {
+            goals.push(format!("{}{}", locale::text("goal-minutes", &[("done", &minutes), ("goal", &goal)]), if minutes >= goal as i64 { " ✓" } else { "" }));
+        }
+        presence.append(config.show_goal.as_deref().unwrap_or("state"), &locale::text("goal", &[("goals", &goals.join(", "))]));
+    }
+}
+
+// "35 min" or "1h 20m"
+fn format_minutes(minutes: i64) -> String {
+    if minutes < 60 {
+        locale::text("minutes", &[("minutes", &minutes)])
+    } else {
+        locale::text("hours-minutes", &[("hours", &(minutes / 60)), ("minutes", &format!("{:02}", minutes % 60))])
+    }
+}
+
+// build_presence for komga_url and every one of komga_servers, keeping the most recent reading.
+// Fails only if every server does.
+async fn build_presence_from_servers(
+    client: &Client,
+    config: &Config,
+    imgur_cache: &mut CoverCache,
+    previous: Option<&Presence>,
+) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+    let Some(servers) = config.komga_servers.as_ref().filter(|servers| !servers.is_empty()) else {
+        let presence = MediaServer::from_config(config)?.build_presence(client, config, imgur_cache, previous).await?;
+        return Ok(presence.map(|mut presence| {
+            presence.label_library(config);
+            presence.label_server(config);
+            presence
+        }));
+    };
+    let mut latest: Option<(Presence, Option<Deferred>)> = None;
+    let mut failures = Vec::new();
+    for server_config in std::iter::once(config.clone()).chain(servers.iter().map(|server| config.for_server(server))) {
+        let server = MediaServer::from_config(&server_config)?;
+        // Only one server's book is shown, so covers wait until it's known which; the rest are
+        // never downloaded
+        imgur_cache.defer();
+        let result = server.build_presence(client, &server_config, imgur_cache, previous).await;
+        let cover = imgur_cache.take_deferred();
+        match result {
+            Ok(Some(mut presence)) => {
+                presence.label_library(&server_config);
+                presence.label_server(&server_config);
+                if latest.as_ref().is_none_or(|(latest, _)| presence.read_at > latest.read_at) {
+                    latest = Some((presence, cover));
                 }
             }
+            Ok(None) => {}
+            Err(e) => {
+                warn!(server = %server_config.komga_url, error = %e, "Failed to check {} server", server.name());
+                failures.push(e);
+            }
         }
-        if found {
-            break;
+    }
+    if let Some(e) = failures.pop().filter(|_| failures.len() == servers.len()) {
+        return Err(e);
+    }
+    let Some((mut presence, cover)) = latest else {
+        return Ok(None);
+    };
+    // Uploaded covers go ahead of a Komf cover standing in, as they do with a single server
+    if let Some(cover) = cover {
+        if let Some(url) = imgur_cache.run(cover).await {
+            presence.large_image = Some(url);
         }
-        // Check if this is the last page
-        let last = books_page.get("last").and_then(|v| v.as_bool()).unwrap_or(false);
-        if last {
-            break;
+    }
+    Ok(Some(presence))
+}
+
+// Work out what should be shown on Discord from the most recently read book, without publishing it.
+// `previous` is what was last shown. Returns None when nothing should be shown.
+#[tracing::instrument(skip_all)]
+async fn build_presence(
+    client: &Client,
+    config: &Config,
+    imgur_cache: &mut CoverCache,
+    previous: Option<&Presence>,
+) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+    // Komga sorts in-progress books by when they were last read, so the first is the one to show.
+    // Rotating between books read at once needs the others read in the last five minutes too.
+    let size = if config.concurrent_books.as_deref() == Some("rotate") { ROTATE_CANDIDATES } else { 1 };
+    let books_url = format!("{}/api/v1/books?read_status=IN_PROGRESS&sort=readProgress.readDate,desc&size={}", config.komga_url, size);
+    let response = komga_get(client, config, &books_url).await?;
+
+    if let Some(unavailable) = ServerUnavailable::from_status(response.status()) {
+        return Err(unavailable.into());
+    }
+    if matches!(response.status(), reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) {
+        // Kept as reqwest's error, so the polling loop can tell the key was turned away
+        response.error_for_status_ref()?;
+    }
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch books with status: {}", response.status()).into());
+    }
+
+    // The books are moved out of the parsed page rather than copied
+    let mut books_page: serde_json::Value = response.json().await?;
+    let books = match books_page.get_mut("content").map(serde_json::Value::take) {
+        Some(serde_json::Value::Array(books)) => books,
+        _ => Vec::new(),
+    };
+    let mut active: Vec<(chrono::DateTime<Utc>, serde_json::Value)> = Vec::new();
+    let mut most_recent_book: Option<serde_json::Value> = None;
+    for book in books {
+        let Some(updated_at) = progress_modified(config, &book) else {
+            continue;
+        };
+        if timestamps::is_recent(config, updated_at) {
+            active.push((updated_at, book));
+        } else if most_recent_book.is_none() {
+            most_recent_book = Some(book);
         }
-        page += 1;
+    }
+    if !active.is_empty() {
+        most_recent_book = Some(pick_active_book(config, active, previous));
     }
 
     let book = match most_recent_book {
         Some(b) => b,
         None => {
             info!("No in-progress books found in Komga");
-            discord.clear_activity()?;
-            return Ok(());
+            return Ok(None);
         }
     };
 
     // Only show as reading if updated in the last 5 minutes
-    let last_modified_str = book.get("readProgress").and_then(|rp| rp.get("lastModified")).and_then(|v| v.as_str());
-    let last_modified = last_modified_str.and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok()).map(|dt| dt.with_timezone(&Utc));
+    let last_modified = progress_modified(config, &book);
     if let Some(updated_at) = last_modified {
-        if (now - updated_at).num_seconds() >= 300 {
-            info!("Most recent in-progress book activity is too old (timestamp: {}), clearing Discord status", updated_at);
-            discord.clear_activity()?;
-            return Ok(());
+        if !timestamps::is_recent(config, updated_at) {
+            info!(%updated_at, "Most recent in-progress book activity is too old, clearing Discord status");
+            return Ok(None);
         }
     } else {
         info!("No valid lastModified timestamp for most recent in-progress book, clearing Discord status");
-        discord.clear_activity()?;
-        return Ok(());
+        return Ok(None);
     }
 
     let book_id = book.get("id").and_then(|v| v.as_str()).unwrap_or("");
@@ -433,85 +1344,70 @@ async fn set_activity(
     let library_id = book.get("libraryId").and_then(|v| v.as_str()).unwrap_or("");
     let page_num = book.get("readProgress").and_then(|rp| rp.get("page")).and_then(|v| v.as_u64()).map(|v| v as u32);
 
-    // Fetch series info for the book
-    let series_url = format!("{}/api/v1/series/{}", config.komga_url, series_id);
-    let response = client
-        .get(&series_url)
-        .header("X-API-Key", &config.komga_api_key)
-        .send()
-        .await?;
-    if !response.status().is_success() {
-        error!("Failed to fetch series info for book {}", book_id);
-        discord.clear_activity()?;
-        return Ok(());
-    }
-    let series: Series = response.json().await?;
-    info!("series object: {:?}", series);
-    let mut series_title = series.title.clone();
-    if series_title.is_none() {
-        // If title is missing, fetch as JSON and try metadata.title
-        let response = client
-            .get(&series_url)
-            .header("X-API-Key", &config.komga_api_key)
-            .send()
-            .await?;
-        if response.status().is_success() {
-            let series_json: serde_json::Value = response.json().await?;
-            series_title = series_json.get("metadata")
-                .and_then(|m| m.get("title"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-        }
-    }
-    let series_title = series_title.unwrap_or_else(|| "Untitled".to_string());
-    info!("series_title resolved = {}", series_title);
-
-    // Fetch library name if needed
-    let mut library_name = None;
-    if library_id != "" {
-        let library_url = format!("{}/api/v1/libraries/{}", config.komga_url, library_id);
-        let response = client
-            .get(&library_url)
-            .header("X-API-Key", &config.komga_api_key)
-            .send()
-            .await?;
-        if response.status().is_success() {
-            let library: serde_json::Value = response.json().await?;
-            library_name = library.get("name").and_then(|v| v.as_str()).map(|s| s.to_string());
+    // Exclude file formats if configured, before spending requests on the series
+    if let Some(ref exclude_formats) = config.exclude_formats {
+        if let Some(format) = excluded_format(exclude_formats, &book) {
+            info!(%format, "Skipping excluded file format");
+            return Ok(None);
         }
     }
 
-    // Exclude libraries by name if configured
+    // Exclude libraries by name if configured, also before the series is fetched
+    let library_name = libraries::name(client, config, library_id).await;
     if let Some(ref exclude_libraries) = config.exclude_libraries {
         if let Some(ref lib_name) = library_name {
             if exclude_libraries.iter().any(|ex| ex.eq_ignore_ascii_case(lib_name)) {
-                info!("Skipping excluded library: {}", lib_name);
-                discord.clear_activity()?;
-                return Ok(());
+                info!(library = %lib_name, "Skipping excluded library");
+                return Ok(None);
             }
         }
     }
 
-    // Exclude by tags if configured (series)
-    if let Some(ref exclude_tags) = config.exclude_tags {
-        // Fetch series info as JSON to check tags
+    let book_name = book_metadata_str(&book, "title").or_else(|| book.get("name").and_then(|v| v.as_str()).map(|s| s.to_string()));
+
+    // Fetch series info for the book. One-shots, and books whose series can't be fetched, are
+    // shown by their own title rather than an "Untitled" series.
+    let mut series_json = serde_json::Value::Null;
+    if !series_id.is_empty() {
         let series_url = format!("{}/api/v1/series/{}", config.komga_url, series_id);
-        let response = client
-            .get(&series_url)
-            .header("X-API-Key", &config.komga_api_key)
-            .send()
-            .await?;
+        let response = komga_get(client, config, &series_url).await?;
         if response.status().is_success() {
-            let series_json: serde_json::Value = response.json().await?;
-            let series_tags = series_json.get("metadata")
-                .and_then(|m| m.get("tags"))
-                .and_then(|tags| tags.as_array());
-            if let Some(tags) = series_tags {
-                if tags.iter().filter_map(|t| t.as_str()).any(|tag| exclude_tags.iter().any(|ex| ex.eq_ignore_ascii_case(tag))) {
-                    info!("Skipping excluded series by tag");
-                    discord.clear_activity()?;
-                    return Ok(());
-                }
+            series_json = response.json().await?;
+        } else {
+            warn!(%book_id, status = %response.status(), "Failed to fetch series info for book, showing it on its own");
+        }
+    }
+    let series = Series::deserialize(&series_json).ok();
+    debug!(?series, "Fetched series");
+    // Anything cached from the series is only good while it's unchanged
+    let series_version = series_json.get("lastModified").and_then(|v| v.as_str()).unwrap_or_default();
+    let oneshot = series.is_none() || book.get("oneshot").and_then(|v| v.as_bool()).unwrap_or(false);
+    // If title is missing, try metadata.title
+    let series_title = series
+        .as_ref()
+        .and_then(|s| s.title.clone())
+        .or_else(|| series_json.get("metadata").and_then(|m| m.get("title")).and_then(|v| v.as_str()).map(|s| s.to_string()))
+        .or_else(|| if oneshot { book_name.clone() } else { None });
+    let series_titles = series_titles(&series_json);
+    let series_title = preferred_title(config, &series_titles, series_title.unwrap_or_else(|| locale::text("untitled", &[])));
+    // Komga's metadata is thin until a provider has matched the series; Komf can fill in the gaps
+    let komf_match = if config.komf_url.is_some() && !oneshot && komf::is_sparse(&series_json) {
+        komf::lookup(client, config, series_id, series_version, library_id, &series_title).await
+    } else {
+        None
+    };
+    let series_title = komf_match.as_ref().map_or(series_title, |found| found.title.clone());
+    info!(%series_title, "Resolved series title");
+
+    // Exclude by tags if configured (series)
+    if let Some(ref exclude_tags) = config.exclude_tags {
+        let series_tags = series_json.get("metadata")
+            .and_then(|m| m.get("tags"))
+            .and_then(|tags| tags.as_array());
+        if let Some(tags) = series_tags {
+            if tags.iter().filter_map(|t| t.as_str()).any(|tag| exclude_tags.iter().any(|ex| ex.eq_ignore_ascii_case(tag))) {
+                info!("Skipping excluded series by tag");
+                return Ok(None);
             }
         }
     }
@@ -523,31 +1419,21 @@ async fn set_activity(
         if let Some(tags) = book_tags {
             if tags.iter().filter_map(|t| t.as_str()).any(|tag| exclude_tags.iter().any(|ex| ex.eq_ignore_ascii_case(tag))) {
                 info!("Skipping excluded book by tag");
-                discord.clear_activity()?;
-                return Ok(());
+                return Ok(None);
             }
         }
     }
     // Check for nocover tags (series or book)
     let mut skip_cover = false;
     if let Some(ref nocover_tags) = config.nocover_tags {
-        // Check series tags
-        let series_url = format!("{}/api/v1/series/{}", config.komga_url, series_id);
-        let response = client
-            .get(&series_url)
-            .header("X-API-Key", &config.komga_api_key)
-            .send()
-            .await?;
-        if response.status().is_success() {
-            let series_json: serde_json::Value = response.json().await?;
-            let series_tags = series_json.get("metadata")
-                .and_then(|m| m.get("tags"))
-                .and_then(|tags| tags.as_array());
-            if let Some(tags) = series_tags {
-                if tags.iter().filter_map(|t| t.as_str()).any(|tag| nocover_tags.iter().any(|ex| ex.eq_ignore_ascii_case(tag))) {
-                    info!("Cover art excluded for series due to nocover_tags match");
-                    skip_cover = true;
-                }
+        // Check series tags, from the series fetched above
+        let series_tags = series_json.get("metadata")
+            .and_then(|m| m.get("tags"))
+            .and_then(|tags| tags.as_array());
+        if let Some(tags) = series_tags {
+            if tags.iter().filter_map(|t| t.as_str()).any(|tag| nocover_tags.iter().any(|ex| ex.eq_ignore_ascii_case(tag))) {
+                info!("Cover art excluded for series due to nocover_tags match");
+                skip_cover = true;
             }
         }
         // Check book tags
@@ -563,124 +1449,467 @@ async fn set_activity(
     }
 
     // Authors: prefer book authors, then series authors, else library name
-    let mut authors: Vec<String> = vec![];
+    let mut credits: Vec<(String, Option<String>)> = vec![];
     if let Some(book_authors) = book.get("metadata").and_then(|m| m.get("authors")).and_then(|a| a.as_array()) {
         for a in book_authors {
             if let Some(name) = a.get("name").and_then(|v| v.as_str()) {
-                authors.push(name.to_string());
+                credits.push((name.to_string(), a.get("role").and_then(|v| v.as_str()).map(|s| s.to_string())));
             }
         }
     }
+    let mut authors = select_authors(config, credits);
     if authors.is_empty() {
-        if let Some(series_authors) = &series.authors {
-            for a in series_authors {
-                authors.push(a.name.clone());
-            }
+        if let Some(series_authors) = series.as_ref().and_then(|s| s.authors.as_ref()) {
+            authors = select_authors(config, series_authors.iter().map(|a| (a.name.clone(), a.role.clone())).collect());
         }
     }
-    let author_text = if !authors.is_empty() {
-        authors.join(", ")
-    } else if let Some(lib_name) = &library_name {
-        lib_name.clone()
-    } else {
-        "Unknown Author".to_string()
+
+    let numbers = BookNumbers::from_book(&book);
+    let media_profile = book.get("media").and_then(|m| m.get("mediaProfile")).and_then(|v| v.as_str());
+    // EPUB "pages" are just Komga's own pagination; the reader tracks a position through the book.
+    // Webtoon "pages" are slices of one long strip, so how far down it is what means something.
+    let pages_count = book.get("media").and_then(|m| m.get("pagesCount")).and_then(|v| v.as_u64()).map(|v| v as u32);
+    let webtoon = series_json.get("metadata").and_then(|m| m.get("readingDirection")).and_then(|v| v.as_str()) == Some("WEBTOON");
+    let page_percent = page_num.zip(pages_count).filter(|(_, pages)| *pages > 0).map(|(page, pages)| page.min(pages) * 100 / pages);
+    let percent = if media_profile == Some("EPUB") { epub_percent(client, config, book_id).await } else { page_percent };
+    let read_list = if media_server::wants_read_list(config, media_profile) { read_list_position(client, config, book_id).await } else { None };
+    let series_metadata_str = |key: &str| series_json.get("metadata").and_then(|m| m.get(key)).and_then(|v| v.as_str()).map(|s| s.to_string());
+    let buttons = komf_match
+        .as_ref()
+        .and_then(|found| Some((found, found.url.clone()?)))
+        .map(|(found, url)| PresenceButton { label: locale::text("view-on", &[("provider", &found.provider_name())]), url })
+        .into_iter()
+        .collect();
+
+    let reading = Reading {
+        series_id: series_id.to_string(),
+        series_title,
+        alternate_titles: series_titles,
+        book_id: book_id.to_string(),
+        book_title: book_name,
+        oneshot,
+        numbers,
+        isbn: book_metadata_str(&book, "isbn"),
+        page: page_num,
+        pages: pages_count,
+        percent,
+        percent_only: media_profile == Some("EPUB") || webtoon,
+        library: library_name.clone(),
+        formats: Vec::new(),
+        media_profile: media_profile.map(|s| s.to_string()),
+        read_at: last_modified,
+        authors,
+        publisher: series_metadata_str("publisher"),
+        release_date: book_metadata_str(&book, "releaseDate"),
+        status: series_metadata_str("status"),
+        read_list,
+        buttons,
+        tags: Vec::new(),
+        context: serde_json::json!({ "book": book, "series": series_json, "library": library_name }),
+    };
+    let Some(mut presence) = reading.lay_out(config, previous) else {
+        return Ok(None);
+    };
+
+    // A curated asset uploaded to the Discord application takes precedence over the Komga thumbnail
+    presence.large_image = match asset_key_for(config, series_id, &presence.series_title, presence.library.as_deref()) {
+        Some(key) if !skip_cover => Some(key.to_string()),
+        _ => get_komga_cover_path(client, config, series_id, series_version, imgur_cache, skip_cover).await?,
+    };
+    // Komf's cover is already public, so it can stand in when Komga's can't be shown
+    if presence.large_image.is_none() && !skip_cover {
+        presence.large_image = komf_match.and_then(|found| found.image_url);
+    }
+    Ok(Some(presence))
+}
+
+// Whole percent through an EPUB, from the position its reader last saved with Komga
+async fn epub_percent(client: &Client, config: &Config, book_id: &str) -> Option<u32> {
+    let url = format!("{}/api/v1/books/{}/progression", config.komga_url, book_id);
+    let progression: serde_json::Value = komga_get(client, config, &url).await.ok()?.error_for_status().ok()?.json().await.ok()?;
+    let total = progression.get("locator")?.get("locations")?.get("totalProgression")?.as_f64()?;
+    Some((total.clamp(0.0, 1.0) * 100.0).round() as u32)
+}
+
+// When the book's read progress last moved
+fn progress_modified(config: &Config, book: &serde_json::Value) -> Option<chrono::DateTime<Utc>> {
+    let last_modified = book.get("readProgress")?.get("lastModified")?.as_str()?;
+    timestamps::parse(config, last_modified)
+}
+
+// Several books read at once (phone and tablet, or a shared account): the most recently active
+// one, or with concurrent_books "rotate", each in turn. Ties go by book ID, so the choice doesn't
+// flap between polls.
+fn pick_active_book(config: &Config, mut active: Vec<(chrono::DateTime<Utc>, serde_json::Value)>, previous: Option<&Presence>) -> serde_json::Value {
+    fn id(book: &serde_json::Value) -> &str {
+        book.get("id").and_then(|v| v.as_str()).unwrap_or("")
+    }
+    if active.len() > 1 {
+        debug!(books = ?active.iter().map(|(_, book)| id(book)).collect::<Vec<_>>(), "Several books are being read at once");
+    }
+    if config.concurrent_books.as_deref() == Some("rotate") {
+        active.sort_by(|(_, a), (_, b)| id(a).cmp(id(b)));
+        let next = previous.and_then(|p| active.iter().position(|(_, book)| id(book) == p.book_id)).map_or(0, |i| (i + 1) % active.len());
+        return active.swap_remove(next).1;
+    }
+    active.sort_by(|(a_time, a), (b_time, b)| b_time.cmp(a_time).then_with(|| id(a).cmp(id(b))));
+    active.swap_remove(0).1
+}
+
+// The read list the book is in (the first of `read_lists` if set), with the book's 1-based
+// position in it and the list's length
+async fn read_list_position(client: &Client, config: &Config, book_id: &str) -> Option<(String, usize, usize)> {
+    let url = format!("{}/api/v1/books/{}/readlists", config.komga_url, book_id);
+    let lists: Vec<serde_json::Value> = komga_get(client, config, &url).await.ok()?.error_for_status().ok()?.json().await.ok()?;
+    let rank = |name: &str| match config.read_lists {
+        Some(ref wanted) => wanted.iter().position(|w| w.eq_ignore_ascii_case(name)),
+        None => Some(0),
+    };
+    let (_, list) = lists
+        .iter()
+        .filter_map(|list| Some((rank(list.get("name")?.as_str()?)?, list)))
+        .min_by_key(|(rank, _)| *rank)?;
+    let book_ids = list.get("bookIds")?.as_array()?;
+    let position = book_ids.iter().position(|id| id.as_str() == Some(book_id))? + 1;
+    Some((list.get("name")?.as_str()?.to_string(), position, book_ids.len()))
+}
+
+// The series title in the first `title_language` that the server has an alternate title for,
+// else its main title, skipping non-Latin scripts if asked to and another title is available
+fn preferred_title(config: &Config, alternates: &[(String, String)], title: String) -> String {
+    if config.title_language.is_none() && !config.latin_titles_only.unwrap_or(false) {
+        return title;
+    }
+    let preferred = config.title_language.iter().flatten().filter_map(|language| {
+        alternates.iter().find(|(label, _)| label.eq_ignore_ascii_case(language)).map(|(_, title)| title.as_str())
+    });
+    let mut candidates: Vec<&str> = preferred.chain([title.as_str()]).chain(alternates.iter().map(|(_, title)| title.as_str())).collect();
+    if config.latin_titles_only.unwrap_or(false) {
+        candidates.retain(|t| is_latin(t));
+    }
+    candidates.first().map(|t| t.to_string()).unwrap_or(title)
+}
+
+// Every title Komga has for the series, each with its language label: the main title (which has
+// none) and then the alternates
+fn series_titles(series_json: &serde_json::Value) -> Vec<(String, String)> {
+    let metadata = series_json.get("metadata");
+    let main = metadata.and_then(|m| m.get("title")).and_then(|v| v.as_str()).map(|title| (String::new(), title.to_string()));
+    let alternates = metadata.and_then(|m| m.get("alternateTitles")).and_then(|a| a.as_array()).into_iter().flatten();
+    main.into_iter().chain(alternates.filter_map(|t| Some((t.get("label")?.as_str()?.to_string(), t.get("title")?.as_str()?.to_string())))).collect()
+}
+
+// Letters are all Latin (including accented), so the title reads for anyone
+fn is_latin(text: &str) -> bool {
+    text.chars().filter(|c| c.is_alphabetic()).all(|c| matches!(c, 'A'..='Z' | 'a'..='z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}'))
+}
+
+// Template values every presence has; build_presence adds the ones only it knows
+fn template_vars(config: &Config, presence: &Presence) -> template::Vars {
+    let mut vars = template::Vars::new();
+    vars.insert("series", presence.series_title.clone());
+    for (name, value) in [
+        ("title", &presence.book_title),
+        ("number", &presence.book_number),
+        ("volume", &presence.volume),
+        ("chapter", &presence.chapter),
+        ("library", &presence.library),
+    ] {
+        if let Some(value) = value {
+            vars.insert(name, value.clone());
+        }
+    }
+    if config.show_page_numbers.unwrap_or(true) {
+        if let Some(page) = presence.page {
+            vars.insert("page", page.to_string());
+        }
+        if let Some(pages) = presence.pages {
+            vars.insert("pages", pages.to_string());
+        }
+    }
+    if let Some(percent) = presence.percent {
+        vars.insert("percent", percent.to_string());
+    }
+    vars
+}
+
+// Whether the reader is on a two-page spread, from the page before (and whether that was one)
+// and the page now. Only a step of exactly two pages starts a spread, since several single
+// pages can go by between two polls; an even jump keeps one going, and a poll that finds the
+// same page keeps whatever was decided before.
+fn is_spread(before: Option<(u32, bool)>, page: Option<u32>) -> bool {
+    match (before, page) {
+        (Some((before, was_spread)), Some(page)) if page == before => was_spread,
+        (Some((before, _)), Some(page)) if page == before + 2 => true,
+        (Some((before, was_spread)), Some(page)) => was_spread && page > before && (page - before) % 2 == 0,
+        _ => false,
+    }
+}
+
+// The exclude_formats entry the book matches: its media profile ("pdf"), media type
+// ("application/pdf", or just "pdf"), or file extension ("cbr")
+fn excluded_format<'a>(exclude_formats: &'a [String], book: &serde_json::Value) -> Option<&'a str> {
+    let media = book.get("media");
+    let profile = media.and_then(|m| m.get("mediaProfile")).and_then(|v| v.as_str());
+    let media_type = media.and_then(|m| m.get("mediaType")).and_then(|v| v.as_str());
+    let subtype = media_type.and_then(|t| t.rsplit('/').next());
+    let extension = book.get("url").and_then(|v| v.as_str()).and_then(|url| std::path::Path::new(url).extension()?.to_str());
+    let formats = [profile, media_type, subtype, extension];
+    exclude_formats
+        .iter()
+        .find(|excluded| formats.iter().flatten().any(|format| format.eq_ignore_ascii_case(excluded.trim_start_matches('.'))))
+        .map(|s| s.as_str())
+}
+
+fn book_metadata_str(book: &serde_json::Value, field: &str) -> Option<String> {
+    book.get("metadata").and_then(|m| m.get(field)).and_then(|v| v.as_str()).filter(|s| !s.is_empty()).map(|s| s.to_string())
+}
+
+// Bots see Invisible as "offline", so that is what "invisible" in the config matches
+fn is_status_suppressed(config: &Config, status: Option<&str>) -> bool {
+    let Some(status) = status else {
+        return false;
     };
+    let default_statuses = vec!["dnd".to_string(), "invisible".to_string()];
+    config.suppress_on_status.as_ref().unwrap_or(&default_statuses).iter().any(|s| {
+        let s = if s.eq_ignore_ascii_case("invisible") { "offline" } else { s.as_str() };
+        s.eq_ignore_ascii_case(status)
+    })
+}
 
-    // Details: series title (first line)
-    let details = series_title.clone();
-    // State: book number and page (second line)
-    let mut book_number_str = String::new();
-    // Prefer metadata.number (string or number)
-    if let Some(meta) = book.get("metadata") {
-        if let Some(num_str) = meta.get("number").and_then(|v| v.as_str()) {
-            if !num_str.is_empty() {
-                book_number_str = format!("Book {}", num_str);
+// Pick the Discord application for the active library, then the book's media profile,
+// falling back to the default client ID
+fn select_client_id<'a>(config: &'a Config, library_name: Option<&str>, media_profile: Option<&str>) -> &'a str {
+    for (map, key) in [(&config.library_client_ids, library_name), (&config.media_profile_client_ids, media_profile)] {
+        if let (Some(map), Some(key)) = (map, key) {
+            if let Some((_, id)) = map.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+                return id;
             }
-        } else if let Some(num) = meta.get("number").and_then(|v| v.as_u64()) {
-            book_number_str = format!("Book {}", num);
-        } else if let Some(num) = book.get("number").and_then(|v| v.as_u64()) {
-            book_number_str = format!("Book {}", num);
-        }
-    } else if let Some(num) = book.get("number").and_then(|v| v.as_u64()) {
-        book_number_str = format!("Book {}", num);
-    }
-    let mut state = book_number_str;
-    if let Some(page_num) = page_num {
-        if !state.is_empty() {
-            state = format!("{} (Page {})", state, page_num);
-        } else {
-            state = format!("Page {}", page_num);
         }
     }
-    if state.is_empty() {
-        state = "Komga-RPC".to_string();
+    &config.discord_client_id
+}
+
+// Names credited in one of `author_roles` (all of them without it), ordered by role as listed
+// there, without repeats
+fn select_authors(config: &Config, credits: Vec<(String, Option<String>)>) -> Vec<String> {
+    let rank = |role: &Option<String>| match (&config.author_roles, role) {
+        (None, _) => Some(0),
+        (Some(roles), Some(role)) => roles.iter().position(|r| r.eq_ignore_ascii_case(role)),
+        (Some(_), None) => None,
+    };
+    let mut ranked: Vec<(usize, String)> = credits.into_iter().filter_map(|(name, role)| Some((rank(&role)?, name))).collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+    let mut names: Vec<String> = Vec::new();
+    for (_, name) in ranked {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// "A, B +3 more" past `max_authors`. With no one credited, `author_fallback`, else the library
+// name, else "Unknown Author". Nothing at all with hide_authors.
+fn describe_authors(config: &Config, authors: &[String], library_name: Option<&str>) -> String {
+    if config.hide_authors.unwrap_or(false) {
+        return String::new();
+    }
+    if authors.is_empty() {
+        return match (&config.author_fallback, library_name) {
+            (Some(fallback), _) => fallback.clone(),
+            (None, Some(library_name)) => library_name.to_string(),
+            (None, None) => locale::text("unknown-author", &[]),
+        };
+    }
+    let shown = config.max_authors.unwrap_or(usize::MAX).max(1);
+    if authors.len() <= shown {
+        return authors.join(", ");
     }
-    let large_text = &details;
+    locale::text("more-authors", &[("names", &authors[..shown].join(", ")), ("count", &(authors.len() - shown))])
+}
 
-    let activity_builder = activity::Activity::new()
-        .details(&details)
-        .state(&state)
-        .activity_type(activity::ActivityType::Playing);
+// Library mappings win over media profile mappings, which win over number_format
+fn select_number_format<'a>(config: &'a Config, library_name: Option<&str>, media_profile: Option<&str>) -> Option<&'a str> {
+    for (map, key) in [(&config.library_number_formats, library_name), (&config.media_profile_number_formats, media_profile)] {
+        if let (Some(map), Some(key)) = (map, key) {
+            if let Some((_, format)) = map.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+                return Some(format);
+            }
+        }
+    }
+    config.number_format.as_deref()
+}
 
-    let cover_url = get_komga_cover_path(client, config, &series_id, imgur_cache, skip_cover).await?;
+// Komga reports read progress only for the user an API key belongs to, even to admins, so
+// komga_user can't widen what is seen; make sure the key is that user's rather than quietly
+// showing someone else's reading
+async fn check_komga_user(client: &Client, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(ref wanted) = config.komga_user else {
+        return Ok(());
+    };
+    let me_url = format!("{}/api/v2/users/me", config.komga_url);
+    let me: serde_json::Value = komga_get(client, config, &me_url).await?.error_for_status()?.json().await?;
+    let is = |user: &serde_json::Value| ["email", "id"].iter().any(|field| user.get(field).and_then(|v| v.as_str()).is_some_and(|v| v.eq_ignore_ascii_case(wanted)));
+    if is(&me) {
+        info!(server = %config.komga_url, user = %wanted, "Showing reading for Komga user");
+        return Ok(());
+    }
+    let owner = me.get("email").and_then(|v| v.as_str()).unwrap_or("another user");
+    // An admin key can at least tell whether the user exists
+    let users_url = format!("{}/api/v2/users", config.komga_url);
+    let exists = match komga_get(client, config, &users_url).await.map(|r| r.error_for_status()) {
+        Ok(Ok(response)) => response.json::<Vec<serde_json::Value>>().await.ok().map(|users| users.iter().any(is)),
+        _ => None,
+    };
+    let hint = match exists {
+        Some(false) => format!("there is no Komga user '{}' on {}", wanted, config.komga_url),
+        _ => format!("Komga only shares a user's read progress with their own API keys; create one while logged in as '{}'", wanted),
+    };
+    Err(exit::with(exit::CONFIG)(format!("komga_user is '{}' but the API key for {} belongs to {}: {}", wanted, config.komga_url, owner, hint)))
+}
 
-    let final_activity = if let Some(ref url) = cover_url {
-        activity_builder.assets(
-            activity::Assets::new()
-                .large_image(url)
-                .large_text(large_text)
+// Make sure the API key can read what build_presence needs, and explain what's missing when it
+// can't, rather than quietly showing nothing forever. Only Komga turning the key away is fatal: a
+// user who can see no books is warned about, since libraries may be shared or scanned later, and
+// a Komga that can't be reached yet (say, at boot) is left to the polling loop.
+async fn check_komga_access(client: &Client, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let probe = async {
+        let me_url = format!("{}/api/v2/users/me", config.komga_url);
+        let me_response = komga_get(client, config, &me_url).await?;
+        let books_url = format!("{}/api/v1/books?size=1", config.komga_url);
+        let books_response = komga_get(client, config, &books_url).await?;
+        Ok::<_, Box<dyn std::error::Error>>((me_response, books_response))
+    };
+    let (me_response, books_response) = match probe.await {
+        Ok(responses) => responses,
+        Err(e) => {
+            warn!(server = %config.komga_url, error = %e, "Couldn't reach Komga to check what the API key can read");
+            return Ok(());
+        }
+    };
+    if me_response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(exit::with(exit::NO_PERMISSION)(format!(
+            "Komga at {} doesn't accept komga_api_key; it may be mistyped or revoked. Create a new one in Komga under Account Settings > API Keys",
+            config.komga_url
+        )));
+    }
+    let me: serde_json::Value = me_response.json().await.unwrap_or_default();
+    let user = me.get("email").and_then(|v| v.as_str()).unwrap_or("the API key's user").to_string();
+    if books_response.status() == reqwest::StatusCode::FORBIDDEN {
+        return Err(exit::with(exit::NO_PERMISSION)(format!(
+            "Komga lets {} sign in but not list books, so nothing could ever be shown. The API key needs a user with access to at least one library; \
+             an admin can share libraries under Server Settings > Users > {} > Edit shared libraries",
+            user, user
+        )));
+    }
+    let books: serde_json::Value = match books_response.error_for_status() {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        Err(e) => {
+            warn!(server = %config.komga_url, error = %e, "Couldn't check what the API key can read");
+            return Ok(());
+        }
+    };
+    if books.get("totalElements").and_then(|v| v.as_u64()) != Some(0) {
+        return Ok(());
+    }
+
+    let libraries_url = format!("{}/api/v1/libraries", config.komga_url);
+    let libraries: Vec<serde_json::Value> = match komga_get(client, config, &libraries_url).await {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    let restricted = me.get("labelsAllow").and_then(|v| v.as_array()).is_some_and(|labels| !labels.is_empty())
+        || me.get("ageRestriction").is_some_and(|v| !v.is_null());
+    let hint = if libraries.is_empty() && me.get("sharedAllLibraries").and_then(|v| v.as_bool()) == Some(false) {
+        format!("no libraries are shared with {}; an admin can share them under Server Settings > Users > {} > Edit shared libraries", user, user)
+    } else if libraries.is_empty() {
+        "Komga has no libraries yet".to_string()
+    } else if restricted {
+        format!(
+            "{} can see {} libraries, but their content restrictions (allowed labels or age rating) hide every book; an admin can relax them under Server Settings > Users > {} > Edit content restrictions",
+            user,
+            libraries.len(),
+            user
         )
     } else {
-        activity_builder
+        format!("the {} libraries {} can see have no books yet", libraries.len(), user)
     };
-
-    discord.set_activity(final_activity)?;
-    timing_info.last_api_time = Some(SystemTime::now());
+    warn!(server = %config.komga_url, "The API key can't see any books, so nothing will be shown: {}", hint);
     Ok(())
 }
 
+async fn komga_get(client: &Client, config: &Config, url: &str) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    komga_request(client, config, reqwest::Method::GET, url).await
+}
+
+// Every Komga request goes through here so each API call gets its own span, and so read_only
+// can refuse anything that could change Komga
+#[tracing::instrument(skip(client, config), fields(status))]
+async fn komga_request(client: &Client, config: &Config, method: reqwest::Method, url: &str) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    if config.read_only.unwrap_or(false) && method != reqwest::Method::GET {
+        return Err(format!("read_only is set, refusing to send {} {} to Komga", method, url).into());
+    }
+    let started = Instant::now();
+    let response = retry::send(config, method.is_idempotent(), || client.request(method.clone(), url).header("X-API-Key", &config.komga_api_key)).await;
+    stats::record_latency(&stats::endpoint_key(method.as_str(), url), started.elapsed());
+    let response = response?;
+    tracing::Span::current().record("status", response.status().as_u16());
+    Ok(response)
+}
+
+// Look up a Discord asset key for the series (by ID or title), then for its library
+fn asset_key_for<'a>(config: &'a Config, series_id: &str, series_title: &str, library_name: Option<&str>) -> Option<&'a str> {
+    if let Some(ref series_assets) = config.series_assets {
+        if let Some((_, key)) = series_assets.iter().find(|(name, _)| *name == series_id || name.eq_ignore_ascii_case(series_title)) {
+            return Some(key);
+        }
+    }
+    if let (Some(library_assets), Some(lib_name)) = (&config.library_assets, library_name) {
+        if let Some((_, key)) = library_assets.iter().find(|(name, _)| name.eq_ignore_ascii_case(lib_name)) {
+            return Some(key);
+        }
+    }
+    None
+}
+
+#[tracing::instrument(skip(client, config, imgur_cache))]
 async fn get_komga_cover_path(
     client: &Client,
     config: &Config,
     series_id: &str,
-    imgur_cache: &mut HashMap<String, String>,
+    version: &str,
+    imgur_cache: &mut CoverCache,
     skip_cover: bool,
 ) -> Result<Option<String>, Box<dyn std::error::Error>> {
     if skip_cover {
         return Ok(None);
     }
     if config.use_imgur_cover.unwrap_or(true) {
-        if let Some(imgur_client_id) = &config.imgur_client_id {
+        if let Some(imgur_client_id) = config.imgur_client_id.clone() {
             let cache_key = format!("komga_{}", series_id);
             
-            // Check cache first
-            if let Some(cached_url) = imgur_cache.get(&cache_key) {
-                return Ok(Some(cached_url.clone()));
-            }            // Get cover from Komga - try /api/v1/series/{id}/thumbnail first, then fallback to Imgur
+            // Check cache first, then what earlier runs uploaded for this version of the series
+            if let Some(cached_url) = imgur_cache.lookup(&cache_key).or_else(|| metadata_cache::get("cover", &cache_key, Some(version))) {
+                return Ok(Some(cached_url));
+            }
+            // Get the cover from Komga's /api/v1/series/{id}/thumbnail and upload it to Imgur;
+            // a missing cover or a failed upload just means none is shown
+            let (client, config) = (client.clone(), config.clone());
             let cover_url = format!("{}/api/v1/series/{}/thumbnail", config.komga_url, series_id);
-            let response = client
-                .get(&cover_url)
-                .header("X-API-Key", &config.komga_api_key)
-                .send()
-                .await;
-
-            if let Ok(resp) = response {
-                let status = resp.status();
-                if status.is_success() {
-                    let cover_bytes = resp.bytes().await?;
-                    // Upload to Imgur
-                    if let Ok(imgur_url) = upload_to_imgur(client, imgur_client_id, &cover_bytes).await {
-                        imgur_cache.insert(cache_key, imgur_url.clone());
-                        return Ok(Some(imgur_url));
-                    }
-                }
-                // If we get a 404, just return None
-                if status == reqwest::StatusCode::NOT_FOUND {
-                    return Ok(None);
+            let (key, version) = (cache_key.clone(), version.to_string());
+            let upload = async move {
+                let response = komga_get(&client, &config, &cover_url).await.ok()?;
+                let cover_bytes = response.error_for_status().ok()?.bytes().await.ok()?;
+                let started = Instant::now();
+                let uploaded = upload_to_imgur(&client, &config, &imgur_client_id, &cover_bytes).await.ok();
+                stats::record_latency("Imgur upload", started.elapsed());
+                if let Some(ref url) = uploaded {
+                    metadata_cache::put("cover", &key, &version, url);
                 }
-                // For other errors, just return None
-                return Ok(None);
-            }
+                uploaded
+            };
+            return Ok(imgur_cache.upload(cache_key, upload).await);
         }
     }
 
@@ -689,24 +1918,26 @@ async fn get_komga_cover_path(
     Ok(None)
 }
 
+#[tracing::instrument(skip_all, fields(bytes = image_data.len()))]
 async fn upload_to_imgur(
     client: &Client,
+    config: &Config,
     client_id: &str,
     image_data: &[u8],
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let part = reqwest::multipart::Part::bytes(image_data.to_vec())
-        .file_name("cover.jpg")
-        .mime_str("image/jpeg")?;
-    
-    let form = reqwest::multipart::Form::new()
-        .part("image", part);
-
-    let response = client
-        .post("https://api.imgur.com/3/image")
-        .header("Authorization", format!("Client-ID {}", client_id))
-        .multipart(form)
-        .send()
-        .await?;
+    // A multipart body is a stream, so every attempt builds its own
+    let request = || {
+        let part = reqwest::multipart::Part::bytes(image_data.to_vec())
+            .file_name("cover.jpg")
+            .mime_str("image/jpeg")
+            .expect("image/jpeg is a valid MIME type");
+        client
+            .post("https://api.imgur.com/3/image")
+            .header("Authorization", format!("Client-ID {}", client_id))
+            .multipart(reqwest::multipart::Form::new().part("image", part))
+    };
+    // Not idempotent: sending an upload twice would put the cover on Imgur twice
+    let response = retry::send(config, false, request).await?;
 
     let status = response.status();
     if !status.is_success() {
@@ -759,4 +1990,36 @@ fn should_show_as_reading_with_timestamp(now: &SystemTime, position_timestamp: u
     } else {
         false
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_spread;
+
+    #[test]
+    fn two_page_step_is_a_spread() {
+        assert!(is_spread(Some((10, false)), Some(12)));
+        assert!(is_spread(Some((12, true)), Some(14)));
+    }
+
+    #[test]
+    fn several_single_pages_between_polls_are_not_a_spread() {
+        assert!(!is_spread(Some((10, false)), Some(14)));
+        assert!(!is_spread(Some((10, false)), Some(16)));
+        assert!(!is_spread(Some((10, false)), Some(11)));
+    }
+
+    #[test]
+    fn an_ongoing_spread_survives_even_jumps_only() {
+        assert!(is_spread(Some((10, true)), Some(14)));
+        assert!(!is_spread(Some((10, true)), Some(13)));
+        assert!(!is_spread(Some((10, true)), Some(8)));
+    }
+
+    #[test]
+    fn the_same_page_keeps_what_was_decided() {
+        assert!(is_spread(Some((10, true)), Some(10)));
+        assert!(!is_spread(Some((10, false)), Some(10)));
+        assert!(!is_spread(None, Some(10)));
+    }
+}
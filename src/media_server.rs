@@ -0,0 +1,336 @@
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder};
+use std::time::Instant;
+use tracing::info;
+
+use crate::calibre_web::CalibreWeb;
+use crate::cover_cache::CoverCache;
+use crate::kavita::Kavita;
+use crate::numbering::BookNumbers;
+use crate::suwayomi::Suwayomi;
+use crate::{asset_key_for, describe_authors, is_spread, locale, metadata_cache, preferred_title, retry, scripting, select_client_id, select_number_format, stats, template, template_vars, upload_to_imgur, Config, Layout, Presence, PresenceButton};
+
+/// The server books are read on, picked by `server_type`. Komga is the default; the others
+/// reuse `komga_url` and `komga_api_key` for their own URL and key.
+pub enum MediaServer {
+    Komga,
+    Kavita(Kavita),
+    CalibreWeb(CalibreWeb),
+    Suwayomi(Suwayomi),
+}
+
+impl MediaServer {
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        match config.server_type.as_deref() {
+            None | Some("komga") => Ok(MediaServer::Komga),
+            Some("kavita") => Ok(MediaServer::Kavita(Kavita::new(&config.komga_url, &config.komga_api_key))),
+            Some("calibre-web") => Ok(MediaServer::CalibreWeb(CalibreWeb::from_config(config))),
+            Some("suwayomi") => Ok(MediaServer::Suwayomi(Suwayomi::from_config(config))),
+            Some(other) => Err(format!("Unknown server_type '{}', expected komga, kavita, calibre-web or suwayomi", other).into()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaServer::Komga => "Komga",
+            MediaServer::Kavita(_) => "Kavita",
+            MediaServer::CalibreWeb(_) => "Calibre-Web",
+            MediaServer::Suwayomi(_) => "Suwayomi",
+        }
+    }
+
+    /// What should be shown for the book most recently read on this server, if anything.
+    pub async fn build_presence(
+        &self,
+        client: &Client,
+        config: &Config,
+        imgur_cache: &mut CoverCache,
+        previous: Option<&Presence>,
+    ) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+        match self {
+            MediaServer::Komga => crate::build_presence(client, config, imgur_cache, previous).await,
+            MediaServer::Kavita(kavita) => kavita.build_presence(client, config, imgur_cache, previous).await,
+            MediaServer::CalibreWeb(calibre_web) => calibre_web.build_presence(client, config, imgur_cache, previous).await,
+            MediaServer::Suwayomi(suwayomi) => suwayomi.build_presence(client, config, imgur_cache, previous).await,
+        }
+    }
+
+    /// Startup checks: that Komga's API key can read books, and komga_user, which only means
+    /// something to Komga.
+    pub async fn check_user(&self, client: &Client, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            MediaServer::Komga => {
+                crate::check_komga_access(client, config).await?;
+                crate::check_komga_user(client, config).await
+            }
+            MediaServer::Kavita(_) | MediaServer::CalibreWeb(_) | MediaServer::Suwayomi(_) => Ok(()),
+        }
+    }
+
+    /// Whether the book shown in `presence` has since been finished.
+    pub async fn completed(&self, client: &Client, config: &Config, presence: &Presence) -> Option<bool> {
+        match self {
+            MediaServer::Komga => crate::komga_completed(client, config, &presence.book_id).await,
+            MediaServer::Kavita(kavita) => kavita.completed(client, presence).await,
+            MediaServer::CalibreWeb(calibre_web) => calibre_web.completed(client, presence).await,
+            MediaServer::Suwayomi(suwayomi) => suwayomi.completed(client, presence).await,
+        }
+    }
+}
+
+/// Download a cover with `request` and re-host it on Imgur, since Discord can't reach a private
+/// server. Cached under `cache_key`, and on disk under `version` (whatever the server changes
+/// along with the cover, or "" if it has nothing); see `CoverCache::upload` for when the URL
+/// comes back.
+pub async fn rehost_cover(
+    client: &Client,
+    config: &Config,
+    cache_key: String,
+    version: &str,
+    request: RequestBuilder,
+    imgur_cache: &mut CoverCache,
+) -> Option<String> {
+    if !config.use_imgur_cover.unwrap_or(true) {
+        return None;
+    }
+    let imgur_client_id = config.imgur_client_id.clone()?;
+    if let Some(cached_url) = imgur_cache.lookup(&cache_key).or_else(|| metadata_cache::get("cover", &cache_key, Some(version))) {
+        return Some(cached_url);
+    }
+    let (client, config) = (client.clone(), config.clone());
+    let (key, version) = (cache_key.clone(), version.to_string());
+    let upload = async move {
+        let cover_bytes = retry::send_request(&config, request).await.ok()?.error_for_status().ok()?.bytes().await.ok()?;
+        let started = Instant::now();
+        let uploaded = upload_to_imgur(&client, &config, &imgur_client_id, &cover_bytes).await.ok();
+        stats::record_latency("Imgur upload", started.elapsed());
+        if let Some(ref url) = uploaded {
+            metadata_cache::put("cover", &key, &version, url);
+        }
+        uploaded
+    };
+    imgur_cache.upload(cache_key, upload).await
+}
+
+/// What a server says is being read, before it is laid out. Every server, Komga included, ends
+/// up here, so the lines read the same whichever one the book is on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub series_id: String,
+    pub series_title: String,
+    pub alternate_titles: Vec<(String, String)>, // Other titles for the series, each with its language label ("" if the server has none)
+    pub book_id: String,
+    pub book_title: Option<String>,
+    pub oneshot: bool, // A book with no series to speak of, shown by its own title
+    pub numbers: BookNumbers,
+    pub isbn: Option<String>,
+    pub page: Option<u32>,
+    pub pages: Option<u32>,
+    pub percent: Option<u32>,
+    pub percent_only: bool, // Page numbers mean little here (EPUB positions, webtoon strips), whatever progress_display says
+    pub library: Option<String>,
+    pub formats: Vec<String>, // Names exclude_formats can match, besides the media profile
+    pub media_profile: Option<String>, // The Komga media profile it reads like: DIVINA, EPUB or PDF
+    pub read_at: Option<DateTime<Utc>>,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    pub release_date: Option<String>,
+    pub status: Option<String>, // The series' publication status: ENDED, ONGOING, ABANDONED or HIATUS
+    pub read_list: Option<(String, usize, usize)>, // Name, position in it and size of the read list the book is on
+    pub buttons: Vec<PresenceButton>,
+    pub tags: Vec<String>,
+    pub context: serde_json::Value, // What presence_script sees alongside the presence
+}
+
+impl Reading {
+    /// Whether nocover_tags hides the cover.
+    pub fn hides_cover(&self, config: &Config) -> bool {
+        self.tagged(&config.nocover_tags)
+    }
+
+    fn tagged(&self, wanted: &Option<Vec<String>>) -> bool {
+        wanted.iter().flatten().any(|w| self.tags.iter().any(|tag| w.eq_ignore_ascii_case(tag)))
+    }
+
+    /// The presence after the library, format and tag filters, with a Discord asset for the
+    /// cover if one is configured. None when a filter or the presence script hides it.
+    pub fn into_presence(self, config: &Config, previous: Option<&Presence>) -> Option<Presence> {
+        if let (Some(exclude_libraries), Some(lib_name)) = (&config.exclude_libraries, &self.library) {
+            if exclude_libraries.iter().any(|ex| ex.eq_ignore_ascii_case(lib_name)) {
+                info!(library = %lib_name, "Skipping excluded library");
+                return None;
+            }
+        }
+        if let Some(ref exclude_formats) = config.exclude_formats {
+            let formats = || self.formats.iter().chain(&self.media_profile).map(|f| f.as_str());
+            if let Some(excluded) = exclude_formats.iter().find(|ex| formats().any(|f| ex.eq_ignore_ascii_case(f))) {
+                info!(format = %excluded, "Skipping excluded file format");
+                return None;
+            }
+        }
+        if self.tagged(&config.exclude_tags) {
+            info!("Skipping excluded book by tag");
+            return None;
+        }
+        let hides_cover = self.hides_cover(config);
+        let mut presence = self.lay_out(config, previous)?;
+        if !hides_cover {
+            presence.large_image = asset_key_for(config, &presence.series_id, &presence.series_title, presence.library.as_deref()).map(|key| key.to_string());
+        }
+        Some(presence)
+    }
+
+    /// Lay the book out as Discord lines: progress, templates and the show_* additions, then the
+    /// presence script. `previous` is what was last shown, for telling a spread. The cover is
+    /// left to the caller. None when the presence script hides the book.
+    pub fn lay_out(self, config: &Config, previous: Option<&Presence>) -> Option<Presence> {
+        let source = Box::new(self.clone());
+        let series_title = preferred_title(config, &self.alternate_titles, self.series_title.clone());
+        let author_text = describe_authors(config, &self.authors, self.library.as_deref());
+        let media_profile = self.media_profile.as_deref();
+        let layout = Layout::for_media_profile(config, media_profile);
+        let display = if self.percent_only { "percent" } else { layout.progress_display.as_deref().unwrap_or("page") };
+        // In dual-page mode the reader moves two pages at a time; show both pages of the spread
+        let last_page = previous.filter(|p| p.book_id == self.book_id).and_then(|p| Some((p.page?, p.spread)));
+        let spread = config.detect_spreads.unwrap_or(false) && is_spread(last_page, self.page);
+        let page_label = |page: u32| match self.pages {
+            Some(pages) if spread && page < pages => locale::text("pages-spread", &[("first", &page), ("last", &(page + 1))]),
+            _ => locale::text("page", &[("page", &page)]),
+        };
+        let progress = match (display, self.page.filter(|_| config.show_page_numbers.unwrap_or(true)), self.percent) {
+            ("percent", _, Some(percent)) => Some(locale::text("percent-read", &[("percent", &percent)])),
+            ("both", Some(page), Some(percent)) => Some(format!("{} · {}", page_label(page), locale::text("percent", &[("percent", &percent)]))),
+            (_, Some(page), _) => Some(page_label(page)),
+            // A hidden page number isn't swapped for a percentage nobody asked for
+            (_, None, Some(percent)) if self.page.is_none() || display != "page" => Some(locale::text("percent-read", &[("percent", &percent)])),
+            _ => None,
+        };
+        let mut state = self.numbers.describe(select_number_format(config, self.library.as_deref(), media_profile)).unwrap_or_default();
+        if let Some(ref progress) = progress {
+            state = if state.is_empty() { progress.clone() } else { format!("{} ({})", state, progress) };
+        }
+        if state.is_empty() {
+            state = "Komga-RPC".to_string();
+        }
+        // A one-shot has no series to speak of: its title goes first and its author second
+        let details = match self.book_title.clone() {
+            Some(title) if self.oneshot => {
+                state = match (author_text.is_empty(), progress) {
+                    (false, Some(progress)) => format!("{} ({})", author_text, progress),
+                    (false, None) => author_text.clone(),
+                    (true, progress) => progress.unwrap_or_default(),
+                };
+                title
+            }
+            _ => series_title.clone(),
+        };
+
+        // Every title the series has besides the one shown
+        let mut alternate_titles: Vec<String> = Vec::new();
+        for title in [self.series_title.as_str()].into_iter().chain(self.alternate_titles.iter().map(|(_, title)| title.as_str())) {
+            if !title.is_empty() && title != series_title && !alternate_titles.iter().any(|t| t == title) {
+                alternate_titles.push(title.to_string());
+            }
+        }
+        let mut presence = Presence {
+            client_id: select_client_id(config, self.library.as_deref(), media_profile).to_string(),
+            details,
+            state,
+            large_image: None,
+            large_text: series_title.clone(),
+            small_image: None,
+            small_text: None,
+            buttons: self.buttons,
+            start_timestamp: None,
+            end_timestamp: None,
+            series_id: self.series_id,
+            series_title,
+            komga_url: config.komga_url.clone(),
+            read_at: self.read_at,
+            alternate_titles,
+            book_id: self.book_id,
+            book_title: self.book_title,
+            book_number: self.numbers.number,
+            volume: self.numbers.volume,
+            chapter: self.numbers.chapter,
+            isbn: self.isbn,
+            page: self.page,
+            pages: self.pages,
+            percent: self.percent,
+            spread,
+            library: self.library,
+            media_profile: self.media_profile,
+            reading: Some(source),
+        };
+
+        let mut vars = template_vars(config, &presence);
+        vars.insert("author", author_text.clone());
+        if let Some(publisher) = self.publisher.filter(|s| !s.is_empty()) {
+            vars.insert("publisher", publisher);
+        }
+        if let Some(date) = self.release_date {
+            vars.insert("year", date.chars().take(4).collect());
+            vars.insert("release_date", date);
+        }
+        if let Some((name, position, size)) = self.read_list {
+            vars.insert("read_list", name);
+            vars.insert("read_list_position", position.to_string());
+            vars.insert("read_list_size", size.to_string());
+        }
+        if let Some(status) = self.status.filter(|s| !s.is_empty()) {
+            let status = match status.as_str() {
+                "ENDED" | "ONGOING" | "ABANDONED" | "HIATUS" => locale::text(&format!("status-{}", status.to_lowercase()), &[]),
+                _ => status,
+            };
+            vars.insert("status", status);
+        }
+        // With a template, {status} goes wherever the template puts it
+        if config.show_series_status.unwrap_or(false) && layout.details_template.is_none() {
+            if let Some(status) = vars.get("status") {
+                presence.details = format!("{} ({})", presence.details, status);
+            }
+        }
+        let lines = [(&layout.details_template, &mut presence.details), (&layout.state_template, &mut presence.state), (&layout.hover_template, &mut presence.large_text)];
+        for (template, line) in lines {
+            if let Some(template) = template {
+                let text = template::render(template, &vars);
+                // An empty line would be rejected by Discord; keep the default instead
+                if !text.trim().is_empty() {
+                    *line = text;
+                }
+            }
+        }
+
+        if let Some(placement) = config.show_author.as_ref().filter(|_| !author_text.is_empty()) {
+            presence.append(placement, &author_text);
+        }
+        if let (Some(placement), Some(name)) = (&config.show_read_list, vars.get("read_list")) {
+            let text = format!("{} · {}/{}", name, vars["read_list_position"], vars["read_list_size"]);
+            presence.append(placement, &text);
+        }
+        if let Some(ref placement) = config.show_publisher {
+            let text = [vars.get("publisher"), vars.get("year")].into_iter().flatten().cloned().collect::<Vec<_>>().join(" · ");
+            if !text.is_empty() {
+                presence.append(placement, &text);
+            }
+        }
+
+        if config.presence_script.is_some() {
+            let mut context = self.context;
+            context["presence"] = serde_json::json!(presence);
+            if !scripting::apply(context, &mut presence) {
+                info!("Presence script chose to hide this book");
+                return None;
+            }
+        }
+        Some(presence)
+    }
+}
+
+/// Whether the layout needs the book's read list: show_read_list, or a template using it. Worth
+/// knowing before asking the server.
+pub fn wants_read_list(config: &Config, media_profile: Option<&str>) -> bool {
+    let layout = Layout::for_media_profile(config, media_profile);
+    config.show_read_list.is_some()
+        || [&layout.details_template, &layout.state_template, &layout.hover_template].into_iter().flatten().any(|t| t.contains("{read_list"))
+}
@@ -0,0 +1,65 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn};
+
+use crate::{stats, Config};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS metadata (
+    kind TEXT NOT NULL,
+    id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (kind, id)
+);
+";
+
+static CACHE: OnceLock<Mutex<Connection>> = OnceLock::new();
+
+/// Open the on-disk cache (`metadata_cache_db`) of what's slow to look up again after a restart:
+/// uploaded cover URLs, Komf's title matches and library names. Without it, `get` finds nothing
+/// and `put` does nothing.
+pub fn open(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(ref path) = config.metadata_cache_db else {
+        return Ok(());
+    };
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    info!(%path, "Caching metadata on disk");
+    let _ = CACHE.set(Mutex::new(conn));
+    Ok(())
+}
+
+/// What was stored for `id`, as long as it was stored for `version` (the thing's lastModified).
+/// With no version, whatever was stored last.
+pub fn get(kind: &'static str, id: &str, version: Option<&str>) -> Option<String> {
+    let conn = CACHE.get()?.lock().unwrap();
+    let found = conn
+        .query_row("SELECT version, value FROM metadata WHERE kind = ?1 AND id = ?2", params![kind, id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .optional()
+        .unwrap_or_else(|e| {
+            warn!(%kind, error = %e, "Failed to read the metadata cache");
+            None
+        })
+        .filter(|(stored, _)| version.is_none_or(|version| version == stored))
+        .map(|(_, value)| value);
+    stats::record_cache("Metadata on disk", found.is_some());
+    found
+}
+
+/// Store `value` for `id` as of `version`, replacing whatever an older version left.
+pub fn put(kind: &'static str, id: &str, version: &str, value: &str) {
+    let Some(conn) = CACHE.get() else {
+        return;
+    };
+    let result = conn.lock().unwrap().execute(
+        "INSERT INTO metadata (kind, id, version, value) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (kind, id) DO UPDATE SET version = excluded.version, value = excluded.value",
+        params![kind, id, version, value],
+    );
+    if let Err(e) = result {
+        warn!(%kind, error = %e, "Failed to write the metadata cache");
+    }
+}
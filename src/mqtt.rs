@@ -0,0 +1,123 @@
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::{Config, Presence};
+
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_TOPIC: &str = "komga-rpc";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// Publishes reading state changes to an MQTT broker for home automation.
+/// The retained `<topic>/state` message always holds the latest state, so late subscribers
+/// see it immediately; its `event` field says what changed: "started", "updated" or "stopped".
+/// `<topic>/availability` is "online" while connected and "offline" (as the last will) otherwise.
+pub struct MqttPublisher {
+    client: AsyncClient,
+    topic: String,
+}
+
+impl MqttPublisher {
+    /// Start the connection in the background; None when no `mqtt_host` is configured.
+    pub fn spawn(config: &Config) -> Option<Self> {
+        let host = config.mqtt_host.as_ref()?;
+        let topic = config.mqtt_topic.clone().unwrap_or_else(|| DEFAULT_TOPIC.to_string());
+        let availability_topic = format!("{}/availability", topic);
+        let mut options = MqttOptions::new("komga-rpc", host, config.mqtt_port.unwrap_or(DEFAULT_PORT));
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(&availability_topic, "offline", QoS::AtLeastOnce, true));
+        if let Some(ref username) = config.mqtt_username {
+            options.set_credentials(username, config.mqtt_password.as_deref().unwrap_or(""));
+        }
+
+        let discovery_prefix = config.mqtt_homeassistant_discovery.unwrap_or(false).then(|| {
+            config.mqtt_discovery_prefix.clone().unwrap_or_else(|| DEFAULT_DISCOVERY_PREFIX.to_string())
+        });
+        let (client, mut eventloop) = AsyncClient::new(options, 16);
+        let announcer = client.clone();
+        let announce_topic = topic.clone();
+        // The event loop drives the connection and reconnects on the next poll after a failure
+        tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    // Announce on every (re)connect, since a clean session forgets our subscriptions
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        queue(&announcer, &availability_topic, "online".to_string());
+                        if let Some(ref prefix) = discovery_prefix {
+                            if let Err(e) = announcer.try_subscribe(format!("{}/status", prefix), QoS::AtLeastOnce) {
+                                warn!(error = %e, "Failed to subscribe to Home Assistant status");
+                            }
+                            publish_discovery(&announcer, prefix, &announce_topic);
+                        }
+                    }
+                    // Home Assistant announces "online" after it restarts and has forgotten every entity
+                    Ok(Event::Incoming(Packet::Publish(message))) if message.payload.as_ref() == b"online" => {
+                        if let Some(ref prefix) = discovery_prefix {
+                            publish_discovery(&announcer, prefix, &announce_topic);
+                        }
+                    }
+                    Ok(event) => debug!(?event, "MQTT event"),
+                    Err(e) => {
+                        warn!(error = %e, "MQTT connection failed, retrying");
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            }
+        });
+        Some(MqttPublisher { client, topic })
+    }
+
+    /// Publish the new state if it differs from `previous`. Never blocks the polling loop;
+    /// a full queue (broker unreachable for a while) drops the update.
+    pub fn publish_change(&self, previous: Option<&Presence>, current: Option<&Presence>) {
+        let Some(event) = crate::reading_event(previous, current) else {
+            return;
+        };
+        let payload = crate::event_payload(event, current);
+        queue(&self.client, &format!("{}/state", self.topic), payload.to_string());
+    }
+}
+
+// Retained publish that never waits on the event loop (it may be the caller)
+fn queue(client: &AsyncClient, topic: &str, payload: String) {
+    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload) {
+        warn!(%topic, error = %e, "Failed to queue MQTT message");
+    }
+}
+
+// Home Assistant MQTT discovery: a "Currently reading" sensor (series title, with the rest of
+// the state as attributes) and a "Reading" binary sensor, grouped under one device
+fn publish_discovery(client: &AsyncClient, prefix: &str, topic: &str) {
+    let device = json!({
+        "identifiers": ["komga_rpc"],
+        "name": "Komga RPC",
+        "manufacturer": "Komga-RPC",
+        "sw_version": crate::CURRENT_VERSION,
+    });
+    let state_topic = format!("{}/state", topic);
+    let availability_topic = format!("{}/availability", topic);
+
+    let sensor = json!({
+        "name": "Currently reading",
+        "unique_id": "komga_rpc_currently_reading",
+        "state_topic": state_topic,
+        "value_template": "{{ value_json.series if value_json.reading else 'Nothing' }}",
+        "json_attributes_topic": state_topic,
+        "json_attributes_template": "{{ {'series': value_json.series, 'book_number': value_json.book_number, 'page': value_json.page, 'library': value_json.library, 'cover_url': value_json.cover_url} | tojson }}",
+        "availability_topic": availability_topic,
+        "icon": "mdi:book-open-page-variant",
+        "device": device,
+    });
+    let binary_sensor = json!({
+        "name": "Reading",
+        "unique_id": "komga_rpc_reading",
+        "state_topic": state_topic,
+        "value_template": "{{ 'ON' if value_json.reading else 'OFF' }}",
+        "availability_topic": availability_topic,
+        "icon": "mdi:book-open-variant",
+        "device": device,
+    });
+    queue(client, &format!("{}/sensor/komga_rpc/currently_reading/config", prefix), sensor.to_string());
+    queue(client, &format!("{}/binary_sensor/komga_rpc/reading/config", prefix), binary_sensor.to_string());
+}
@@ -0,0 +1,65 @@
+use regex::Regex;
+use serde_json::Value;
+use std::sync::LazyLock;
+
+use crate::locale;
+
+// "Vol. 12", "Volume 12", "v12"
+static VOLUME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:vol(?:ume)?\.?\s*|v)(\d+(?:\.\d+)?)\b").unwrap());
+// "Ch. 101", "Chapter 101", "c101"
+static CHAPTER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:ch(?:apter)?\.?\s*|c)(\d+(?:\.\d+)?)\b").unwrap());
+
+/// Where a book sits in its series. Komga only has a single `number`, so volume and chapter
+/// numbers are read from it, the book title, or the file name, whichever mentions them first.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BookNumbers {
+    // metadata.number, else numberSort, else Komga's own position in the series
+    pub number: Option<String>,
+    pub volume: Option<String>,
+    pub chapter: Option<String>,
+}
+
+impl BookNumbers {
+    pub fn from_book(book: &Value) -> Self {
+        let metadata = book.get("metadata");
+        let number = metadata
+            .and_then(|m| m.get("number"))
+            .and_then(|v| match v {
+                Value::String(s) => Some(s.trim().to_string()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .filter(|s| !s.is_empty())
+            // numberSort is a float; 12.0 prints as "12"
+            .or_else(|| metadata.and_then(|m| m.get("numberSort")).and_then(|v| v.as_f64()).map(|n| n.to_string()))
+            .or_else(|| book.get("number").and_then(|v| v.as_u64()).map(|n| n.to_string()));
+
+        let sources: Vec<&str> = [number.as_deref(), metadata.and_then(|m| m.get("title")).and_then(|v| v.as_str()), book.get("name").and_then(|v| v.as_str())]
+            .into_iter()
+            .flatten()
+            .collect();
+        let find = |pattern: &Regex| sources.iter().find_map(|s| pattern.captures(s)).map(|c| c[1].to_string());
+        BookNumbers { volume: find(&VOLUME), chapter: find(&CHAPTER), number }
+    }
+
+    /// The number rendered through `format` ("#{number}", "Volume {number}", ...) when one is
+    /// configured, else "Vol. 12 · Ch. 101", "Ch. 101", or "Book 12" when nothing more specific
+    /// is known.
+    pub fn describe(&self, format: Option<&str>) -> Option<String> {
+        if let (Some(format), Some(number)) = (format, &self.number) {
+            return Some(format.replace("{number}", number));
+        }
+        let parts: Vec<String> = [
+            self.volume.as_ref().map(|v| locale::text("volume", &[("number", v)])),
+            self.chapter.as_ref().map(|c| locale::text("chapter", &[("number", c)])),
+        ]
+            .into_iter()
+            .flatten()
+            .collect();
+        if parts.is_empty() {
+            self.number.as_ref().map(|n| locale::text("book", &[("number", n)]))
+        } else {
+            Some(parts.join(" · "))
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::ErrorKind;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use crate::ipc::DiscordClient;
+use crate::logging::ErrorThrottle;
+use crate::stats;
+use crate::status_api::{ErrorCategory, SharedState};
+use crate::{Config, Presence};
+
+// How long to wait between attempts to reach Discord again after losing it
+const RECONNECT_EVERY: Duration = Duration::from_secs(5);
+
+/// What the poller wants Discord to show.
+pub enum PresenceUpdate {
+    Show(Box<Presence>),
+    Clear,
+}
+
+/// The Discord side of the client: a thread that owns the connection, shows the updates the
+/// poller sends it and reconnects when Discord goes away, so a slow Komga never holds up a
+/// reconnect and a reconnect never holds up a poll. Only the newest update matters; one that
+/// couldn't be shown is shown once Discord is back.
+pub struct Presenter {
+    sender: Option<Sender<PresenceUpdate>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Presenter {
+    pub fn spawn(config: &Config, discord: DiscordClient, state: SharedState) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let config = config.clone();
+        let thread = std::thread::spawn(move || run(&config, discord, &receiver, &state));
+        Presenter { sender: Some(sender), thread: Some(thread) }
+    }
+
+    pub fn show(&self, presence: &Presence) {
+        let mut presence = presence.clone();
+        presence.fit_discord_limits();
+        self.send(PresenceUpdate::Show(Box::new(presence)));
+    }
+
+    pub fn clear(&self) {
+        self.send(PresenceUpdate::Clear);
+    }
+
+    fn send(&self, update: PresenceUpdate) {
+        if let Some(ref sender) = self.sender {
+            let _ = sender.send(update);
+        }
+    }
+}
+
+// Waits for the presenter to clear the activity and hang up, so exiting doesn't leave it showing
+impl Drop for Presenter {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(config: &Config, mut discord: DiscordClient, receiver: &Receiver<PresenceUpdate>, state: &SharedState) {
+    let mut reconnect_errors = ErrorThrottle::new("Discord reconnect", Duration::from_secs(300));
+    let mut latest: Option<PresenceUpdate> = None;
+    // Whether `latest` still has to reach Discord
+    let mut pending = false;
+    let mut connected = true;
+    loop {
+        // While disconnected, wake up to try again even if nothing new arrives
+        let received = if connected {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(RECONNECT_EVERY)
+        };
+        match received {
+            Ok(update) => {
+                latest = Some(update);
+                pending = true;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // The poller is gone; dropping the client clears the activity
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        while let Ok(update) = receiver.try_recv() {
+            latest = Some(update);
+            pending = true;
+        }
+
+        if !connected {
+            let mut new_discord = DiscordClient::from_config(config, &config.discord_client_id);
+            if let Err(e) = new_discord.connect() {
+                state.lock().unwrap().record_error(ErrorCategory::Discord, e.to_string());
+                if reconnect_errors.record(&e.to_string()) {
+                    error!(error = %e, "Failed to reconnect to Discord");
+                }
+                continue;
+            }
+            reconnect_errors.reset();
+            info!("Successfully reconnected to Discord.");
+            state.lock().unwrap().discord.record_success();
+            discord = new_discord;
+            connected = true;
+            pending = latest.is_some();
+        }
+        let Some(update) = latest.as_ref().filter(|_| pending) else {
+            continue;
+        };
+        match show(&mut discord, update) {
+            Ok(()) => {
+                pending = false;
+                state.lock().unwrap().discord.record_success();
+            }
+            Err(e) => {
+                {
+                    let mut state = state.lock().unwrap();
+                    state.discord.record_failure(e.to_string());
+                    state.record_error(ErrorCategory::Discord, e.to_string());
+                }
+                if is_pipe_error(&*e) {
+                    warn!("Connection to Discord lost (pipe closed). Attempting to reconnect...");
+                    if let Err(close_err) = discord.close() {
+                        error!(error = %close_err, "Error closing old Discord client (connection likely already broken)");
+                    }
+                    connected = false;
+                } else {
+                    // Not something trying again would fix; wait for the next update
+                    error!(error = %e, "Failed to update Discord activity");
+                    pending = false;
+                }
+            }
+        }
+    }
+}
+
+fn show(discord: &mut DiscordClient, update: &PresenceUpdate) -> Result<(), Box<dyn std::error::Error>> {
+    let PresenceUpdate::Show(presence) = update else {
+        return discord.clear_activity();
+    };
+    if presence.client_id != discord.client_id() {
+        info!(client_id = %presence.client_id, "Switching Discord application");
+        discord.switch_client_id(&presence.client_id)?;
+    }
+    let started = Instant::now();
+    discord.set_activity(presence.to_activity())?;
+    stats::record_latency("Discord set_activity", started.elapsed());
+    Ok(())
+}
+
+// Discord closed the connection, somewhere down the error chain
+fn is_pipe_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+            if io_err.kind() == ErrorKind::BrokenPipe || io_err.raw_os_error() == Some(232) || io_err.raw_os_error() == Some(32) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
@@ -0,0 +1,68 @@
+use reqwest::Client;
+use crate::cover_cache::CoverCache;
+use crate::{build_presence_from_servers, Config, Presence};
+
+/// `preview`: print a mock-up of the Discord card built from live Komga data, without touching Discord.
+pub async fn run(client: &Client, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut imgur_cache = CoverCache::new();
+    match build_presence_from_servers(client, config, &mut imgur_cache, None).await? {
+        Some(mut presence) => {
+            presence.fit_discord_limits();
+            let app_name = application_name(client, &presence.client_id).await;
+            println!("{}", render_card(&app_name, &presence));
+        }
+        None => println!("Nothing would be shown: no book has been read in the last 5 minutes (or it is excluded)."),
+    }
+    Ok(())
+}
+
+// Discord serves the public application name over an unauthenticated endpoint
+async fn application_name(client: &Client, client_id: &str) -> String {
+    let url = format!("https://discord.com/api/v10/oauth2/applications/{}/rpc", client_id);
+    let name = match client.get(&url).send().await {
+        Ok(resp) if resp.status().is_success() => resp
+            .json::<serde_json::Value>()
+            .await
+            .ok()
+            .and_then(|json| json.get("name").and_then(|v| v.as_str()).map(|s| s.to_string())),
+        _ => None,
+    };
+    name.unwrap_or_else(|| format!("Application {}", client_id))
+}
+
+fn render_card(app_name: &str, presence: &Presence) -> String {
+    let art = if presence.large_image.is_some() { ["+------+", "|      |", "| ART  |", "|      |", "+------+"] } else { [""; 5] };
+    let text = [
+        format!("Playing {}", app_name),
+        presence.details.clone(),
+        presence.state.clone(),
+        "(no timestamps)".to_string(),
+        if presence.buttons.is_empty() {
+            "(no buttons)".to_string()
+        } else {
+            presence.buttons.iter().map(|b| format!("[{}]", b.label)).collect::<Vec<_>>().join(" ")
+        },
+    ];
+    let lines: Vec<String> = art
+        .iter()
+        .zip(text.iter())
+        .map(|(art, text)| if art.is_empty() { text.clone() } else { format!("{}  {}", art, text) })
+        .collect();
+
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let border = format!("+{}+", "-".repeat(width + 2));
+    let mut out = vec![border.clone()];
+    for line in &lines {
+        out.push(format!("| {}{} |", line, " ".repeat(width - line.chars().count())));
+    }
+    out.push(border);
+    out.push(format!("Application ID: {}", presence.client_id));
+    out.push(format!("Image:          {}", presence.large_image.as_deref().unwrap_or("(none)")));
+    if presence.large_image.is_some() {
+        out.push(format!("Image hover:    {}", presence.large_text));
+    }
+    if let Some(ref image) = presence.small_image {
+        out.push(format!("Small image:    {} ({})", image, presence.small_text.as_deref().unwrap_or("no hover")));
+    }
+    out.join("\n")
+}
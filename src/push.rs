@@ -0,0 +1,71 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::json;
+use std::net::SocketAddr;
+use tracing::{debug, error, info, warn};
+
+use crate::status_api::SharedState;
+use crate::Config;
+
+#[derive(Clone)]
+struct Receiver {
+    state: SharedState,
+    token: Option<String>,
+}
+
+/// Make sure a push receiver reachable from other machines has `push_token` set; without it,
+/// anyone who can reach the port could set off Komga checks whenever they like.
+pub fn check(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(addr), None) = (&config.push_listen_addr, &config.push_token) else {
+        return Ok(());
+    };
+    let loopback = match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr.ip().is_loopback(),
+        Err(_) => addr.starts_with("localhost:"),
+    };
+    if !loopback {
+        return Err(format!("push_listen_addr {} takes pushes from other machines; set push_token, or listen on 127.0.0.1", addr).into());
+    }
+    Ok(())
+}
+
+/// Accept read-progress notifications (a Komga script, a reverse-proxy hook, ...) on POST
+/// /progress and have the polling loop check Komga right away. Komga stays the source of truth,
+/// so the payload is only logged. With `push_token` set, requests must carry it as a bearer token.
+pub async fn serve(addr: String, token: Option<String>, state: SharedState) {
+    let app = Router::new().route("/progress", post(progress)).with_state(Receiver { state, token });
+
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(%addr, error = %e, "Failed to bind push receiver");
+            return;
+        }
+    };
+    info!(%addr, "Push receiver listening");
+    if let Err(e) = axum::serve(listener, app).await {
+        error!(error = %e, "Push receiver stopped");
+    }
+}
+
+async fn progress(State(receiver): State<Receiver>, headers: HeaderMap, body: String) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(ref token) = receiver.token {
+        let given = headers.get("authorization").and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| same_token(given.as_bytes(), token.as_bytes())) {
+            warn!("Rejected a push without the right token");
+            return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "missing or wrong token" })));
+        }
+    }
+    // Whatever sent it may put more in the body than belongs in a log
+    debug!(bytes = body.len(), "Received a progress push");
+    receiver.state.lock().unwrap().push_requested = true;
+    (StatusCode::ACCEPTED, Json(json!({ "checking": true })))
+}
+
+// Looks at every byte whatever the input, so the time taken doesn't give away how much of a
+// guess was right
+pub fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
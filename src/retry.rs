@@ -0,0 +1,80 @@
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::error::Error as _;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::Config;
+
+const DEFAULT_ATTEMPTS: u32 = 3;
+const DEFAULT_BASE_MS: u64 = 500;
+// No single wait grows past this, however many attempts are allowed
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Send the request `build` makes, trying again on transient failures: connection errors,
+/// timeouts, resets and 429/502/503/504 responses, up to `retry_attempts` tries in all, with
+/// jittered exponential backoff from `retry_base_ms`. A request that isn't `idempotent` (an
+/// upload, say) is only sent again when it never left, so it can't happen twice.
+pub async fn send<F>(config: &Config, idempotent: bool, build: F) -> reqwest::Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let attempts = config.retry_attempts.unwrap_or(DEFAULT_ATTEMPTS).max(1);
+    let base = Duration::from_millis(config.retry_base_ms.unwrap_or(DEFAULT_BASE_MS));
+    let mut attempt = 1;
+    loop {
+        let result = build().send().await;
+        let reason = match result {
+            _ if attempt >= attempts => None,
+            Ok(ref response) if idempotent && is_transient(response.status()) => Some(response.status().to_string()),
+            Err(ref e) if e.is_connect() || (idempotent && (e.is_timeout() || is_reset(e))) => Some(e.to_string()),
+            _ => None,
+        };
+        let Some(reason) = reason else {
+            return result;
+        };
+        let delay = backoff(base, attempt);
+        debug!(attempt, delay_ms = delay.as_millis() as u64, %reason, "Retrying request");
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// `send` for a request that's already built, such as a cover download handed over by a media
+/// server. Requests are idempotent here; one with a streamed body can't be copied, so it gets a
+/// single try.
+pub async fn send_request(config: &Config, request: RequestBuilder) -> reqwest::Result<Response> {
+    if request.try_clone().is_none() {
+        return request.send().await;
+    }
+    send(config, true, || request.try_clone().expect("the request was cloned above")).await
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
+
+// The connection dropped mid-request, somewhere down the error chain
+fn is_reset(e: &reqwest::Error) -> bool {
+    let mut source = e.source();
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+// Doubling from `base`, then a random point in the upper half so clients don't retry in step
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let ceiling = base.saturating_mul(1 << (attempt - 1).min(16)).min(MAX_DELAY);
+    let random = RandomState::new().build_hasher().finish();
+    let jitter = ceiling.as_millis() as u64 / 2;
+    ceiling - Duration::from_millis(if jitter == 0 { 0 } else { random % jitter })
+}
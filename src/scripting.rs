@@ -0,0 +1,119 @@
+use serde_json::Value;
+
+use crate::{Config, Presence};
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use rhai::{Dynamic, Engine, Scope, AST};
+    use serde::Deserialize;
+    use std::sync::OnceLock;
+    use tracing::{info, warn};
+
+    use crate::{Config, Presence, PresenceButton};
+
+    // Keeps a runaway loop in a script from freezing the client
+    const MAX_OPERATIONS: u64 = 1_000_000;
+
+    static SCRIPT: OnceLock<(Engine, AST)> = OnceLock::new();
+
+    // Fields a script may return; anything left out keeps its default
+    #[derive(Deserialize)]
+    struct ScriptOutput {
+        details: Option<String>,
+        state: Option<String>,
+        large_text: Option<String>,
+        buttons: Option<Vec<PresenceButton>>,
+    }
+
+    pub fn load(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(ref path) = config.presence_script else {
+            return Ok(());
+        };
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!(target: "presence_script", "{}", text));
+        let ast = engine.compile_file(path.into()).map_err(|e| format!("presence_script {}: {}", path, e))?;
+        let _ = SCRIPT.set((engine, ast));
+        info!(%path, "Loaded presence script");
+        Ok(())
+    }
+
+    pub fn apply(context: serde_json::Value, presence: &mut Presence) -> bool {
+        let Some((engine, ast)) = SCRIPT.get() else {
+            return true;
+        };
+        let mut scope = Scope::new();
+        for (name, value) in context.as_object().into_iter().flatten() {
+            match rhai::serde::to_dynamic(value) {
+                Ok(value) => {
+                    scope.push_dynamic(name.as_str(), value);
+                }
+                Err(e) => warn!(%name, error = %e, "Could not pass value to presence script"),
+            }
+        }
+        let result = match engine.eval_ast_with_scope::<Dynamic>(&mut scope, ast) {
+            Ok(result) => result,
+            Err(e) => {
+                warn!(error = %e, "Presence script failed, using the default presence");
+                return true;
+            }
+        };
+        if result.is_unit() || result.as_bool() == Ok(true) {
+            return true;
+        }
+        if result.as_bool() == Ok(false) {
+            return false;
+        }
+        let output: ScriptOutput = match rhai::serde::from_dynamic(&result) {
+            Ok(output) => output,
+            Err(e) => {
+                warn!(error = %e, "Presence script returned something other than a map of details/state/large_text/buttons");
+                return true;
+            }
+        };
+        if let Some(details) = output.details {
+            presence.details = details;
+        }
+        if let Some(state) = output.state {
+            presence.state = state;
+        }
+        if let Some(large_text) = output.large_text {
+            presence.large_text = large_text;
+        }
+        if let Some(buttons) = output.buttons {
+            presence.buttons = buttons;
+        }
+        true
+    }
+}
+
+/// Compile `presence_script` once at startup so syntax errors surface immediately.
+pub fn load(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "scripting")]
+    {
+        engine::load(config)
+    }
+    #[cfg(not(feature = "scripting"))]
+    {
+        if config.presence_script.is_some() {
+            tracing::warn!("presence_script is set, but this build was compiled without the `scripting` feature; it will be ignored");
+        }
+        Ok(())
+    }
+}
+
+/// Run the presence script, if any. Each entry of `context` becomes a script variable
+/// (`book`, `series`, `library`, `presence`); the script returns `()` to keep the presence,
+/// `false` to hide it, or a map overriding `details`, `state`, `large_text` and `buttons`.
+/// Returns false when the presence should be hidden.
+pub fn apply(context: Value, presence: &mut Presence) -> bool {
+    #[cfg(feature = "scripting")]
+    {
+        engine::apply(context, presence)
+    }
+    #[cfg(not(feature = "scripting"))]
+    {
+        let _ = (context, presence);
+        true
+    }
+}
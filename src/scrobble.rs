@@ -0,0 +1,211 @@
+use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+
+use crate::anilist::AniList;
+use crate::kitsu::Kitsu;
+use crate::{Config, Presence};
+
+const DEFAULT_CACHE_FILE: &str = "tracker_cache.json";
+
+/// A reading tracker that progress can be pushed to.
+pub enum Tracker {
+    AniList(AniList),
+    Kitsu(Kitsu),
+}
+
+impl Tracker {
+    fn from_config(config: &Config, unit: ProgressUnit) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match config.tracker.as_deref() {
+            None => Ok(None),
+            Some("anilist") => {
+                let token = config.anilist_token.clone().ok_or("tracker is \"anilist\" but anilist_token is not set")?;
+                Ok(Some(Tracker::AniList(AniList::new(token))))
+            }
+            Some("kitsu") => {
+                if unit != ProgressUnit::Chapters {
+                    return Err("Kitsu only tracks chapters; set tracker_progress to \"chapters\"".into());
+                }
+                let (Some(username), Some(password)) = (config.kitsu_username.clone(), config.kitsu_password.clone()) else {
+                    return Err("tracker is \"kitsu\" but kitsu_username or kitsu_password is not set".into());
+                };
+                Ok(Some(Tracker::Kitsu(Kitsu::new(username, password))))
+            }
+            Some(other) => Err(format!("Unknown tracker '{}', expected anilist or kitsu", other).into()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Tracker::AniList(_) => "anilist",
+            Tracker::Kitsu(_) => "kitsu",
+        }
+    }
+
+    // Candidate entries for a title, each with every title it is known by
+    async fn search(&self, client: &Client, title: &str) -> Result<Vec<(String, Vec<String>)>, Box<dyn std::error::Error>> {
+        match self {
+            Tracker::AniList(anilist) => anilist.search(client, title).await,
+            Tracker::Kitsu(kitsu) => kitsu.search(client, title).await,
+        }
+    }
+
+    // Raise progress to `progress` volumes (or chapters); never lowers what the tracker already has
+    async fn update_progress(&self, client: &Client, media_id: &str, progress: u32, unit: ProgressUnit) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Tracker::AniList(anilist) => anilist.update_progress(client, media_id, progress, unit).await,
+            Tracker::Kitsu(kitsu) => kitsu.update_progress(client, media_id, progress).await,
+        }
+    }
+}
+
+/// Tracker IDs found by matching, so each title is only looked up once. Shared by every
+/// tracker and persisted to `tracker_cache_file` as tracker -> Komga ID -> tracker ID.
+pub struct MappingCache {
+    file: String,
+    ids: Mutex<HashMap<String, HashMap<String, String>>>,
+}
+
+impl MappingCache {
+    pub fn load(config: &Config) -> Arc<Self> {
+        let file = config.tracker_cache_file.clone().unwrap_or_else(|| DEFAULT_CACHE_FILE.to_string());
+        let ids = fs::read_to_string(&file).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default();
+        Arc::new(MappingCache { file, ids: Mutex::new(ids) })
+    }
+
+    pub fn get(&self, tracker: &str, komga_id: &str) -> Option<String> {
+        self.ids.lock().unwrap().get(tracker).and_then(|ids| ids.get(komga_id)).cloned()
+    }
+
+    pub fn insert(&self, tracker: &str, komga_id: &str, tracker_id: &str) {
+        let mut ids = self.ids.lock().unwrap();
+        ids.entry(tracker.to_string()).or_default().insert(komga_id.to_string(), tracker_id.to_string());
+        let saved = serde_json::to_string_pretty(&*ids).map_err(|e| e.to_string()).and_then(|json| fs::write(&self.file, json).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            warn!(file = %self.file, error = %e, "Failed to save tracker cache");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressUnit {
+    Volumes,
+    Chapters,
+}
+
+/// Pushes finished books to the configured tracker. Komga book numbers are treated as volume
+/// (or, with `tracker_progress: "chapters"`, chapter) numbers. Series are matched by title once
+/// and the tracker ID is remembered in `tracker_cache_file`; `tracker_series_ids` overrides both.
+#[derive(Clone)]
+pub struct Scrobbler {
+    inner: Arc<ScrobblerInner>,
+}
+
+struct ScrobblerInner {
+    client: Client,
+    tracker: Tracker,
+    unit: ProgressUnit,
+    threshold: u32,
+    overrides: HashMap<String, String>,
+    cache: Arc<MappingCache>,
+    scrobbled: Mutex<HashSet<String>>,                       // book IDs already sent this run
+}
+
+impl Scrobbler {
+    pub fn from_config(config: &Config, client: &Client, cache: Arc<MappingCache>) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let unit = match config.tracker_progress.as_deref().unwrap_or("volumes") {
+            "volumes" => ProgressUnit::Volumes,
+            "chapters" => ProgressUnit::Chapters,
+            other => return Err(format!("Unknown tracker_progress '{}', expected volumes or chapters", other).into()),
+        };
+        let Some(tracker) = Tracker::from_config(config, unit)? else {
+            return Ok(None);
+        };
+        info!(tracker = tracker.name(), "Scrobbling finished books");
+        Ok(Some(Scrobbler {
+            inner: Arc::new(ScrobblerInner {
+                client: client.clone(),
+                tracker,
+                unit,
+                threshold: config.scrobble_threshold.unwrap_or(100).clamp(1, 100),
+                overrides: config.tracker_series_ids.clone().unwrap_or_default(),
+                cache,
+                scrobbled: Mutex::new(HashSet::new()),
+            }),
+        }))
+    }
+
+    /// Called with the book being read on every change; scrobbles once it passes `scrobble_threshold` percent.
+    pub fn progress(&self, presence: &Presence) {
+        let (Some(page), Some(pages)) = (presence.page, presence.pages) else {
+            return;
+        };
+        if pages > 0 && page * 100 >= pages * self.inner.threshold {
+            self.finished(presence);
+        }
+    }
+
+    /// Called when Komga marks a book completed.
+    pub fn finished(&self, presence: &Presence) {
+        if !self.inner.scrobbled.lock().unwrap().insert(presence.book_id.clone()) {
+            return;
+        }
+        let scrobbler = self.clone();
+        let presence = presence.clone();
+        tokio::spawn(async move {
+            if let Err(e) = scrobbler.scrobble(&presence).await {
+                warn!(tracker = scrobbler.inner.tracker.name(), series = %presence.series_title, error = %e, "Failed to update tracker");
+                // Allow another attempt the next time this book comes up
+                scrobbler.inner.scrobbled.lock().unwrap().remove(&presence.book_id);
+            }
+        });
+    }
+
+    async fn scrobble(&self, presence: &Presence) -> Result<(), Box<dyn std::error::Error>> {
+        let inner = &self.inner;
+        // Books like "12.5" are extras; they count toward the volume they follow
+        let Some(progress) = presence.book_number.as_deref().and_then(|n| n.trim().parse::<f64>().ok()).map(|n| n.floor() as u32) else {
+            debug!(book = %presence.book_id, "Book has no number, nothing to scrobble");
+            return Ok(());
+        };
+        let Some(media_id) = self.media_id(presence).await? else {
+            warn!(tracker = inner.tracker.name(), series = %presence.series_title, "No tracker entry matches this series; add it to tracker_series_ids");
+            return Ok(());
+        };
+        inner.tracker.update_progress(&inner.client, &media_id, progress, inner.unit).await?;
+        info!(tracker = inner.tracker.name(), series = %presence.series_title, progress, "Tracker progress updated");
+        Ok(())
+    }
+
+    async fn media_id(&self, presence: &Presence) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let inner = &self.inner;
+        let configured = inner.overrides.iter().find(|(key, _)| *key == &presence.series_id || key.eq_ignore_ascii_case(&presence.series_title));
+        if let Some((_, id)) = configured {
+            return Ok(Some(id.clone()));
+        }
+        let tracker = inner.tracker.name();
+        if let Some(id) = inner.cache.get(tracker, &presence.series_id) {
+            return Ok(Some(id));
+        }
+
+        // Localized or romanized titles often only match under one of the series' other names
+        let titles: Vec<&String> = std::iter::once(&presence.series_title).chain(&presence.alternate_titles).collect();
+        let wanted: Vec<String> = titles.iter().map(|t| normalize_title(t)).collect();
+        for title in titles {
+            let candidates = inner.tracker.search(&inner.client, title).await?;
+            if let Some((id, _)) = candidates.into_iter().find(|(_, names)| names.iter().any(|n| wanted.contains(&normalize_title(n)))) {
+                info!(tracker, series = %presence.series_title, searched = %title, %id, "Matched series on tracker");
+                inner.cache.insert(tracker, &presence.series_id, &id);
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Titles differ in punctuation and case across Komga and trackers ("Re:Zero" vs "Re: ZERO")
+fn normalize_title(title: &str) -> String {
+    title.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
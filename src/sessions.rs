@@ -0,0 +1,303 @@
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::history::History;
+use crate::state::{SavedState, StateFile};
+use crate::{locale, Presence};
+
+// Ended sessions are kept in memory for this long, enough for a daily summary
+const KEEP_HOURS: i64 = 48;
+// How far back the reading pace is averaged when the current session is too short to tell
+const PACE_HISTORY_DAYS: i64 = 30;
+// Streaks longer than this are reported as this long
+const MAX_STREAK_DAYS: i64 = 366;
+// A saved session carries on after a restart if its last page was turned this recently
+const RESUME_MINUTES: i64 = 15;
+// How much each finished session moves the rolling pace estimate
+const PACE_WEIGHT: f64 = 0.3;
+
+/// One continuous stretch of reading a single book, as observed by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub book_id: String,
+    pub book_title: Option<String>,
+    pub book_number: Option<String>,
+    pub series_id: String,
+    pub series_title: String,
+    pub library: Option<String>,
+    pub cover_url: Option<String>,
+    pub started_at: DateTime<Utc>,
+    // Last time the page moved, which is when reading actually stopped
+    pub ended_at: DateTime<Utc>,
+    pub start_page: u32,
+    pub end_page: u32,
+    pub finished: bool,
+    // DIVINA, EPUB or PDF; not kept in the history database
+    #[serde(default)]
+    pub media_profile: Option<String>,
+}
+
+impl Session {
+    fn start(presence: &Presence) -> Self {
+        let now = Utc::now();
+        let page = presence.page.unwrap_or(0);
+        Session {
+            book_id: presence.book_id.clone(),
+            book_title: presence.book_title.clone(),
+            book_number: presence.book_number.clone(),
+            series_id: presence.series_id.clone(),
+            series_title: presence.series_title.clone(),
+            library: presence.library.clone(),
+            cover_url: presence.cover_url().map(|s| s.to_string()),
+            started_at: now,
+            ended_at: now,
+            start_page: page,
+            end_page: page,
+            finished: false,
+            media_profile: presence.media_profile.clone(),
+        }
+    }
+
+    pub fn pages_read(&self) -> u32 {
+        self.end_page.saturating_sub(self.start_page)
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.ended_at - self.started_at
+    }
+}
+
+/// How the session on the book being read is going, measured up to now.
+pub struct SessionStats {
+    pub pages: u32,
+    pub duration: chrono::Duration,
+}
+
+impl SessionStats {
+    // Too short a session gives wild rates
+    const MIN_MINUTES_FOR_PACE: i64 = 5;
+
+    pub fn pages_per_hour(&self) -> Option<f64> {
+        let minutes = self.duration.num_minutes();
+        (minutes >= Self::MIN_MINUTES_FOR_PACE && self.pages > 0).then(|| self.pages as f64 * 60.0 / minutes as f64)
+    }
+
+    /// e.g. "42 pages in 35 min · 72 pages/h"
+    pub fn describe(&self) -> String {
+        let mut text = locale::text("session-pages", &[("pages", &self.pages), ("minutes", &self.duration.num_minutes())]);
+        if let Some(rate) = self.pages_per_hour() {
+            text.push_str(&format!(" · {}", locale::text("session-rate", &[("rate", &format!("{:.0}", rate))])));
+        }
+        text
+    }
+}
+
+pub type SharedSessions = Arc<Mutex<SessionTracker>>;
+
+/// Turns the stream of presence changes into reading sessions, recording them in the
+/// history database when one is configured. The session in progress is kept in the state file
+/// so a restart doesn't start it over.
+pub struct SessionTracker {
+    pub current: Option<Session>,
+    pub ended: Vec<Session>,
+    history: Option<History>,
+    state_file: StateFile,
+    // The session in progress when the client last stopped, until reading is first observed
+    resumable: Option<Session>,
+    restorable: Option<Presence>,
+    // Pages per hour by media profile, since comics and prose read at very different speeds
+    paces: HashMap<String, f64>,
+    // Kept in the state file alongside the session
+    profile: Option<String>,
+}
+
+impl SessionTracker {
+    pub fn new(history: Option<History>, state_file: StateFile) -> SharedSessions {
+        let cutoff = Utc::now() - chrono::Duration::minutes(RESUME_MINUTES);
+        let saved = state_file.load();
+        let resumable = saved.session.filter(|s| s.ended_at > cutoff);
+        let restorable = saved.presence.filter(|p| resumable.as_ref().is_some_and(|s| s.book_id == p.book_id));
+        Arc::new(Mutex::new(SessionTracker {
+            current: None,
+            ended: Vec::new(),
+            history,
+            state_file,
+            resumable,
+            restorable,
+            paces: saved.paces,
+            profile: saved.profile,
+        }))
+    }
+
+    /// `current` is what is being read now (None once reading stops); `finished` is a book
+    /// Komga has just marked completed.
+    pub fn observe(&mut self, current: Option<&Presence>, finished: Option<&Presence>) {
+        let ended_before = self.ended.len();
+        let resumable = self.resumable.take();
+        let same_book = matches!((&self.current, current), (Some(session), Some(presence)) if session.book_id == presence.book_id);
+        let mut moved = !same_book;
+        if same_book {
+            let (session, presence) = (self.current.as_mut().unwrap(), current.unwrap());
+            if let Some(page) = presence.page.filter(|page| *page != session.end_page) {
+                session.end_page = page;
+                session.ended_at = Utc::now();
+                moved = true;
+            }
+        } else {
+            if let Some(session) = self.current.take() {
+                self.update_pace(&session);
+                self.ended.push(session);
+            }
+            self.current = current.map(|presence| match resumable {
+                Some(session) if session.book_id == presence.book_id => session,
+                _ => Session::start(presence),
+            });
+        }
+        if moved {
+            self.state_file.save(&SavedState { session: self.current.clone(), presence: current.cloned(), paces: self.paces.clone(), profile: self.profile.clone() });
+        }
+
+        let mut changed: Vec<Session> = self.ended[ended_before..].to_vec();
+        if let Some(book) = finished {
+            if let Some(session) = self.ended.iter_mut().rev().find(|s| s.book_id == book.book_id) {
+                session.finished = true;
+                // Komga only reports the last page it saw; finishing means reading to the end
+                if let Some(pages) = book.pages {
+                    session.end_page = session.end_page.max(pages);
+                }
+                changed.push(session.clone());
+            }
+        }
+        // The session in progress is written on every change so a crash loses nothing
+        changed.extend(self.current.clone());
+        if let Some(ref history) = self.history {
+            for session in &changed {
+                if let Err(e) = history.record(session) {
+                    warn!(error = %e, "Failed to record reading session");
+                }
+            }
+        }
+
+        let cutoff = Utc::now() - chrono::Duration::hours(KEEP_HOURS);
+        self.ended.retain(|s| s.ended_at > cutoff);
+    }
+
+    /// The profile switched to before the last restart, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Remember `profile` in the state file. The activity kept there was laid out for the old
+    /// profile, so a restart waits for the first check instead of showing it again.
+    pub fn set_profile(&mut self, profile: &str) {
+        self.profile = Some(profile.to_string());
+        let mut saved = self.state_file.load();
+        saved.profile = self.profile.clone();
+        saved.presence = None;
+        self.state_file.save(&saved);
+    }
+
+    /// The presence last shown for a session that can carry on after a restart, once.
+    pub fn take_restorable(&mut self) -> Option<Presence> {
+        self.restorable.take()
+    }
+
+    /// When the session on `presence`'s book started, including one carried over from before a
+    /// restart that hasn't been observed yet.
+    pub fn started_at(&self, presence: &Presence) -> Option<DateTime<Utc>> {
+        self.current.iter().chain(self.resumable.iter()).find(|s| s.book_id == presence.book_id).map(|s| s.started_at)
+    }
+
+    /// Stats for the session on `presence`'s book, counting its latest page as read now.
+    pub fn stats_for(&self, presence: &Presence) -> Option<SessionStats> {
+        let session = self.current.as_ref().filter(|s| s.book_id == presence.book_id)?;
+        let page = presence.page.unwrap_or(session.end_page);
+        Some(SessionStats { pages: page.saturating_sub(session.start_page), duration: Utc::now() - session.started_at })
+    }
+
+    // Fold a finished session into the rolling pace for its media profile
+    fn update_pace(&mut self, session: &Session) {
+        let stats = SessionStats { pages: session.pages_read(), duration: session.duration() };
+        if let Some(rate) = stats.pages_per_hour() {
+            let pace = self.paces.entry(pace_key(session.media_profile.as_deref()).to_string()).or_insert(rate);
+            *pace += (rate - *pace) * PACE_WEIGHT;
+        }
+    }
+
+    /// Pages per hour: this session's pace once it is long enough to measure, else the rolling
+    /// pace for books of the same media profile, else the average over recent sessions.
+    pub fn pace(&self, presence: &Presence) -> Option<f64> {
+        if let Some(rate) = self.stats_for(presence).and_then(|stats| stats.pages_per_hour()) {
+            return Some(rate);
+        }
+        if let Some(pace) = self.paces.get(pace_key(presence.media_profile.as_deref())) {
+            return Some(*pace);
+        }
+        let recent = self.since(Utc::now() - chrono::Duration::days(PACE_HISTORY_DAYS));
+        let pages: u32 = recent.iter().map(|s| s.pages_read()).sum();
+        let minutes: i64 = recent.iter().map(|s| s.duration().num_minutes()).sum();
+        (minutes >= SessionStats::MIN_MINUTES_FOR_PACE && pages > 0).then(|| pages as f64 * 60.0 / minutes as f64)
+    }
+
+    /// Estimated minutes to finish `presence`'s book at the current pace.
+    pub fn minutes_left(&self, presence: &Presence) -> Option<i64> {
+        let remaining = presence.pages?.saturating_sub(presence.page?);
+        let pace = self.pace(presence)?;
+        Some((remaining as f64 * 60.0 / pace).ceil() as i64)
+    }
+
+    /// Consecutive days, ending today, with at least `min_pages` read. Today still counts as
+    /// part of the streak before its pages are in. Needs the history database.
+    pub fn streak(&self, min_pages: u32) -> Option<u32> {
+        let history = self.history.as_ref()?;
+        let days = match history.daily_pages(Utc::now() - chrono::Duration::days(MAX_STREAK_DAYS + 1)) {
+            Ok(days) => days,
+            Err(e) => {
+                warn!(error = %e, "Failed to read reading history");
+                return None;
+            }
+        };
+        let met = |day| days.get(&day).is_some_and(|pages| *pages >= min_pages.max(1));
+        let mut day = Local::now().date_naive();
+        if !met(day) {
+            day = day.pred_opt()?;
+        }
+        let mut streak = 0;
+        while met(day) {
+            streak += 1;
+            day = day.pred_opt()?;
+        }
+        Some(streak)
+    }
+
+    /// Pages and minutes read since the daily goal last reset at `reset` (local time).
+    pub fn today(&self, reset: NaiveTime) -> (u32, i64) {
+        let now = Local::now();
+        let mut start = now.date_naive().and_time(reset);
+        if start > now.naive_local() {
+            start -= chrono::Duration::days(1);
+        }
+        let start = Local.from_local_datetime(&start).earliest().map(|t| t.with_timezone(&Utc)).unwrap_or_else(Utc::now);
+        let sessions = self.since(start);
+        (sessions.iter().map(|s| s.pages_read()).sum(), sessions.iter().map(|s| s.duration().num_minutes()).sum())
+    }
+
+    /// Sessions that ended after `since`, plus the one in progress. Reads the history database
+    /// when there is one, so sessions from before a restart count too.
+    pub fn since(&self, since: DateTime<Utc>) -> Vec<Session> {
+        if let Some(ref history) = self.history {
+            match history.since(since) {
+                Ok(sessions) => return sessions,
+                Err(e) => warn!(error = %e, "Failed to read reading history, using this run's sessions"),
+            }
+        }
+        self.ended.iter().chain(self.current.iter()).filter(|s| s.ended_at > since).cloned().collect()
+    }
+}
+
+fn pace_key(media_profile: Option<&str>) -> &str {
+    media_profile.unwrap_or("unknown")
+}
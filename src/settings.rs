@@ -0,0 +1,276 @@
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::config_upgrade;
+
+#[cfg(feature = "gui")]
+use crate::setup::{write_config, DEFAULT_CLIENT_ID};
+
+#[cfg(feature = "gui")]
+mod window {
+    use eframe::egui;
+    use serde_json::{json, Map, Value};
+
+    use crate::template;
+
+    const SERVER_TYPES: [&str; 4] = ["komga", "kavita", "calibre-web", "suwayomi"];
+    const AUTHOR_PLACEMENTS: [(&str, &str); 3] = [("", "Hidden"), ("state", "Second line"), ("hover", "Cover hover text")];
+
+    // The common options as edited; everything else in the file is kept from `original`
+    struct Settings {
+        config_file: String,
+        original: Map<String, Value>,
+        server_type: String,
+        komga_url: String,
+        komga_api_key: String,
+        discord_client_id: String,
+        exclude_libraries: String,
+        exclude_tags: String,
+        nocover_tags: String,
+        show_author: String,
+        use_imgur_cover: bool,
+        imgur_client_id: String,
+        details_template: String,
+        state_template: String,
+        hover_template: String,
+        message: Option<String>,
+    }
+
+    impl Settings {
+        fn new(config_file: &str, original: Map<String, Value>) -> Self {
+            let text = |key: &str| original.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            let list = |key: &str| {
+                let items: Vec<&str> = original.get(key).and_then(|v| v.as_array()).into_iter().flatten().filter_map(|v| v.as_str()).collect();
+                items.join(", ")
+            };
+            Settings {
+                config_file: config_file.to_string(),
+                server_type: Some(text("server_type")).filter(|s| !s.is_empty()).unwrap_or_else(|| "komga".to_string()),
+                komga_url: text("komga_url"),
+                komga_api_key: text("komga_api_key"),
+                discord_client_id: Some(text("discord_client_id")).filter(|s| !s.is_empty()).unwrap_or_else(|| super::DEFAULT_CLIENT_ID.to_string()),
+                exclude_libraries: list("exclude_libraries"),
+                exclude_tags: list("exclude_tags"),
+                nocover_tags: list("nocover_tags"),
+                show_author: text("show_author"),
+                use_imgur_cover: original.get("use_imgur_cover").and_then(|v| v.as_bool()).unwrap_or(false),
+                imgur_client_id: text("imgur_client_id"),
+                details_template: text("details_template"),
+                state_template: text("state_template"),
+                hover_template: text("hover_template"),
+                message: None,
+                original,
+            }
+        }
+
+        // What's wrong with each field, as (field, problem)
+        fn problems(&self) -> Vec<(&'static str, String)> {
+            let mut problems = Vec::new();
+            match url::Url::parse(&self.komga_url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                Ok(url) => problems.push(("komga_url", format!("must be http or https, not {}", url.scheme()))),
+                Err(e) => problems.push(("komga_url", e.to_string())),
+            }
+            if self.komga_api_key.trim().is_empty() {
+                problems.push(("komga_api_key", "is required".to_string()));
+            }
+            let id = self.discord_client_id.trim();
+            if !(17..=20).contains(&id.len()) || !id.chars().all(|c| c.is_ascii_digit()) {
+                problems.push(("discord_client_id", "should be the 17 to 20 digit application ID".to_string()));
+            }
+            if self.use_imgur_cover && self.imgur_client_id.trim().is_empty() {
+                problems.push(("imgur_client_id", "is needed to upload covers".to_string()));
+            }
+            problems
+        }
+
+        // `original` with the edited options in place; blank ones are removed so defaults apply
+        fn to_json(&self) -> Value {
+            let mut config = self.original.clone();
+            let mut set = |key: &str, value: Option<Value>| match value {
+                Some(value) => {
+                    config.insert(key.to_string(), value);
+                }
+                None => {
+                    config.remove(key);
+                }
+            };
+            let text = |value: &str| Some(value.trim()).filter(|v| !v.is_empty()).map(|v| json!(v));
+            let list = |value: &str| {
+                let items: Vec<&str> = value.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).collect();
+                (!items.is_empty()).then(|| json!(items))
+            };
+            set("server_type", text(&self.server_type).filter(|v| v != "komga"));
+            set("komga_url", Some(json!(self.komga_url.trim().trim_end_matches('/'))));
+            set("komga_api_key", Some(json!(self.komga_api_key.trim())));
+            set("discord_client_id", Some(json!(self.discord_client_id.trim())));
+            set("exclude_libraries", list(&self.exclude_libraries));
+            set("exclude_tags", list(&self.exclude_tags));
+            set("nocover_tags", list(&self.nocover_tags));
+            set("show_author", text(&self.show_author));
+            set("use_imgur_cover", self.use_imgur_cover.then_some(Value::Bool(true)));
+            set("imgur_client_id", text(&self.imgur_client_id));
+            set("details_template", text(&self.details_template));
+            set("state_template", text(&self.state_template));
+            set("hover_template", text(&self.hover_template));
+            Value::Object(config)
+        }
+
+        fn preview(&self) -> (String, String, String) {
+            let vars: template::Vars = [
+                ("series", "One Piece"),
+                ("title", "Romance Dawn"),
+                ("number", "1"),
+                ("volume", "1"),
+                ("chapter", "1"),
+                ("library", "Manga"),
+                ("page", "34"),
+                ("pages", "212"),
+                ("percent", "16"),
+                ("author", "Eiichiro Oda"),
+                ("publisher", "Shueisha"),
+                ("release_date", "1997-12-24"),
+                ("year", "1997"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name, value.to_string()))
+            .collect();
+            let render = |template: &str| {
+                if template.trim().is_empty() {
+                    "(default)".to_string()
+                } else {
+                    template::render(template, &vars)
+                }
+            };
+            (render(&self.details_template), render(&self.state_template), render(&self.hover_template))
+        }
+    }
+
+    // A labelled text field, with its problem (if any) shown underneath in red
+    fn field(ui: &mut egui::Ui, label: &str, value: &mut String, password: bool, problem: Option<&String>) {
+        ui.label(label);
+        ui.add(egui::TextEdit::singleline(value).password(password).desired_width(f32::INFINITY));
+        ui.end_row();
+        if let Some(problem) = problem {
+            ui.label("");
+            ui.colored_label(ui.visuals().error_fg_color, problem);
+            ui.end_row();
+        }
+    }
+
+    impl eframe::App for Settings {
+        fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+            let problems = self.problems();
+            let problem = |key: &str| problems.iter().find(|(field, _)| *field == key).map(|(_, problem)| problem);
+            egui::CentralPanel::default().show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.heading("Server");
+                    egui::Grid::new("server").num_columns(2).show(ui, |ui| {
+                        ui.label("Server type");
+                        egui::ComboBox::from_id_salt("server_type").selected_text(self.server_type.as_str()).show_ui(ui, |ui| {
+                            for server_type in SERVER_TYPES {
+                                ui.selectable_value(&mut self.server_type, server_type.to_string(), server_type);
+                            }
+                        });
+                        ui.end_row();
+                        field(ui, "URL", &mut self.komga_url, false, problem("komga_url"));
+                        field(ui, "API key", &mut self.komga_api_key, true, problem("komga_api_key"));
+                        field(ui, "Discord client ID", &mut self.discord_client_id, false, problem("discord_client_id"));
+                    });
+
+                    ui.separator();
+                    ui.heading("Filters");
+                    ui.label("Comma-separated; matching books are never shown.");
+                    egui::Grid::new("filters").num_columns(2).show(ui, |ui| {
+                        field(ui, "Excluded libraries", &mut self.exclude_libraries, false, None);
+                        field(ui, "Excluded tags", &mut self.exclude_tags, false, None);
+                    });
+
+                    ui.separator();
+                    ui.heading("Privacy");
+                    egui::Grid::new("privacy").num_columns(2).show(ui, |ui| {
+                        field(ui, "Tags hiding the cover", &mut self.nocover_tags, false, None);
+                        ui.label("Authors");
+                        let selected = AUTHOR_PLACEMENTS.iter().find(|(value, _)| *value == self.show_author).map_or(self.show_author.as_str(), |(_, label)| label);
+                        egui::ComboBox::from_id_salt("show_author").selected_text(selected).show_ui(ui, |ui| {
+                            for (value, label) in AUTHOR_PLACEMENTS {
+                                ui.selectable_value(&mut self.show_author, value.to_string(), label);
+                            }
+                        });
+                        ui.end_row();
+                        ui.label("Cover art");
+                        ui.checkbox(&mut self.use_imgur_cover, "Upload covers to Imgur so Discord can show them");
+                        ui.end_row();
+                        if self.use_imgur_cover {
+                            field(ui, "Imgur client ID", &mut self.imgur_client_id, false, problem("imgur_client_id"));
+                        }
+                    });
+
+                    ui.separator();
+                    ui.heading("Templates");
+                    ui.label("Names in braces are filled in, e.g. {series}, {number}, {page}, {pages}, {percent}, {author}. {?page}...{/page} only shows when there is a page.");
+                    egui::Grid::new("templates").num_columns(2).show(ui, |ui| {
+                        field(ui, "First line", &mut self.details_template, false, None);
+                        field(ui, "Second line", &mut self.state_template, false, None);
+                        field(ui, "Cover hover text", &mut self.hover_template, false, None);
+                    });
+                    let (details, state, hover) = self.preview();
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        ui.strong(details);
+                        ui.label(state);
+                        ui.weak(hover);
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(problems.is_empty(), egui::Button::new("Save")).clicked() {
+                            self.message = Some(match super::write_config(&self.config_file, &self.to_json()) {
+                                Ok(()) => format!("Saved {}. Restart komga-discord-rpc to use it.", self.config_file),
+                                Err(e) => format!("Couldn't save: {}", e),
+                            });
+                        }
+                        if !problems.is_empty() {
+                            ui.label("Fix the fields marked in red to save.");
+                        } else if let Some(ref message) = self.message {
+                            ui.label(message);
+                        }
+                    });
+                });
+            });
+        }
+    }
+
+    pub fn run(config_file: &str, original: Map<String, Value>) -> Result<(), Box<dyn std::error::Error>> {
+        let options = eframe::NativeOptions {
+            viewport: egui::ViewportBuilder::default().with_inner_size([520.0, 640.0]),
+            ..Default::default()
+        };
+        let settings = Settings::new(config_file, original);
+        eframe::run_native("Komga-RPC settings", options, Box::new(|_| Ok(Box::new(settings)))).map_err(|e| e.to_string().into())
+    }
+}
+
+/// `settings`: edit the common options of `config_file` in a window. Options the window doesn't
+/// show are kept as they are.
+pub fn run(config_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let original = if Path::new(config_file).exists() {
+        let mut original: Value = serde_json::from_str(&fs::read_to_string(config_file)?)?;
+        config_upgrade::upgrade(&mut original)?;
+        match original {
+            Value::Object(original) => original,
+            _ => return Err(format!("{} is not a JSON object", config_file).into()),
+        }
+    } else {
+        Map::new()
+    };
+    #[cfg(feature = "gui")]
+    {
+        window::run(config_file, original)
+    }
+    #[cfg(not(feature = "gui"))]
+    {
+        let _ = original;
+        Err("this build was compiled without the `gui` feature; rebuild with `--features gui`, or use `setup` or edit the config by hand".into())
+    }
+}
@@ -0,0 +1,236 @@
+use dialoguer::{MultiSelect, Password};
+use discord_rich_presence::activity;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config_upgrade;
+use crate::install;
+use crate::ipc::DiscordClient;
+
+// The public Komga-RPC application; users can swap in their own later
+pub const DEFAULT_CLIENT_ID: &str = "1387202171270861033";
+// Label of the API keys setup makes
+const API_KEY_COMMENT: &str = "komga-rpc";
+// How long the test activity stays up
+const TEST_PRESENCE_TIME: Duration = Duration::from_secs(5);
+
+/// `setup`: ask for the few things every config needs, check them against Komga, and write
+/// `config_file`. Everything else keeps its default until edited in (see config.json.example).
+pub async fn run(client: &Client, config_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Komga Discord RPC setup");
+    if Path::new(config_file).exists() && !confirm(&format!("{} already exists. Replace it?", config_file), false)? {
+        println!("Left {} as it was.", config_file);
+        return Ok(());
+    }
+
+    let (komga_url, komga_api_key) = loop {
+        let url = prompt("Komga URL (e.g. http://localhost:25600)")?.trim_end_matches('/').to_string();
+        let key = prompt_with_default("Komga API key (blank to log in and have one made)", "")?;
+        let login = if key.is_empty() {
+            let username = prompt("Komga username")?;
+            let password = Password::new().with_prompt("Komga password").interact()?;
+            Some((username, password))
+        } else {
+            None
+        };
+        let verified: Result<(String, String), Box<dyn std::error::Error>> = async {
+            let key = match login {
+                Some((username, password)) => create_api_key(client, &url, &username, &password).await?,
+                None => key,
+            };
+            let user = komga_user(client, &url, &key).await?;
+            Ok((key, user))
+        }
+        .await;
+        match verified {
+            Ok((key, user)) => {
+                println!("Connected to Komga as {}.", user);
+                break (url, key);
+            }
+            Err(e) => {
+                println!("Couldn't reach Komga with that: {}", e);
+                if !confirm("Try again?", true)? {
+                    return Err("setup cancelled".into());
+                }
+            }
+        }
+    };
+    let discord_client_id = prompt_with_default("Discord client ID", DEFAULT_CLIENT_ID)?;
+
+    let mut config = Map::new();
+    config.insert("komga_url".into(), json!(komga_url));
+    config.insert("komga_api_key".into(), json!(komga_api_key));
+    config.insert("discord_client_id".into(), json!(discord_client_id));
+    let excluded = pick_libraries(client, &komga_url, &komga_api_key).await;
+    if !excluded.is_empty() {
+        config.insert("exclude_libraries".into(), json!(excluded));
+    }
+    let imgur_client_id = prompt_with_default("Imgur client ID, to show cover art (blank to skip)", "")?;
+    if !imgur_client_id.is_empty() {
+        config.insert("use_imgur_cover".into(), json!(true));
+        config.insert("imgur_client_id".into(), json!(imgur_client_id));
+    }
+
+    write_config(config_file, &Value::Object(config))?;
+    println!("Saved {}.", config_file);
+    println!("Showing a test activity on your Discord profile...");
+    match test_presence(config_file).await {
+        Ok(()) => println!("Discord showed the test activity, so everything is connected."),
+        Err(e) => println!("Couldn't show a test activity on Discord ({}). Is the Discord app running? The config is saved either way.", e),
+    }
+    if cfg!(windows) {
+        windows_extras(config_file)?;
+    } else {
+        println!("Run komga-discord-rpc to start showing your reading on Discord.");
+    }
+    Ok(())
+}
+
+// Offer the pieces that make a Windows install start on its own: a copy of the binary under
+// %LOCALAPPDATA%\Programs, a Start Menu shortcut and a logon task. Each step can fail without
+// undoing the config.
+fn windows_extras(config_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = std::path::absolute(config_file)?;
+    let mut exe = std::env::current_exe()?;
+    if confirm("Copy komga-discord-rpc to your programs folder?", true)? {
+        match install::windows_program_dir().and_then(|dir| install::copy_binary(&dir)) {
+            Ok(copy) => {
+                println!("Copied to {}.", copy.display());
+                exe = copy;
+            }
+            Err(e) => println!("Couldn't copy the program: {}", e),
+        }
+    }
+    if confirm("Add a Start Menu shortcut?", true)? {
+        match install::windows_shortcut(&exe, &config_file) {
+            Ok(()) => println!("Added Komga-RPC to the Start Menu."),
+            Err(e) => println!("Couldn't add the shortcut: {}", e),
+        }
+    }
+    if confirm("Start Komga-RPC in the background whenever you log on?", true)? {
+        match install::windows_logon_task(&exe, &config_file) {
+            Ok(()) => println!("Komga-RPC will start at your next logon."),
+            Err(e) => println!("Couldn't register the logon task: {}", e),
+        }
+    } else {
+        println!("Run {} to start showing your reading on Discord.", exe.display());
+    }
+    Ok(())
+}
+
+// Write the config, stamped with the format version, once sure the main loop will accept it
+pub fn write_config(config_file: &str, config: &Value) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config.clone();
+    config["config_version"] = json!(config_upgrade::CONFIG_VERSION);
+    serde_json::from_value::<crate::Config>(config.clone()).map_err(|e| format!("the new config doesn't load: {}", e))?;
+    // Through a temporary file, so a failed write can't leave the old config half overwritten
+    let temp = format!("{}.tmp", config_file);
+    fs::write(&temp, serde_json::to_string_pretty(&config)? + "\n")?;
+    fs::rename(&temp, config_file)?;
+    Ok(())
+}
+
+// Show "Komga-RPC setup successful" for a few seconds, proving the IPC connection, the client ID
+// and the config all work together
+async fn test_presence(config_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = crate::load_config(config_file)?;
+    let mut discord = DiscordClient::from_config(&config, &config.discord_client_id);
+    discord.connect()?;
+    let shown = discord.set_activity(activity::Activity::new().details("Komga-RPC setup successful").state("This goes away in a few seconds"));
+    if shown.is_ok() {
+        tokio::time::sleep(TEST_PRESENCE_TIME).await;
+        let _ = discord.clear_activity();
+    }
+    let _ = discord.close();
+    shown
+}
+
+// Log in with the Komga account and have Komga make a key; only the key is kept
+async fn create_api_key(client: &Client, url: &str, username: &str, password: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let endpoint = format!("{}/api/v2/users/me/api-keys", url);
+    let create = |comment: &str| client.post(&endpoint).basic_auth(username, Some(password)).json(&json!({ "comment": comment })).send();
+    let mut comment = API_KEY_COMMENT.to_string();
+    let mut response = create(&comment).await?;
+    // Comments are unique per user, so another install's key already has the plain one
+    if response.status() == StatusCode::BAD_REQUEST {
+        comment = format!("{} ({})", API_KEY_COMMENT, chrono::Local::now().format("%Y-%m-%d %H:%M"));
+        response = create(&comment).await?;
+    }
+    let created: Value = response.error_for_status()?.json().await?;
+    let key = created.get("key").and_then(|v| v.as_str()).ok_or("Komga didn't return the new key")?;
+    println!("Created the Komga API key \"{}\"; it can be revoked from your Komga account settings.", comment);
+    Ok(key.to_string())
+}
+
+// Who the API key belongs to, which also proves the URL and key work
+async fn komga_user(client: &Client, url: &str, key: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let me: Value = client.get(format!("{}/api/v2/users/me", url)).header("X-API-Key", key).send().await?.error_for_status()?.json().await?;
+    Ok(me.get("email").and_then(|v| v.as_str()).unwrap_or("an unnamed user").to_string())
+}
+
+// Tick the libraries whose books may be shown; the unticked ones come back, to be excluded.
+// Skipped (nothing excluded) with fewer than two libraries, or when there's no terminal to ask on.
+async fn pick_libraries(client: &Client, url: &str, key: &str) -> Vec<String> {
+    let libraries: Result<Vec<Value>, reqwest::Error> = async {
+        client.get(format!("{}/api/v1/libraries", url)).header("X-API-Key", key).send().await?.error_for_status()?.json().await
+    }
+    .await;
+    let names: Vec<String> = match libraries {
+        Ok(libraries) => libraries.iter().filter_map(|l| l.get("name")?.as_str()).map(|s| s.to_string()).collect(),
+        Err(e) => {
+            println!("Couldn't list the Komga libraries ({}); every library will be shown.", e);
+            return Vec::new();
+        }
+    };
+    if names.len() < 2 {
+        return Vec::new();
+    }
+    let picked = MultiSelect::new()
+        .with_prompt("Libraries to show on Discord (space toggles, enter confirms)")
+        .items_checked(names.iter().map(|name| (name, true)))
+        .interact_opt();
+    match picked {
+        Ok(Some(picked)) => names.into_iter().enumerate().filter(|(i, _)| !picked.contains(i)).map(|(_, name)| name).collect(),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            println!("Skipping the library picker ({}); every library will be shown.", e);
+            Vec::new()
+        }
+    }
+}
+
+fn prompt(prompt: &str) -> Result<String, io::Error> {
+    loop {
+        let input = prompt_with_default(prompt, "")?;
+        if !input.is_empty() {
+            return Ok(input);
+        }
+    }
+}
+
+fn prompt_with_default(prompt: &str, default: &str) -> Result<String, io::Error> {
+    if default.is_empty() {
+        print!("{}: ", prompt);
+    } else {
+        print!("{} [{}]: ", prompt, default);
+    }
+    io::stdout().flush()?;
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no more input"));
+    }
+    let input = input.trim();
+    Ok(if input.is_empty() { default.to_string() } else { input.to_string() })
+}
+
+fn confirm(question: &str, default: bool) -> Result<bool, io::Error> {
+    let answer = prompt_with_default(&format!("{} ({})", question, if default { "Y/n" } else { "y/N" }), "")?;
+    Ok(match answer.to_lowercase().as_str() {
+        "" => default,
+        answer => answer.starts_with('y'),
+    })
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use tracing::warn;
+
+use crate::sessions::Session;
+use crate::{Config, Presence};
+
+const DEFAULT_STATE_FILE: &str = "komga-rpc-state.json";
+
+/// What was being read when the client last ran, so a restart can carry on where it left off,
+/// and the profile it was showing it with.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedState {
+    pub session: Option<Session>,
+    // What Discord was last shown for it
+    pub presence: Option<Presence>,
+    // Rolling reading pace in pages per hour, by media profile
+    #[serde(default)]
+    pub paces: HashMap<String, f64>,
+    // The profile last switched to through the status API
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// The small JSON file (`state_file`) the saved state lives in.
+pub struct StateFile {
+    path: String,
+}
+
+impl StateFile {
+    pub fn from_config(config: &Config) -> Self {
+        StateFile { path: config.state_file.clone().unwrap_or_else(|| DEFAULT_STATE_FILE.to_string()) }
+    }
+
+    pub fn load(&self) -> SavedState {
+        match fs::read_to_string(&self.path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!(file = %self.path, error = %e, "Ignoring unreadable state file");
+                SavedState::default()
+            }),
+            Err(_) => SavedState::default(),
+        }
+    }
+
+    pub fn save(&self, state: &SavedState) {
+        // Write a temporary file and rename it, so a crash mid-write can't leave half a file
+        let temp = format!("{}.tmp", self.path);
+        let saved = serde_json::to_string_pretty(state)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&temp, json).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&temp, &self.path).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            warn!(file = %self.path, error = %e, "Failed to save state file");
+        }
+    }
+}
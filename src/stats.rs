@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tracing::info;
+
+// Percentiles are computed over this many most recent samples per operation
+const WINDOW: usize = 200;
+
+static STATS: LazyLock<Mutex<Stats>> = LazyLock::new(|| Mutex::new(Stats::default()));
+
+#[derive(Default)]
+struct Stats {
+    latencies: BTreeMap<String, VecDeque<f64>>,
+    caches: BTreeMap<&'static str, (u64, u64)>, // hits, misses
+}
+
+/// Record how long one call took, keyed by operation (e.g. "GET /api/v1/series/{id}").
+pub fn record_latency(operation: &str, elapsed: Duration) {
+    let mut stats = STATS.lock().unwrap();
+    let samples = stats.latencies.entry(operation.to_string()).or_default();
+    if samples.len() == WINDOW {
+        samples.pop_front();
+    }
+    samples.push_back(elapsed.as_secs_f64() * 1000.0);
+}
+
+pub fn record_cache(cache: &'static str, hit: bool) {
+    let mut stats = STATS.lock().unwrap();
+    let entry = stats.caches.entry(cache).or_default();
+    if hit {
+        entry.0 += 1;
+    } else {
+        entry.1 += 1;
+    }
+}
+
+/// Collapse IDs in a Komga URL so every book/series shares one bucket.
+pub fn endpoint_key(method: &str, url: &str) -> String {
+    let path = url::Url::parse(url).map(|u| u.path().to_string()).unwrap_or_else(|_| url.to_string());
+    let path: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            let looks_like_id = segment.len() >= 10 && segment.chars().any(|c| c.is_ascii_digit() || c.is_ascii_uppercase());
+            if looks_like_id { "{id}" } else { segment }
+        })
+        .collect();
+    format!("{} {}", method, path.join("/"))
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let index = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+/// One line per operation and cache, e.g. "GET /api/v1/books: n=40 p50=35.2ms p95=120.8ms".
+pub fn summary() -> Vec<String> {
+    let stats = STATS.lock().unwrap();
+    let mut lines = Vec::new();
+    for (operation, samples) in &stats.latencies {
+        let mut sorted: Vec<f64> = samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        lines.push(format!(
+            "{}: n={} p50={:.1}ms p95={:.1}ms",
+            operation,
+            sorted.len(),
+            percentile(&sorted, 0.50),
+            percentile(&sorted, 0.95)
+        ));
+    }
+    for (cache, (hits, misses)) in &stats.caches {
+        let total = hits + misses;
+        let rate = if total > 0 { *hits as f64 * 100.0 / total as f64 } else { 0.0 };
+        lines.push(format!("{} cache: {} hits, {} misses ({:.0}% hit rate)", cache, hits, misses, rate));
+    }
+    lines
+}
+
+/// `--stats`: log the rolling latency and cache summary every `interval`.
+pub async fn report_periodically(interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let lines = summary();
+        if lines.is_empty() {
+            info!("Latency stats: no requests yet");
+        }
+        for line in lines {
+            info!("Latency stats: {}", line);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use axum::extract::{Path, Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use tracing::{error, info, warn};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::{Config, Presence};
+
+// A client that hasn't completed a Komga check for this long is considered wedged
+const STALE_AFTER_SECS: i64 = 120;
+
+pub type SharedState = Arc<Mutex<ApiState>>;
+
+/// State shared between the polling loop and the local HTTP API.
+pub struct ApiState {
+    pub started_at: DateTime<Utc>,
+    pub komga: ComponentHealth,
+    pub discord: ComponentHealth,
+    pub current: Option<Presence>,
+    pub paused: bool,
+    // Set by /clear, consumed by the polling loop on its next tick
+    pub clear_requested: bool,
+    // Set by the push receiver, likewise
+    pub push_requested: bool,
+    // When the polling loop last ran each kind of check, for the dashboard
+    pub last_full_check: Option<DateTime<Utc>>,
+    pub last_page_update: Option<DateTime<Utc>>,
+    pub errors: BTreeMap<ErrorCategory, ErrorCount>,
+    // The profile in use, switched by POST /profile/{name} and picked up by the polling loop
+    pub profile: String,
+    pub profiles: Vec<String>,
+}
+
+/// What kind of failure an error was, so "it's not updating" comes with a cause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCategory {
+    Network, // Komga (or Imgur) couldn't be reached or didn't answer in time
+    Auth, // The server turned the API key away
+    Parse, // An answer didn't look like what was expected
+    Discord, // Discord's IPC connection or an activity update failed
+    Other,
+}
+
+impl ErrorCategory {
+    /// Sort a polling error by the first cause down its chain that says what it is.
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut source = Some(error);
+        while let Some(err) = source {
+            if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+                if e.status().is_some_and(|status| status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN) {
+                    return ErrorCategory::Auth;
+                }
+                if e.is_decode() {
+                    return ErrorCategory::Parse;
+                }
+                return ErrorCategory::Network;
+            }
+            if err.is::<serde_json::Error>() {
+                return ErrorCategory::Parse;
+            }
+            // A 502 or 503 is the server (or its proxy) not answering, however the request went
+            if err.is::<tokio::time::error::Elapsed>() || err.is::<crate::breaker::ServerUnavailable>() {
+                return ErrorCategory::Network;
+            }
+            // Polling only reaches Discord through the presenter, which files its own failures, so
+            // an I/O error here is the connection to the server (or a local file)
+            if let Some(e) = err.downcast_ref::<io::Error>() {
+                return match e.kind() {
+                    io::ErrorKind::InvalidData => ErrorCategory::Parse,
+                    io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::AddrNotAvailable
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::HostUnreachable
+                    | io::ErrorKind::NetworkUnreachable
+                    | io::ErrorKind::NetworkDown => ErrorCategory::Network,
+                    _ => ErrorCategory::Other,
+                };
+            }
+            source = err.source();
+        }
+        ErrorCategory::Other
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::Network => "network",
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::Parse => "parse",
+            ErrorCategory::Discord => "discord",
+            ErrorCategory::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ErrorCount {
+    pub count: u64,
+    pub last_error: Option<String>,
+    pub last_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub ok: bool,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn record_success(&mut self) {
+        self.ok = true;
+        self.last_success = Some(Utc::now());
+    }
+
+    pub fn record_failure(&mut self, err: String) {
+        self.ok = false;
+        self.last_error = Some(err);
+    }
+}
+
+impl ApiState {
+    pub fn new() -> SharedState {
+        let unknown = ComponentHealth { ok: true, last_success: None, last_error: None };
+        Arc::new(Mutex::new(ApiState {
+            started_at: Utc::now(),
+            komga: unknown.clone(),
+            discord: unknown,
+            current: None,
+            paused: false,
+            clear_requested: false,
+            push_requested: false,
+            last_full_check: None,
+            last_page_update: None,
+            errors: BTreeMap::new(),
+            profile: String::new(),
+            profiles: Vec::new(),
+        }))
+    }
+
+    pub fn record_error(&mut self, category: ErrorCategory, err: String) {
+        let entry = self.errors.entry(category).or_default();
+        entry.count += 1;
+        entry.last_error = Some(err);
+        entry.last_at = Some(Utc::now());
+    }
+
+    // Keyed by category name, for JSON
+    fn error_summary(&self) -> BTreeMap<&'static str, ErrorCount> {
+        self.errors.iter().map(|(category, count)| (category.name(), count.clone())).collect()
+    }
+
+    fn is_healthy(&self) -> bool {
+        let last_komga = self.komga.last_success.unwrap_or(self.started_at);
+        self.komga.ok && self.discord.ok && (Utc::now() - last_komga).num_seconds() < STALE_AFTER_SECS
+    }
+}
+
+/// Refuse to serve the controls beyond this machine without a token, at startup rather than
+/// when the API is first used.
+pub fn check(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(addr), None) = (&config.status_api_addr, &config.status_api_token) else {
+        return Ok(());
+    };
+    let loopback = match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr.ip().is_loopback(),
+        Err(_) => addr.starts_with("localhost:"),
+    };
+    if !loopback {
+        return Err(format!("status_api_addr {} lets other machines pause and clear the activity; set status_api_token, or listen on 127.0.0.1", addr).into());
+    }
+    Ok(())
+}
+
+/// Serve the local HTTP API until the process exits. With `token` set, the POST controls must
+/// carry it as a bearer token; the GETs stay open.
+pub async fn serve(addr: String, token: Option<String>, state: SharedState) {
+    let controls = Router::new()
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/clear", post(clear))
+        .route("/profile/{name}", post(switch_profile))
+        .route_layer(middleware::from_fn_with_state(token, require_token));
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/status", get(status))
+        .route("/profile", get(profile))
+        .merge(controls)
+        .with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(%addr, error = %e, "Failed to bind status API");
+            return;
+        }
+    };
+    info!(%addr, "Status API listening");
+    if let Err(e) = axum::serve(listener, app).await {
+        error!(error = %e, "Status API stopped");
+    }
+}
+
+async fn require_token(State(token): State<Option<String>>, headers: HeaderMap, request: Request, next: Next) -> Response {
+    if let Some(ref token) = token {
+        let given = headers.get("authorization").and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| crate::push::same_token(given.as_bytes(), token.as_bytes())) {
+            warn!(path = %request.uri().path(), "Rejected a status API request without the right token");
+            return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "missing or wrong token" }))).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+async fn healthz(State(state): State<SharedState>) -> (StatusCode, Json<serde_json::Value>) {
+    let state = state.lock().unwrap();
+    let healthy = state.is_healthy();
+    let code = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, Json(json!({
+        "status": if healthy { "ok" } else { "unhealthy" },
+        "komga": state.komga,
+        "discord": state.discord,
+        "errors": state.error_summary(),
+    })))
+}
+
+async fn status(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    let state = state.lock().unwrap();
+    Json(json!({
+        "reading": state.current.is_some(),
+        "paused": state.paused,
+        "profile": state.profile,
+        "presence": state.current,
+        "healthy": state.is_healthy(),
+        "komga": state.komga,
+        "discord": state.discord,
+        "errors": state.error_summary(),
+    }))
+}
+
+async fn pause(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    state.lock().unwrap().paused = true;
+    info!("Presence paused through the status API");
+    Json(json!({ "paused": true }))
+}
+
+async fn resume(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    state.lock().unwrap().paused = false;
+    info!("Presence resumed through the status API");
+    Json(json!({ "paused": false }))
+}
+
+async fn clear(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    state.lock().unwrap().clear_requested = true;
+    Json(json!({ "cleared": true }))
+}
+
+async fn profile(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    let state = state.lock().unwrap();
+    Json(json!({ "profile": state.profile, "profiles": state.profiles }))
+}
+
+async fn switch_profile(State(state): State<SharedState>, Path(name): Path<String>) -> (StatusCode, Json<serde_json::Value>) {
+    let mut state = state.lock().unwrap();
+    if !state.profiles.contains(&name) {
+        return (StatusCode::NOT_FOUND, Json(json!({ "error": format!("No profile named '{}'", name), "profiles": state.profiles })));
+    }
+    info!(profile = %name, "Profile switched through the status API");
+    state.profile = name;
+    (StatusCode::OK, Json(json!({ "profile": state.profile })))
+}
@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use std::time::Instant;
+use tracing::info;
+
+use crate::cover_cache::CoverCache;
+use crate::media_server::{rehost_cover, Reading};
+use crate::numbering::BookNumbers;
+use crate::{locale, stats, timestamps, Config, Presence};
+
+const LAST_READ_QUERY: &str = "
+query {
+  chapters(orderBy: LAST_READ_AT, orderByType: DESC, first: 1) {
+    nodes {
+      id name chapterNumber lastPageRead pageCount lastReadAt isRead
+      manga { id title author artist genre thumbnailUrl categories { nodes { name } } }
+    }
+  }
+}";
+const CHAPTER_QUERY: &str = "query ($id: Int!) { chapter(id: $id) { isRead } }";
+
+/// Suwayomi (Tachidesk) GraphQL client. Suwayomi keeps the last page read and when, per chapter,
+/// so the chapter read last is the book and its manga the series.
+pub struct Suwayomi {
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Suwayomi {
+    pub fn from_config(config: &Config) -> Self {
+        Suwayomi {
+            url: config.komga_url.trim_end_matches('/').to_string(),
+            username: config.suwayomi_username.clone(),
+            password: config.suwayomi_password.clone(),
+        }
+    }
+
+    // Suwayomi only asks for a login when basic auth is turned on in its settings
+    fn authed(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.username {
+            Some(ref username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    async fn query(&self, client: &Client, query: &str, variables: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/graphql", self.url);
+        let request = self.authed(client.post(&url).json(&json!({ "query": query, "variables": variables })));
+        let started = Instant::now();
+        let response = request.send().await;
+        stats::record_latency(&stats::endpoint_key("POST", &url), started.elapsed());
+        let body: Value = response?.error_for_status()?.json().await?;
+        if let Some(message) = body["errors"][0]["message"].as_str() {
+            return Err(format!("Suwayomi query failed: {}", message).into());
+        }
+        Ok(body["data"].clone())
+    }
+
+    /// What should be shown for the chapter most recently read on Suwayomi, like the Komga
+    /// build_presence.
+    pub async fn build_presence(
+        &self,
+        client: &Client,
+        config: &Config,
+        imgur_cache: &mut CoverCache,
+        previous: Option<&Presence>,
+    ) -> Result<Option<Presence>, Box<dyn std::error::Error>> {
+        let data = self.query(client, LAST_READ_QUERY, Value::Null).await?;
+        let chapter = &data["chapters"]["nodes"][0];
+        // lastReadAt is Unix seconds, sent as a string since it may not fit a GraphQL Int
+        let read_at = match &chapter["lastReadAt"] {
+            Value::String(s) => s.parse::<i64>().ok(),
+            other => other.as_i64(),
+        };
+        let Some(read_at) = read_at.filter(|at| *at > 0).and_then(|at| DateTime::<Utc>::from_timestamp(at, 0)) else {
+            info!("No in-progress books found in Suwayomi");
+            return Ok(None);
+        };
+        // Only show as reading if updated in the last 5 minutes
+        if !timestamps::is_recent(config, read_at) {
+            info!(updated_at = %read_at, "Most recent in-progress book activity is too old, clearing Discord status");
+            return Ok(None);
+        }
+        // A chapter read to the end is marked read; that's finishing, not reading
+        if chapter["isRead"].as_bool().unwrap_or(false) {
+            info!("Most recently read chapter is finished, clearing Discord status");
+            return Ok(None);
+        }
+
+        let manga = &chapter["manga"];
+        let manga_id = manga["id"].as_u64().ok_or("Suwayomi chapter has no manga")?;
+        let series_title = manga["title"].as_str().filter(|s| !s.is_empty()).map(|s| s.to_string()).unwrap_or_else(|| locale::text("untitled", &[]));
+        info!(%series_title, "Resolved series title");
+        let name = chapter["name"].as_str().unwrap_or_default();
+        // "Vol.3 Ch.20" names carry the volume; chapterNumber is -1 when the source has none
+        let mut numbers = BookNumbers::from_book(&json!({ "name": name }));
+        if numbers.chapter.is_none() {
+            numbers.chapter = chapter["chapterNumber"].as_f64().filter(|n| *n >= 0.0).map(|n| n.to_string());
+        }
+        let pages = chapter["pageCount"].as_u64().map(|v| v as u32).filter(|pages| *pages > 0);
+        // lastPageRead counts from 0
+        let page = chapter["lastPageRead"].as_u64().map(|read| (read as u32 + 1).min(pages.unwrap_or(u32::MAX)));
+        let authors = [&manga["author"], &manga["artist"]]
+            .iter()
+            .filter_map(|a| a.as_str())
+            .flat_map(|a| a.split(','))
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .fold(Vec::new(), |mut authors: Vec<String>, a| {
+                if !authors.contains(&a) {
+                    authors.push(a);
+                }
+                authors
+            });
+
+        let reading = Reading {
+            series_id: manga_id.to_string(),
+            series_title,
+            alternate_titles: Vec::new(),
+            book_id: chapter["id"].as_u64().ok_or("Suwayomi chapter has no ID")?.to_string(),
+            book_title: Some(name.to_string()).filter(|s| !s.is_empty()),
+            numbers,
+            oneshot: false,
+            isbn: None,
+            page,
+            pages,
+            percent: page.zip(pages).map(|(page, pages)| page * 100 / pages),
+            percent_only: false,
+            // A category is the closest thing Suwayomi has to a library
+            library: manga["categories"]["nodes"][0]["name"].as_str().map(|s| s.to_string()),
+            formats: Vec::new(),
+            media_profile: Some("DIVINA".to_string()),
+            read_at: Some(read_at),
+            authors,
+            publisher: None,
+            release_date: None,
+            status: None,
+            read_list: None,
+            buttons: Vec::new(),
+            tags: manga["genre"].as_array().into_iter().flatten().filter_map(|g| g.as_str()).map(|s| s.to_string()).collect(),
+            context: json!({ "book": chapter, "series": manga, "library": manga["categories"]["nodes"][0]["name"] }),
+        };
+        let hides_cover = reading.hides_cover(config);
+        let Some(mut presence) = reading.into_presence(config, previous) else {
+            return Ok(None);
+        };
+        if let Some(thumbnail) = manga["thumbnailUrl"].as_str().filter(|_| presence.large_image.is_none() && !hides_cover) {
+            let thumbnail = if thumbnail.starts_with("http") { thumbnail.to_string() } else { format!("{}{}", self.url, thumbnail) };
+            let cover = self.authed(client.get(&thumbnail));
+            presence.large_image = rehost_cover(client, config, format!("suwayomi_{}_{}", self.url, manga_id), "", cover, imgur_cache).await;
+        }
+        Ok(Some(presence))
+    }
+
+    /// Whether Suwayomi marks the chapter shown in `presence` read.
+    pub async fn completed(&self, client: &Client, presence: &Presence) -> Option<bool> {
+        let id: i64 = presence.book_id.parse().ok()?;
+        let data = self.query(client, CHAPTER_QUERY, json!({ "id": id })).await.ok()?;
+        data["chapter"]["isRead"].as_bool()
+    }
+}
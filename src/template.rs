@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+/// Values a presence template can use, by name. Missing values render as nothing.
+pub type Vars = HashMap<&'static str, String>;
+
+/// Replace each `{name}` in `template` with its value from `vars`, and keep each
+/// `{?name}...{/name}` section only if `name` has a value, e.g. `{?page}(Page {page}){/page}`.
+/// Braces that don't enclose a name are kept as they are.
+pub fn render(template: &str, vars: &Vars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let tag = after.find('}').map(|close| &after[..close]);
+        let section = tag.and_then(|tag| tag.strip_prefix('?')).filter(|name| is_name(name)).and_then(|name| {
+            let body = &after[name.len() + 2..];
+            section_end(body, name).map(|close| (name, &body[..close], &body[close + name.len() + 3..]))
+        });
+        match (tag, section) {
+            (_, Some((name, inside, after_section))) => {
+                if vars.get(name).is_some_and(|value| !value.is_empty()) {
+                    out.push_str(&render(inside, vars));
+                }
+                rest = after_section;
+            }
+            (Some(name), None) if is_name(name) => {
+                out.push_str(vars.get(name).map(String::as_str).unwrap_or(""));
+                rest = &after[name.len() + 1..];
+            }
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Where the `{/name}` closing a section starts in `body`, passing over any sections of the same
+// name nested inside it
+fn section_end(body: &str, name: &str) -> Option<usize> {
+    let (open, close) = (format!("{{?{}}}", name), format!("{{/{}}}", name));
+    let mut depth = 0;
+    let mut at = 0;
+    loop {
+        let next_close = at + body[at..].find(&close)?;
+        match body[at..next_close].find(&open) {
+            Some(nested) => {
+                depth += 1;
+                at += nested + open.len();
+            }
+            None if depth == 0 => return Some(next_close),
+            None => {
+                depth -= 1;
+                at = next_close + close.len();
+            }
+        }
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `text` cut to at most `max_chars` characters, ending in "…" when anything was cut.
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        None => text.to_string(),
+        Some(_) => {
+            let keep = text.char_indices().nth(max_chars.saturating_sub(1)).map_or(0, |(at, _)| at);
+            format!("{}…", text[..keep].trim_end())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(values: &[(&'static str, &str)]) -> Vars {
+        values.iter().map(|(name, value)| (*name, value.to_string())).collect()
+    }
+
+    #[test]
+    fn values_fill_in_and_missing_ones_render_as_nothing() {
+        let vars = vars(&[("series", "Berserk"), ("page", "12"), ("volume", "")]);
+        assert_eq!(render("{series} p.{page}", &vars), "Berserk p.12");
+        assert_eq!(render("{series}{chapter}{volume}!", &vars), "Berserk!");
+    }
+
+    #[test]
+    fn sections_need_a_non_empty_value() {
+        let vars = vars(&[("page", "12"), ("volume", "")]);
+        assert_eq!(render("{?page}(Page {page}){/page}", &vars), "(Page 12)");
+        assert_eq!(render("a{?chapter}(Ch. {chapter}){/chapter}b", &vars), "ab");
+        assert_eq!(render("a{?volume}(Vol. {volume}){/volume}b", &vars), "ab");
+    }
+
+    #[test]
+    fn unmatched_section_tags_are_kept_as_written() {
+        let vars = vars(&[("page", "12")]);
+        assert_eq!(render("{?page}Page {page}", &vars), "{?page}Page 12");
+        assert_eq!(render("Page {page}{/page}", &vars), "Page 12{/page}");
+        assert_eq!(render("{not a name} {}", &vars), "{not a name} {}");
+    }
+
+    #[test]
+    fn sections_nest() {
+        let vars = vars(&[("volume", "3"), ("chapter", "20")]);
+        assert_eq!(render("{?volume}Vol. {volume}{?chapter}, Ch. {chapter}{/chapter}{/volume}", &vars), "Vol. 3, Ch. 20");
+        assert_eq!(render("{?volume}Vol. {volume}{?page} p.{page}{/page}{/volume}", &vars), "Vol. 3");
+        assert_eq!(render("{?page}{?volume}Vol. {volume}{/volume}{/page}!", &vars), "!");
+    }
+
+    #[test]
+    fn a_section_inside_one_of_the_same_name_closes_in_order() {
+        let vars = vars(&[("a", "1")]);
+        assert_eq!(render("{?a}x{?a}y{/a}z{/a}", &vars), "xyz");
+        assert_eq!(render("{?b}x{?b}y{/b}z{/b}w", &vars), "w");
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+use crate::Config;
+
+// How long after progress last moved a book still counts as being read
+const FRESH_SECONDS: i64 = 300;
+// How far the server's clock may be off from this machine's, unless clock_skew_tolerance says otherwise
+const DEFAULT_SKEW_SECONDS: u64 = 60;
+
+static WARNED_AHEAD: AtomicBool = AtomicBool::new(false);
+
+enum Zone {
+    Local,
+    Named(Tz),
+}
+
+fn zone(name: &str) -> Result<Zone, String> {
+    if name.eq_ignore_ascii_case("local") {
+        return Ok(Zone::Local);
+    }
+    name.parse::<Tz>()
+        .map(Zone::Named)
+        .map_err(|_| format!("Unknown server_timezone '{}', expected a time zone name like \"Europe/Berlin\" or \"local\"", name))
+}
+
+/// Make sure each `server_timezone`, top-level or in komga_servers, names a real time zone.
+pub fn check(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let servers = config.komga_servers.iter().flatten().filter_map(|server| server.server_timezone.as_ref());
+    for name in config.server_timezone.iter().chain(servers) {
+        zone(name)?;
+    }
+    Ok(())
+}
+
+/// A timestamp from the server. One with an offset ("Z", "+02:00") means what it says; one
+/// without is the server's local time, in `server_timezone` (UTC unless set).
+pub fn parse(config: &Config, timestamp: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    let local = match config.server_timezone.as_deref().map(zone) {
+        Some(Ok(Zone::Local)) => Local.from_local_datetime(&naive).earliest()?.with_timezone(&Utc),
+        Some(Ok(Zone::Named(tz))) => tz.from_local_datetime(&naive).earliest()?.with_timezone(&Utc),
+        // Checked at startup
+        None | Some(Err(_)) => naive.and_utc(),
+    };
+    Some(local)
+}
+
+/// Whether progress that last moved at `read_at` is recent enough to show as being read: in the
+/// last five minutes, give or take `clock_skew_tolerance` seconds for a server clock that's off.
+/// A timestamp from the future counts as just now.
+pub fn is_recent(config: &Config, read_at: DateTime<Utc>) -> bool {
+    let skew = config.clock_skew_tolerance.unwrap_or(DEFAULT_SKEW_SECONDS) as i64;
+    let age = (Utc::now() - read_at).num_seconds();
+    // Hours ahead is almost always a time zone mix-up, which keeps the book "being read" until the
+    // clock catches up; worth saying once, since it's fixed in the config
+    if age < -skew && !WARNED_AHEAD.swap(true, Ordering::Relaxed) {
+        warn!(%read_at, ahead_seconds = -age, "Reading progress is timestamped ahead of this machine's clock; set server_timezone or clock_skew_tolerance if the server's time zone or clock is off");
+    }
+    age.max(0) < FRESH_SECONDS + skew
+}
@@ -0,0 +1,230 @@
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::stats;
+use crate::status_api::{ComponentHealth, ErrorCategory, ErrorCount, SharedState};
+
+// Log lines kept for the scrolling pane
+const LOG_LINES: usize = 1000;
+// How often the dashboard redraws (and checks for keys)
+const REDRAW_EVERY: Duration = Duration::from_millis(250);
+
+static LOG: LazyLock<Mutex<VecDeque<String>>> = LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+/// Where log lines go while the dashboard owns the terminal; they show up in its log pane.
+pub fn log_writer() -> LogLines {
+    LogLines
+}
+
+pub struct LogLines;
+
+impl Write for LogLines {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut log = LOG.lock().unwrap();
+        for line in String::from_utf8_lossy(buf).lines().filter(|line| !line.trim().is_empty()) {
+            if log.len() == LOG_LINES {
+                log.pop_front();
+            }
+            log.push_back(line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How often the polling loop checks Komga, for the countdowns.
+pub struct Timers {
+    pub full_check: Duration,
+    pub page_update: Duration,
+    pub polling: bool,
+}
+
+/// Puts the terminal back as it was when dropped, so an error out of the polling loop doesn't
+/// leave it in raw mode.
+pub struct Dashboard;
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+/// `tui`: draw the dashboard on its own thread while the polling loop carries on as usual. q, Esc
+/// or Ctrl-C quits the whole client.
+pub fn spawn(state: SharedState, timers: Timers) -> Dashboard {
+    std::thread::spawn(move || {
+        let result = run(&state, &timers);
+        ratatui::restore();
+        if let Err(e) = result {
+            eprintln!("The dashboard failed: {}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    });
+    Dashboard
+}
+
+fn run(state: &SharedState, timers: &Timers) -> io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    // Lines back from the newest log line; 0 follows the log
+    let mut scroll: usize = 0;
+    loop {
+        terminal.draw(|frame| draw(frame, state, timers, scroll))?;
+        if !event::poll(REDRAW_EVERY)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let logged = LOG.lock().unwrap().len();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Up => scroll = (scroll + 1).min(logged),
+            KeyCode::PageUp => scroll = (scroll + 10).min(logged),
+            KeyCode::Down => scroll = scroll.saturating_sub(1),
+            KeyCode::PageDown => scroll = scroll.saturating_sub(10),
+            KeyCode::End => scroll = 0,
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &SharedState, timers: &Timers, scroll: usize) {
+    let [top, latencies, log] = Layout::vertical([Constraint::Length(10), Constraint::Length(8), Constraint::Min(5)]).areas(frame.area());
+    let [reading, status] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(top);
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let dim = Style::default().fg(Color::DarkGray);
+
+    let (reading_lines, status_lines) = {
+        let state = state.lock().unwrap();
+        let now = Utc::now();
+        let reading_lines = match state.current {
+            Some(ref presence) => {
+                let book = [presence.book_number.as_ref().map(|n| format!("#{}", n)), presence.book_title.clone()]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                let progress = match (presence.page, presence.pages, presence.percent) {
+                    (Some(page), Some(pages), _) => format!("page {} of {}", page, pages),
+                    (_, _, Some(percent)) => format!("{}%", percent),
+                    _ => "unknown".to_string(),
+                };
+                let cover = match presence.large_image {
+                    Some(ref image) if image.starts_with("http") => Span::styled(format!("uploaded ({})", image), Style::default().fg(Color::Green)),
+                    Some(ref key) => Span::styled(format!("Discord asset \"{}\"", key), Style::default().fg(Color::Green)),
+                    None => Span::styled("none", Style::default().fg(Color::Yellow)),
+                };
+                vec![
+                    Line::from(Span::styled(presence.series_title.clone(), bold)),
+                    Line::from(book),
+                    Line::from(vec![Span::styled("Progress ", dim), Span::raw(progress)]),
+                    Line::from(vec![Span::styled("Library  ", dim), Span::raw(presence.library.clone().unwrap_or_default())]),
+                    Line::from(vec![Span::styled("Cover    ", dim), cover]),
+                    Line::from(vec![Span::styled("Shown    ", dim), Span::raw(format!("{} / {}", presence.details, presence.state))]),
+                ]
+            }
+            None if state.paused => vec![Line::from(Span::styled("Paused through the status API", Style::default().fg(Color::Yellow)))],
+            None => vec![Line::from(Span::styled("Nothing being read", dim))],
+        };
+        let status_lines = vec![
+            health_line("Komga   ", &state.komga, now),
+            health_line("Discord ", &state.discord, now),
+            Line::from(""),
+            timer_line("Full check  ", state.last_full_check, timers.full_check, timers.polling, now),
+            timer_line("Page update ", state.last_page_update, timers.page_update, timers.polling, now),
+            Line::from(vec![Span::styled("Running for ", dim), Span::raw(ago(state.started_at, now))]),
+            error_line(&state.errors),
+        ];
+        (reading_lines, status_lines)
+    };
+    frame.render_widget(Paragraph::new(reading_lines).block(Block::bordered().title(" Reading ")), reading);
+    frame.render_widget(Paragraph::new(status_lines).block(Block::bordered().title(" Status ")), status);
+
+    let mut latency_lines: Vec<Line> = stats::summary().into_iter().map(Line::from).collect();
+    if latency_lines.is_empty() {
+        latency_lines.push(Line::from(Span::styled("No requests yet", dim)));
+    }
+    frame.render_widget(Paragraph::new(latency_lines).block(Block::bordered().title(" Latency (p50 / p95) ")), latencies);
+
+    let height = log.height.saturating_sub(2) as usize;
+    let log_lines: Vec<Line> = {
+        let log = LOG.lock().unwrap();
+        let end = log.len().saturating_sub(scroll);
+        log.range(end.saturating_sub(height)..end).map(|line| Line::from(line.clone())).collect()
+    };
+    let title = if scroll > 0 { format!(" Log ({} lines back, End to follow) ", scroll) } else { " Log (↑/↓ to scroll, q to quit) ".to_string() };
+    frame.render_widget(Paragraph::new(log_lines).block(Block::bordered().title(title)), log);
+}
+
+fn health_line(label: &'static str, health: &ComponentHealth, now: DateTime<Utc>) -> Line<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut spans = vec![Span::styled(label, dim)];
+    if health.ok {
+        spans.push(Span::styled("ok", Style::default().fg(Color::Green)));
+    } else {
+        spans.push(Span::styled("failing", Style::default().fg(Color::Red)));
+    }
+    if let Some(at) = health.last_success {
+        spans.push(Span::styled(format!(", last success {} ago", ago(at, now)), dim));
+    }
+    if let Some(ref error) = health.last_error.as_ref().filter(|_| !health.ok) {
+        spans.push(Span::styled(format!(": {}", error), Style::default().fg(Color::Red)));
+    }
+    Line::from(spans)
+}
+
+// "Errors network 3 · auth 1", with the newest one's message
+fn error_line(errors: &BTreeMap<ErrorCategory, ErrorCount>) -> Line<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    if errors.is_empty() {
+        return Line::from(vec![Span::styled("Errors ", dim), Span::styled("none", Style::default().fg(Color::Green))]);
+    }
+    let counts: Vec<String> = errors.iter().map(|(category, count)| format!("{} {}", category.name(), count.count)).collect();
+    let mut spans = vec![Span::styled("Errors ", dim), Span::styled(counts.join(" · "), Style::default().fg(Color::Red))];
+    if let Some((category, latest)) = errors.iter().max_by_key(|(_, count)| count.last_at) {
+        spans.push(Span::styled(format!(", last {}: {}", category.name(), latest.last_error.as_deref().unwrap_or_default()), dim));
+    }
+    Line::from(spans)
+}
+
+fn timer_line(label: &'static str, last: Option<DateTime<Utc>>, every: Duration, polling: bool, now: DateTime<Utc>) -> Line<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let last_text = last.map_or("not yet".to_string(), |at| format!("{} ago", ago(at, now)));
+    let next_text = if !polling {
+        "on push only".to_string()
+    } else {
+        let due = last.map_or(now, |at| at + every);
+        match (due - now).to_std() {
+            Ok(wait) if !wait.is_zero() => format!("next in {}s", wait.as_secs() + 1),
+            _ => "due now".to_string(),
+        }
+    };
+    Line::from(vec![Span::styled(label, dim), Span::raw(format!("{}, {}", last_text, next_text))])
+}
+
+// "42s", "3m 5s" or "2h 10m"
+fn ago(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - at).num_seconds().max(0);
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+
+use crate::{Config, Presence};
+
+/// Raises the alarm when Komga shows reading progress but Discord hasn't accepted an activity
+/// update for `watchdog_minutes`: an error in the log, a desktop notification
+/// (`watchdog_notify`) and a POST to `watchdog_webhook`. Fires once per outage.
+pub struct Watchdog {
+    after: Option<chrono::Duration>,
+    notify: bool,
+    webhook: Option<String>,
+    client: Client,
+    last_progress: Option<(String, Option<u32>)>,
+    latest: Option<Presence>,
+    // First progress Discord hasn't shown yet
+    pending_since: Option<DateTime<Utc>>,
+    alerted: bool,
+}
+
+impl Watchdog {
+    pub fn from_config(config: &Config, client: &Client) -> Self {
+        Watchdog {
+            after: config.watchdog_minutes.map(|minutes| chrono::Duration::minutes(minutes.max(1) as i64)),
+            notify: config.watchdog_notify.unwrap_or(false),
+            webhook: config.watchdog_webhook.clone(),
+            client: client.clone(),
+            last_progress: None,
+            latest: None,
+            pending_since: None,
+            alerted: false,
+        }
+    }
+
+    /// Komga reports `presence` as being read.
+    pub fn komga_progress(&mut self, presence: &Presence) {
+        let progress = (presence.book_id.clone(), presence.page);
+        if self.last_progress.as_ref() != Some(&progress) {
+            self.last_progress = Some(progress);
+            self.pending_since.get_or_insert_with(Utc::now);
+        }
+        self.latest = Some(presence.clone());
+    }
+
+    /// Discord accepted an activity update (or clear).
+    pub fn discord_updated(&mut self) {
+        self.pending_since = None;
+        if std::mem::take(&mut self.alerted) {
+            info!("Discord activity is updating again");
+        }
+    }
+
+    /// Called every loop; alerts if progress has waited too long. `last_error` is Discord's.
+    pub fn check(&mut self, last_error: Option<&str>) {
+        let (Some(after), Some(since)) = (self.after, self.pending_since) else {
+            return;
+        };
+        if self.alerted || Utc::now() - since < after {
+            return;
+        }
+        self.alerted = true;
+        let series = self.latest.as_ref().map(|p| p.series_title.as_str()).unwrap_or("a book");
+        let message = format!(
+            "Discord activity hasn't updated for {} minutes while reading {}{}",
+            after.num_minutes(),
+            series,
+            last_error.map(|e| format!(" (last error: {})", e)).unwrap_or_default()
+        );
+        error!(minutes = after.num_minutes(), error = ?last_error, "{}", message);
+
+        if self.notify {
+            tokio::spawn(notify(message.clone()));
+        }
+        if let Some(ref url) = self.webhook {
+            let mut payload = crate::event_payload("watchdog", self.latest.as_ref());
+            payload["message"] = message.clone().into();
+            // Lets the URL be a Discord webhook too
+            payload["content"] = message.into();
+            let request = self.client.post(url).timeout(Duration::from_secs(10)).json(&payload);
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                    warn!(error = %e, "Watchdog webhook delivery failed");
+                }
+            });
+        }
+    }
+}
+
+// Desktop notification through whatever the platform ships with
+async fn notify(message: String) {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            "Add-Type -AssemblyName System.Windows.Forms; $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Warning; $n.Visible = $true; \
+             $n.ShowBalloonTip(10000, 'Komga RPC', $env:KOMGA_RPC_MESSAGE, 'Warning'); Start-Sleep 10; $n.Dispose()",
+        ]);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.args(["-e", "display notification (system attribute \"KOMGA_RPC_MESSAGE\") with title \"Komga RPC\""]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["-u", "critical", "Komga RPC"]).arg(&message);
+        command
+    };
+    command.env("KOMGA_RPC_MESSAGE", &message).kill_on_drop(true);
+    match command.status().await {
+        Ok(status) if !status.success() => warn!(%status, "Desktop notification command failed"),
+        Ok(_) => {}
+        Err(e) => warn!(error = %e, "Failed to show desktop notification"),
+    }
+}
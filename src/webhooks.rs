@@ -0,0 +1,44 @@
+use reqwest::Client;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::{Config, Presence};
+
+const EVENTS: [&str; 3] = ["started", "stopped", "finished"];
+
+/// POSTs a JSON payload to every `webhook_urls` entry when a reading session starts,
+/// stops, or a book is finished. Deliveries run in the background and are not retried.
+pub struct Webhooks {
+    client: Client,
+    urls: Vec<String>,
+    events: Vec<String>,
+}
+
+impl Webhooks {
+    pub fn from_config(config: &Config, client: &Client) -> Option<Self> {
+        let urls = config.webhook_urls.clone().filter(|urls| !urls.is_empty())?;
+        let events = config.webhook_events.clone().unwrap_or_else(|| EVENTS.iter().map(|e| e.to_string()).collect());
+        for event in events.iter().filter(|e| !EVENTS.contains(&e.as_str())) {
+            warn!(%event, "Unknown webhook event, expected started, stopped or finished");
+        }
+        Some(Webhooks { client: client.clone(), urls, events })
+    }
+
+    pub fn send(&self, event: &str, presence: Option<&Presence>) {
+        if !self.events.iter().any(|e| e == event) {
+            return;
+        }
+        let payload = crate::event_payload(event, presence);
+        for url in &self.urls {
+            let request = self.client.post(url).timeout(Duration::from_secs(10)).json(&payload);
+            let url = url.clone();
+            let event = event.to_string();
+            tokio::spawn(async move {
+                match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(_) => debug!(%url, %event, "Webhook delivered"),
+                    Err(e) => warn!(%url, %event, error = %e, "Webhook delivery failed"),
+                }
+            });
+        }
+    }
+}
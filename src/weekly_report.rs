@@ -0,0 +1,103 @@
+use chrono::{Local, NaiveTime, Utc, Weekday};
+use reqwest::Client;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
+
+use crate::daily_summary::until_next;
+use crate::sessions::{Session, SharedSessions};
+use crate::Config;
+
+const DEFAULT_DAY: &str = "sunday";
+const DEFAULT_TIME: &str = "20:00";
+const TOP_SERIES: usize = 5;
+
+/// Once a week, have a bot post a recap of the last seven days from the history database to
+/// `weekly_report_channel_id`. The bot only needs permission to send messages in that channel.
+pub fn spawn(config: &Config, client: &Client, sessions: SharedSessions) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(channel_id) = config.weekly_report_channel_id.clone() else {
+        return Ok(());
+    };
+    let token = config
+        .weekly_report_bot_token
+        .clone()
+        .or_else(|| config.discord_status_bot_token.clone())
+        .ok_or("weekly_report_channel_id is set but there is no weekly_report_bot_token (or discord_status_bot_token)")?;
+    if config.history_db.is_none() {
+        return Err("The weekly report is built from the history database; set history_db".into());
+    }
+    let day_str = config.weekly_report_day.as_deref().unwrap_or(DEFAULT_DAY);
+    let day: Weekday = day_str.parse().map_err(|_| format!("weekly_report_day '{}' is not a day of the week", day_str))?;
+    let time_str = config.weekly_report_time.as_deref().unwrap_or(DEFAULT_TIME);
+    let time = NaiveTime::parse_from_str(time_str, "%H:%M").map_err(|_| format!("weekly_report_time '{}' is not HH:MM", time_str))?;
+    let min_pages = config.streak_min_pages.unwrap_or(1);
+    let client = client.clone();
+
+    tokio::spawn(async move {
+        let url = format!("https://discord.com/api/v10/channels/{}/messages", channel_id);
+        loop {
+            tokio::time::sleep(until_next(time, Some(day))).await;
+            let (week, streak) = {
+                let sessions = sessions.lock().unwrap();
+                (sessions.since(Utc::now() - chrono::Duration::days(7)), sessions.streak(min_pages).unwrap_or(0))
+            };
+            let result = client
+                .post(&url)
+                .header("Authorization", format!("Bot {}", token))
+                .json(&json!({ "embeds": [report_embed(&week, streak)] }))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            match result {
+                Ok(_) => info!(sessions = week.len(), "Posted weekly reading report"),
+                Err(e) => warn!(error = %e, "Failed to post weekly reading report"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn report_embed(sessions: &[Session], streak: u32) -> serde_json::Value {
+    let pages: u32 = sessions.iter().map(|s| s.pages_read()).sum();
+    let minutes: i64 = sessions.iter().map(|s| s.duration().num_minutes()).sum();
+    let books: HashSet<&str> = sessions.iter().map(|s| s.book_id.as_str()).collect();
+    let finished = sessions.iter().filter(|s| s.finished).count();
+
+    let mut by_series: HashMap<&str, u32> = HashMap::new();
+    for session in sessions {
+        *by_series.entry(session.series_title.as_str()).or_insert(0) += session.pages_read();
+    }
+    let mut top: Vec<(&str, u32)> = by_series.into_iter().collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let top_series = top
+        .iter()
+        .take(TOP_SERIES)
+        .enumerate()
+        .map(|(i, (series, pages))| format!("{}. {} — {} pages", i + 1, series, pages))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let longest = sessions.iter().max_by_key(|s| s.duration()).map(|s| {
+        let book = s.book_number.as_ref().map(|n| format!(" (Book {})", n)).unwrap_or_default();
+        format!("{} min — {}{}", s.duration().num_minutes(), s.series_title, book)
+    });
+
+    let mut embed = json!({
+        "title": format!("Weekly reading recap — week of {}", (Local::now() - chrono::Duration::days(6)).format("%B %-d")),
+        "color": 0x005ed3,
+        "fields": [
+            { "name": "Pages", "value": pages.to_string(), "inline": true },
+            { "name": "Time", "value": format!("{}h {:02}m", minutes / 60, minutes % 60), "inline": true },
+            { "name": "Books", "value": format!("{} ({} finished)", books.len(), finished), "inline": true },
+            { "name": "Streak", "value": format!("🔥 {} days", streak), "inline": true },
+            { "name": "Longest session", "value": longest.unwrap_or_else(|| "—".to_string()), "inline": true },
+            { "name": "Top series", "value": if top_series.is_empty() { "No reading this week".to_string() } else { top_series } },
+        ],
+    });
+    // The most read series' cover, if the week had one
+    let cover = top.first().and_then(|(series, _)| sessions.iter().rev().find(|s| s.series_title == *series)?.cover_url.clone());
+    if let Some(cover) = cover {
+        embed["thumbnail"] = json!({ "url": cover });
+    }
+    embed
+}